@@ -0,0 +1,69 @@
+use std::io;
+use common::admin::{AdminRequest, AdminResponse, AdminSession};
+use common::UserId;
+
+const USAGE: &str = "\
+Usage: admin <server address> <command>
+The admin key is read from the ADMIN_KEY environment variable.
+Commands:
+  stats
+  users
+  kick <id> [reason]
+  ban <id>
+  unban <ip>
+  reloadkeys
+  shutdown";
+
+/// Administers a running server over its reliable address.
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (Some(addr), Some(request)) = (args.first(), parse_request(args.get(1..).unwrap_or_default())) else {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    };
+    let Ok(key) = std::env::var("ADMIN_KEY") else {
+        eprintln!("Set ADMIN_KEY to the admin key of the server");
+        std::process::exit(2);
+    };
+
+    let mut session = AdminSession::connect(addr.as_str(), key).await?;
+    match session.request(request).await? {
+        AdminResponse::Stats(stats) => {
+            println!("uptime: {} s", stats.uptime_secs);
+            println!("tick:   {}", stats.tick);
+            println!("users:  {}", stats.users);
+            println!("rooms:  {}", stats.rooms);
+            println!("bans:   {}", stats.bans);
+        }
+        AdminResponse::Users(users) => {
+            for user in users {
+                let rtt = user.rtt_ms.map_or("-".to_string(), |rtt| format!("{rtt} ms"));
+                println!("{:>20}  {:<16}  {:<24}  {rtt}", user.id, user.name, user.address);
+            }
+        }
+        AdminResponse::Done(outcome) => println!("{outcome}"),
+        AdminResponse::Error(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn parse_request(args: &[String]) -> Option<AdminRequest> {
+    let user_id = |arg: Option<&String>| arg?.parse::<UserId>().ok();
+    Some(match args.first()?.as_str() {
+        "stats" => AdminRequest::Stats,
+        "users" => AdminRequest::Users,
+        "kick" => AdminRequest::Kick {
+            id: user_id(args.get(1))?,
+            reason: Some(args[2..].join(" ")).filter(|reason| !reason.is_empty()),
+        },
+        "ban" => AdminRequest::Ban { id: user_id(args.get(1))? },
+        "unban" => AdminRequest::Unban(args.get(1)?.clone()),
+        "reloadkeys" => AdminRequest::ReloadAdminKeys,
+        "shutdown" => AdminRequest::Shutdown,
+        _ => return None,
+    })
+}
@@ -0,0 +1,62 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use common::UserId;
+
+/// A line of the chat, as shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatLine {
+    Room { room: String, from: String, text: String },
+    Private { from: String, text: String },
+    /// A publication to a subscribed topic, see `crate::pubsub`.
+    Published { topic: String, from: String, text: String },
+    /// Messages from the server and about the connection.
+    Notice(String),
+}
+
+impl Display for ChatLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatLine::Room { room, from, text } => write!(f, "[{room}] {from}: {text}"),
+            ChatLine::Private { from, text } => write!(f, "{from} (private): {text}"),
+            ChatLine::Published { topic, from, text } => write!(f, "<{topic}> {from}: {text}"),
+            ChatLine::Notice(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+/// What the client knows about the chat. The frontends poll the new lines and read the rest.
+#[derive(Default)]
+pub struct Chat {
+    new_lines: VecDeque<ChatLine>,
+    /// The users that are online, by id.
+    pub users: BTreeMap<UserId, String>,
+    /// The rooms that have been joined, in order.
+    pub rooms: Vec<String>,
+    /// The room chat messages are sent to.
+    pub room: Option<String>,
+}
+
+impl Chat {
+    pub fn push(&mut self, line: ChatLine) {
+        self.new_lines.push_back(line);
+    }
+
+    pub fn notice(&mut self, text: impl Into<String>) {
+        self.push(ChatLine::Notice(text.into()));
+    }
+
+    /// The oldest line that has not been polled yet.
+    pub fn poll_line(&mut self) -> Option<ChatLine> {
+        self.new_lines.pop_front()
+    }
+
+    /// Makes the room the current one. Returns false if it had been joined before.
+    pub fn join(&mut self, room: String) -> bool {
+        let new = !self.rooms.contains(&room);
+        if new {
+            self.rooms.push(room.clone());
+        }
+        self.room = Some(room);
+        new
+    }
+}
@@ -1,23 +1,156 @@
+use std::time::Duration;
+use serializeable::Serializeable;
 use tokio::net::ToSocketAddrs;
-use crate::network_interface::NetworkInterface;
+use common::interest::Interest;
+use common::message::{ClientTcpMessage, ClientUdpMessage};
+use common::replication::Tick;
+use common::services::directory;
+use common::UserId;
+use common::time_sync::Timestamp;
+use common::transport::{QuicTransport, ServerAddr, TcpUdpTransport, Transport, TransportKind};
+use crate::chat::Chat;
+use crate::console::{Command, Console};
+use crate::network_interface::{ConnectionState, NetworkInterface};
+use crate::network_interface::config::ConnectionConfig;
+use crate::network_interface::rpc::RpcClient;
+use crate::prediction::Prediction;
+use crate::pubsub::PubSub;
+use crate::replication::ClientWorld;
+use crate::time_sync::TimeSync;
 
 pub(super) struct Client{
     pub network_interface: NetworkInterface,
+    pub world: ClientWorld,
+    pub prediction: Prediction,
+    pub time_sync: TimeSync,
+    /// The timestamp of the latest timestamped message from the server.
+    pub last_server_timestamp: Option<Timestamp>,
+    pub chat: Chat,
+    pub pubsub: PubSub,
+    pub connection: ConnectionState,
 }
 
 impl Client {
     /// Creates a new Client Instance and connects to the provided Address
     pub async fn new<A: ToSocketAddrs>(server_address: A) -> std::io::Result<Self> {
-        let interface = NetworkInterface::create(server_address).await?;
-        Ok(Self{
-            network_interface: interface
-        })
+        Self::with_transport(TcpUdpTransport, server_address, ConnectionConfig::default()).await
     }
 
-    pub fn run(mut self) {
+    /// Like new, but connects using the selected transport and config.
+    pub async fn with_transport_kind<A: ToSocketAddrs>(kind: TransportKind, server_address: A, config: ConnectionConfig) -> std::io::Result<Self> {
+        match kind {
+            TransportKind::TcpUdp => Self::with_transport(TcpUdpTransport, server_address, config).await,
+            TransportKind::Quic { server_name, certificate_path } => {
+                let certificate = std::fs::read(certificate_path)?;
+                Self::with_transport(QuicTransport::trusting(&server_name, certificate), server_address, config).await
+            }
+        }
+    }
+
+    /// Like new, but connects over a custom transport, e.g. an in-memory one for tests.
+    pub async fn with_transport<T: Transport, A: ToSocketAddrs>(transport: T, server_address: A, config: ConnectionConfig) -> std::io::Result<Self> {
+        let interface = NetworkInterface::create_with_transport(transport, server_address, config).await?;
+        Ok(Self::with_interface(interface))
+    }
+
+    /// Connects to a server that may use different addresses for the reliable and the unreliable channel.
+    pub async fn connect<T: Transport>(transport: T, server_addr: ServerAddr, config: ConnectionConfig) -> std::io::Result<Self> {
+        let interface = NetworkInterface::connect(transport, server_addr, config).await?;
+        Ok(Self::with_interface(interface))
+    }
+
+    fn with_interface(interface: NetworkInterface) -> Self {
+        Self{
+            network_interface: interface,
+            world: Default::default(),
+            prediction: Default::default(),
+            time_sync: Default::default(),
+            last_server_timestamp: None,
+            chat: Default::default(),
+            pubsub: Default::default(),
+            connection: ConnectionState::Connected,
+        }
+    }
+
+    /// The id the server knows this client by.
+    pub fn user_id(&self) -> UserId {
+        self.network_interface.user_id()
+    }
+
+    /// Queries about the users and topics of the server.
+    pub fn directory(&self) -> directory::Client<RpcClient> {
+        directory::Client::new(self.network_interface.rpc())
+    }
+
+    /// Sends a time request whenever the time sync asks for one.
+    fn sync_time(&mut self) {
+        if let Some(send_time) = self.time_sync.poll_request() {
+            self.network_interface.send_udp(ClientUdpMessage::TimeRequest(send_time));
+        }
+    }
+
+    /// The estimated time since the server started. None until the clocks have been synchronized.
+    pub fn server_time(&self) -> Option<Duration> {
+        self.time_sync.server_time()
+    }
+
+    pub fn estimated_server_tick(&self) -> Option<Tick> {
+        self.time_sync.estimated_server_tick()
+    }
+
+    /// Applies the input locally right away and sends it to the server.
+    pub fn send_input<I: Serializeable>(&mut self, input: &I) {
+        let data = input.serialize();
+        let sequence = self.prediction.predict(data.clone());
+        self.network_interface.send_udp(ClientUdpMessage::Input(sequence, data));
+    }
+
+    /// Handles the incoming messages and keeps the clocks in sync. Called once per frame by the frontends.
+    pub fn update(&mut self) {
+        self.handle_incoming_messages();
+        self.sync_time();
+    }
+
+    /// Runs until the console asks to quit.
+    pub async fn run(mut self, mut console: Console) {
         loop{
-            self.handle_incoming_messages();
+            self.update();
+            while let Some(command) = console.poll_command() {
+                match command {
+                    Ok(Command::Quit) => return,
+                    Ok(command) => self.execute(command),
+                    Err(e) => self.chat.notice(e),
+                }
+            }
+            self.show_publications();
+            while let Some(line) = self.chat.poll_line() {
+                console.print(&line.to_string());
+            }
             //client loop goes here (such as rendering)
+            // Nothing reacts to the replicated entities yet, so their events are only drained.
+            while self.world.poll_event().is_some() {}
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Carries out a command that the user entered. Quit is up to the caller.
+    pub fn execute(&mut self, command: Command) {
+        match command {
+            Command::Name(name) => self.network_interface.send_tcp(ClientTcpMessage::SetName(name)),
+            Command::Join(room) => {
+                if self.chat.join(room.clone()) {
+                    self.network_interface.send_tcp(ClientTcpMessage::Subscribe(Interest::Room(room)));
+                }
+            }
+            Command::Msg { to, text } => self.network_interface.send_tcp(ClientTcpMessage::PrivateMessage { to, text }),
+            Command::Say(text) => match self.chat.room.clone() {
+                Some(topic) => self.network_interface.send_tcp(ClientTcpMessage::Chat { topic, text }),
+                None => self.chat.notice("Join a room with /join <room> first"),
+            },
+            Command::Subscribe(pattern) => self.subscribe(pattern),
+            Command::Unsubscribe(pattern) => self.unsubscribe(&pattern),
+            Command::Publish { topic, text } => self.publish(topic, text.into_bytes()),
+            Command::Quit => {}
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+/// What the user entered into the console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `/name <name>` changes the name other users see.
+    Name(String),
+    /// `/join <topic>` subscribes to the topic and sends the following chat messages to it.
+    Join(String),
+    /// `/msg <name> <text>` sends a private message.
+    Msg { to: String, text: String },
+    /// `/sub <pattern>` subscribes to a topic, or to every topic that matches the pattern.
+    Subscribe(String),
+    /// `/unsub <pattern>` undoes a `/sub`.
+    Unsubscribe(String),
+    /// `/pub <topic> <text>` publishes the text to the topic, if the server permits it.
+    Publish { topic: String, text: String },
+    /// `/quit` disconnects and exits.
+    Quit,
+    /// Anything that is not a command is sent to the joined topic.
+    Say(String),
+}
+
+impl Command {
+    /// Parses a line, or explains why it is not a valid command.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix('/') else {
+            return Ok(Self::Say(line.to_string()));
+        };
+        let (name, arguments) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let arguments = arguments.trim();
+        match name {
+            "name" if !arguments.is_empty() => Ok(Self::Name(arguments.to_string())),
+            "name" => Err("Usage: /name <name>".to_string()),
+            "join" if !arguments.is_empty() => Ok(Self::Join(arguments.to_string())),
+            "join" => Err("Usage: /join <topic>".to_string()),
+            "msg" => match arguments.split_once(char::is_whitespace) {
+                Some((to, text)) if !text.trim().is_empty() => Ok(Self::Msg { to: to.to_string(), text: text.trim().to_string() }),
+                _ => Err("Usage: /msg <name> <text>".to_string()),
+            },
+            "sub" if !arguments.is_empty() => Ok(Self::Subscribe(arguments.to_string())),
+            "sub" => Err("Usage: /sub <pattern>".to_string()),
+            "unsub" if !arguments.is_empty() => Ok(Self::Unsubscribe(arguments.to_string())),
+            "unsub" => Err("Usage: /unsub <pattern>".to_string()),
+            "pub" => match arguments.split_once(char::is_whitespace) {
+                Some((topic, text)) if !text.trim().is_empty() => Ok(Self::Publish { topic: topic.to_string(), text: text.trim().to_string() }),
+                _ => Err("Usage: /pub <topic> <text>".to_string()),
+            },
+            "quit" => Ok(Self::Quit),
+            _ => Err(format!("Unknown command /{name}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("/name  Alice "), Ok(Command::Name("Alice".to_string())));
+        assert_eq!(Command::parse("/join lobby"), Ok(Command::Join("lobby".to_string())));
+        assert_eq!(Command::parse("/msg bob how are you?"), Ok(Command::Msg { to: "bob".to_string(), text: "how are you?".to_string() }));
+        assert_eq!(Command::parse("/sub game/*"), Ok(Command::Subscribe("game/*".to_string())));
+        assert_eq!(Command::parse("/unsub game/*"), Ok(Command::Unsubscribe("game/*".to_string())));
+        assert_eq!(Command::parse("/pub game/score 3 to 1"), Ok(Command::Publish { topic: "game/score".to_string(), text: "3 to 1".to_string() }));
+        assert_eq!(Command::parse("/quit"), Ok(Command::Quit));
+    }
+
+    #[test]
+    fn everything_else_is_said() {
+        assert_eq!(Command::parse("  hello there "), Ok(Command::Say("hello there".to_string())));
+        assert_eq!(Command::parse("a/b"), Ok(Command::Say("a/b".to_string())));
+    }
+
+    #[test]
+    fn explains_invalid_commands() {
+        assert_eq!(Command::parse("/name"), Err("Usage: /name <name>".to_string()));
+        assert_eq!(Command::parse("/join   "), Err("Usage: /join <topic>".to_string()));
+        assert_eq!(Command::parse("/msg bob"), Err("Usage: /msg <name> <text>".to_string()));
+        assert_eq!(Command::parse("/pub game/score"), Err("Usage: /pub <topic> <text>".to_string()));
+        assert_eq!(Command::parse("/dance"), Err("Unknown command /dance".to_string()));
+    }
+}
@@ -0,0 +1,179 @@
+mod command;
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, queue, terminal};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc::error::TryRecvError;
+
+pub use command::Command;
+
+const PROMPT: &str = "> ";
+
+/// Reads commands without blocking the client loop and prints lines without clobbering the one being typed. \
+/// Whoever needs both locks takes stdout first and the typed line second.
+pub struct Console {
+    lines: UnboundedReceiver<String>,
+    /// The line being typed. None when the commands come from a script.
+    typed: Option<Arc<Mutex<String>>>,
+}
+
+impl Console {
+    /// Reads the commands from the terminal, which is switched to raw mode until the console is dropped.
+    pub fn interactive() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let typed = Arc::new(Mutex::new(String::new()));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        // A thread rather than a task, since the blocking read would keep the runtime from shutting down.
+        let typed_clone = typed.clone();
+        std::thread::spawn(move || Self::read_terminal(sender, typed_clone));
+
+        let console = Self { lines: receiver, typed: Some(typed) };
+        console.redraw(&mut io::stdout().lock())?;
+        Ok(console)
+    }
+
+    /// Reads the commands from a file, one per line. \
+    /// `/wait <ms>` pauses the script, e.g. to give the server time to answer.
+    pub fn script<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(Self::read_script(tokio::fs::File::from_std(file), sender));
+        Ok(Self { lines: receiver, typed: None })
+    }
+
+    /// The next command, if one has been entered. Once the input ends, the console asks to quit.
+    pub fn poll_command(&mut self) -> Option<Result<Command, String>> {
+        match self.lines.try_recv() {
+            Ok(line) => Some(Command::parse(&line)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Ok(Command::Quit)),
+        }
+    }
+
+    /// Prints the line above the one being typed.
+    pub fn print(&self, line: &str) {
+        let mut stdout = io::stdout().lock();
+        let result = match self.typed {
+            Some(_) => queue!(stdout, cursor::MoveToColumn(0), terminal::Clear(terminal::ClearType::CurrentLine))
+                .and_then(|_| write!(stdout, "{line}\r\n"))
+                .and_then(|_| self.redraw(&mut stdout)),
+            None => writeln!(stdout, "{line}"),
+        };
+        if let Err(e) = result {
+            eprintln!("Could not write to the console: {e}");
+        }
+    }
+
+    fn redraw(&self, stdout: &mut impl Write) -> io::Result<()> {
+        match &self.typed {
+            Some(typed) => Self::redraw_typed(stdout, typed),
+            None => stdout.flush(),
+        }
+    }
+
+    fn redraw_typed(stdout: &mut impl Write, typed: &Mutex<String>) -> io::Result<()> {
+        let typed = typed.lock().unwrap();
+        queue!(stdout, cursor::MoveToColumn(0), terminal::Clear(terminal::ClearType::CurrentLine))?;
+        write!(stdout, "{PROMPT}{typed}")?;
+        stdout.flush()
+    }
+
+    fn read_terminal(sender: UnboundedSender<String>, typed: Arc<Mutex<String>>) {
+        loop {
+            let key = match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+                Ok(_) => continue,
+                Err(e) => {
+                    eprintln!("Could not read from the console: {e}");
+                    return;
+                }
+            };
+            {
+                // Released before the redraw, which locks stdout first.
+                let mut line = typed.lock().unwrap();
+                match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        let _ = sender.send("/quit".to_string());
+                        return;
+                    }
+                    KeyCode::Char(c) => line.push(c),
+                    KeyCode::Backspace => { line.pop(); }
+                    KeyCode::Enter => {
+                        let entered = std::mem::take(&mut *line);
+                        if !entered.trim().is_empty() && sender.send(entered).is_err() {
+                            return;
+                        }
+                    }
+                    _ => continue,
+                }
+            }
+            let _ = Self::redraw_typed(&mut io::stdout().lock(), &typed);
+        }
+    }
+
+    async fn read_script(file: tokio::fs::File, sender: UnboundedSender<String>) {
+        let mut lines = BufReader::new(file).lines();
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => return,
+                Err(e) => {
+                    eprintln!("Could not read the script: {e}");
+                    return;
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(millis) = line.strip_prefix("/wait") {
+                match millis.trim().parse() {
+                    Ok(millis) => tokio::time::sleep(Duration::from_millis(millis)).await,
+                    Err(_) => eprintln!("Invalid wait in the script: {line}"),
+                }
+                continue;
+            }
+            if sender.send(line.to_string()).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        if self.typed.is_some() {
+            let _ = terminal::disable_raw_mode();
+            println!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_the_sample_script() {
+        let mut console = Console::script(concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/sample.txt")).unwrap();
+        let mut commands = Vec::new();
+        loop {
+            match console.poll_command() {
+                Some(Ok(Command::Quit)) => break,
+                Some(command) => commands.push(command),
+                None => tokio::time::sleep(Duration::from_millis(1)).await,
+            }
+        }
+        assert_eq!(commands, [
+            Ok(Command::Name("script".to_string())),
+            Ok(Command::Join("lobby".to_string())),
+            Ok(Command::Say("hello from a script".to_string())),
+            Ok(Command::Msg { to: "someone".to_string(), text: "hi there".to_string() }),
+        ]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use serializeable::Serializeable;
+use tokio::net::UdpSocket;
+use common::discovery::{DiscoveryMessage, ServerInfo, DISCOVERY_MULTICAST_ADDR, DISCOVERY_PORT};
+use common::transport::ServerAddr;
+
+/// A server that answered a discovery query.
+#[derive(Debug, Clone)]
+pub struct DiscoveredServer {
+    /// Where the answer came from.
+    pub responder: SocketAddr,
+    pub info: ServerInfo,
+}
+
+impl DiscoveredServer {
+    /// The addresses to connect to, as advertised by the server, see `ServerInfo::addresses`.
+    pub fn addresses(&self) -> Vec<ServerAddr> {
+        self.info.addresses(self.responder.ip())
+    }
+
+    /// Several servers on the same host share the discovery port and answer from the same address,
+    /// so servers are told apart by what they advertise instead.
+    fn is_same_server(&self, other: &DiscoveredServer) -> bool {
+        self.info.name == other.info.name && self.addresses() == other.addresses()
+    }
+}
+
+/// Looks for servers in the local network, by broadcasting a query and sending it to the discovery multicast group. \
+/// Collects the answers until the timeout has passed. Each server is listed once, even if it received both queries.
+pub async fn discover(timeout: Duration) -> io::Result<Vec<DiscoveredServer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    let query = DiscoveryMessage::Query.serialize();
+    socket.send_to(&query, (Ipv4Addr::BROADCAST, DISCOVERY_PORT)).await?;
+    // Not every network has a multicast route, the broadcast alone might be enough.
+    let _ = socket.send_to(&query, (DISCOVERY_MULTICAST_ADDR, DISCOVERY_PORT)).await;
+    collect_answers(&socket, timeout).await
+}
+
+/// Receives the answers to queries sent from the socket until the timeout has passed.
+async fn collect_answers(socket: &UdpSocket, timeout: Duration) -> io::Result<Vec<DiscoveredServer>> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut servers: Vec<DiscoveredServer> = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (n, responder) = received?;
+        let Ok(DiscoveryMessage::Info(info)) = DiscoveryMessage::deserialize(&mut &buf[..n]) else { continue };
+        let server = DiscoveredServer { responder, info };
+        if !servers.iter().any(|known| known.is_same_server(&server)) {
+            servers.push(server);
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use common::discovery::ServerEndpoint;
+    use super::*;
+
+    fn info(name: &str, port: u16) -> ServerInfo {
+        let endpoint = format!("0.0.0.0:{port}");
+        ServerInfo {
+            name: name.to_string(),
+            version: "1".to_string(),
+            player_count: 0,
+            capacity: 8,
+            endpoints: vec![ServerEndpoint { reliable: endpoint.clone(), unreliable: endpoint }],
+        }
+    }
+
+    /// Two servers sharing the discovery port answer from the same address. Each of them got the broadcast
+    /// and the multicast query, so each answers twice.
+    #[tokio::test]
+    async fn lists_servers_sharing_the_discovery_port_separately() {
+        let responders = Arc::new(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap());
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let client = socket.local_addr().unwrap();
+        for server in [info("first", 25550), info("second", 25560)] {
+            let responders = responders.clone();
+            tokio::spawn(async move {
+                for _ in 0..2 {
+                    responders.send_to(&DiscoveryMessage::Info(server.clone()).serialize(), client).await.unwrap();
+                }
+            });
+        }
+
+        let mut servers = collect_answers(&socket, Duration::from_millis(200)).await.unwrap();
+        servers.sort_by(|a, b| a.info.name.cmp(&b.info.name));
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].responder, servers[1].responder);
+        assert_eq!(servers[0].addresses(), vec![ServerAddr::from("127.0.0.1:25550".parse::<SocketAddr>().unwrap())]);
+        assert_eq!(servers[1].addresses(), vec![ServerAddr::from("127.0.0.1:25560".parse::<SocketAddr>().unwrap())]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serializeable::Serializeable;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use common::rendezvous::{RendezvousMessage, SessionId, KEEP_ALIVE_INTERVAL};
+
+/// How the packets of a peer connection travel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerPath {
+    Direct(SocketAddr),
+    /// Through the rendezvous at this address.
+    Relayed(SocketAddr),
+}
+
+/// A udp connection to another peer, opened by hole punching through a rendezvous. \
+/// Falls back to relaying through the rendezvous if punching fails, and switches to the direct path
+/// if a punch from the other peer still gets through later. \
+/// Only the endpoints the rendezvous introduced and the rendezvous itself are listened to.
+pub struct PeerConnection {
+    socket: Arc<UdpSocket>,
+    session: SessionId,
+    rendezvous: SocketAddr,
+    candidates: Vec<SocketAddr>,
+    path: Arc<Mutex<PeerPath>>,
+    keep_alive: JoinHandle<()>,
+}
+
+impl PeerConnection {
+    const REGISTER_INTERVAL: Duration = Duration::from_millis(500);
+    const PUNCH_INTERVAL: Duration = Duration::from_millis(100);
+    const PUNCH_DURATION: Duration = Duration::from_secs(3);
+
+    /// Registers with the rendezvous until the other peer of the session has registered as well, then tries to punch. \
+    /// Fails if the other peer does not show up within the timeout.
+    pub async fn establish(socket: UdpSocket, rendezvous: SocketAddr, session: SessionId, timeout: Duration) -> io::Result<Self> {
+        let candidates = Self::peer_endpoints(&socket, rendezvous, session, timeout).await?;
+        let path = Self::punch(&socket, session, &candidates).await?.unwrap_or(PeerPath::Relayed(rendezvous));
+        let socket = Arc::new(socket);
+        let path = Arc::new(Mutex::new(path));
+        let keep_alive = tokio::spawn(Self::keep_alive(socket.clone(), session, path.clone()));
+        Ok(Self { socket, session, rendezvous, candidates, path, keep_alive })
+    }
+
+    pub fn path(&self) -> PeerPath {
+        *self.path.lock().unwrap()
+    }
+
+    pub async fn send(&self, payload: Vec<u8>) -> io::Result<()> {
+        let session = self.session;
+        match self.path() {
+            PeerPath::Direct(peer) => self.socket.send_to(&RendezvousMessage::Data { session, payload }.serialize(), peer).await?,
+            PeerPath::Relayed(rendezvous) => self.socket.send_to(&RendezvousMessage::Relay { session, payload }.serialize(), rendezvous).await?,
+        };
+        Ok(())
+    }
+
+    /// Waits for the next payload from the other peer, whichever path it took.
+    pub async fn recv(&self) -> io::Result<Vec<u8>> {
+        let mut buf = [0u8; 2048];
+        loop {
+            let (n, sender) = self.socket.recv_from(&mut buf).await?;
+            let from_peer = self.candidates.contains(&sender);
+            if !from_peer && sender != self.rendezvous {
+                continue;
+            }
+            match RendezvousMessage::deserialize(&mut &buf[..n]) {
+                Ok(RendezvousMessage::Data { session, payload }) if session == self.session && from_peer => return Ok(payload),
+                Ok(RendezvousMessage::Relay { session, payload }) if session == self.session && sender == self.rendezvous => return Ok(payload),
+                Ok(RendezvousMessage::Punch { session }) if session == self.session && from_peer => {
+                    self.socket.send_to(&RendezvousMessage::PunchAck { session }.serialize(), sender).await?;
+                    *self.path.lock().unwrap() = PeerPath::Direct(sender);
+                }
+                Ok(RendezvousMessage::PunchAck { session }) if session == self.session && from_peer => {
+                    *self.path.lock().unwrap() = PeerPath::Direct(sender);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Keeps the session at the rendezvous alive while the packets are relayed. \
+    /// This will not return, it is aborted when the connection is dropped.
+    async fn keep_alive(socket: Arc<UdpSocket>, session: SessionId, path: Arc<Mutex<PeerPath>>) {
+        let keep_alive = RendezvousMessage::KeepAlive { session }.serialize();
+        let mut interval = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let path = *path.lock().unwrap();
+            if let PeerPath::Relayed(rendezvous) = path {
+                // A lost keepalive is made up for by the next one.
+                let _ = socket.send_to(&keep_alive, rendezvous).await;
+            }
+        }
+    }
+
+    /// Returns every address the other peer might be reachable at, the observed one first.
+    async fn peer_endpoints(socket: &UdpSocket, rendezvous: SocketAddr, session: SessionId, timeout: Duration) -> io::Result<Vec<SocketAddr>> {
+        let local_address = socket.local_addr()?;
+        let local_addresses = if local_address.ip().is_unspecified() { Vec::new() } else { vec![local_address.to_string()] };
+        let register = RendezvousMessage::Register { session, local_addresses }.serialize();
+
+        let deadline = Instant::now() + timeout;
+        let mut interval = tokio::time::interval(Self::REGISTER_INTERVAL);
+        let mut buf = [0u8; 2048];
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => return Err(io::ErrorKind::TimedOut.into()),
+                _ = interval.tick() => { socket.send_to(&register, rendezvous).await?; }
+                received = socket.recv_from(&mut buf) => {
+                    let (n, sender) = received?;
+                    if sender != rendezvous {
+                        continue;
+                    }
+                    if let Ok(RendezvousMessage::PeerEndpoints { observed, local }) = RendezvousMessage::deserialize(&mut &buf[..n]) {
+                        let mut candidates: Vec<SocketAddr> = Vec::new();
+                        for addr in std::iter::once(observed).chain(local).filter_map(|addr| addr.parse().ok()) {
+                            if !candidates.contains(&addr) {
+                                candidates.push(addr);
+                            }
+                        }
+                        return Ok(candidates);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Punches every candidate until one of them acknowledges. Punches of the other peer are answered meanwhile. \
+    /// Returns None if no path could be opened in time.
+    async fn punch(socket: &UdpSocket, session: SessionId, candidates: &[SocketAddr]) -> io::Result<Option<PeerPath>> {
+        let punch = RendezvousMessage::Punch { session }.serialize();
+        let deadline = Instant::now() + Self::PUNCH_DURATION;
+        let mut interval = tokio::time::interval(Self::PUNCH_INTERVAL);
+        let mut buf = [0u8; 2048];
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => return Ok(None),
+                _ = interval.tick() => {
+                    for candidate in candidates {
+                        // Unreachable candidates, e.g. local addresses of another network, are expected.
+                        let _ = socket.send_to(&punch, candidate).await;
+                    }
+                }
+                received = socket.recv_from(&mut buf) => {
+                    let (n, sender) = received?;
+                    if !candidates.contains(&sender) {
+                        continue;
+                    }
+                    match RendezvousMessage::deserialize(&mut &buf[..n]) {
+                        Ok(RendezvousMessage::Punch { session: punched }) if punched == session => {
+                            socket.send_to(&RendezvousMessage::PunchAck { session }.serialize(), sender).await?;
+                        }
+                        Ok(RendezvousMessage::PunchAck { session: acknowledged }) if acknowledged == session => {
+                            return Ok(Some(PeerPath::Direct(sender)));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for PeerConnection {
+    fn drop(&mut self) {
+        self.keep_alive.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: SessionId = 7;
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Introduces the first two peers that register to each other and relays their packets. \
+    /// Advertises `advertised` as their observed addresses instead of the real ones, if set.
+    async fn fake_rendezvous(socket: UdpSocket, advertised: Option<[SocketAddr; 2]>) {
+        let mut peers: Vec<SocketAddr> = Vec::new();
+        let mut buf = [0u8; 2048];
+        while let Ok((n, sender)) = socket.recv_from(&mut buf).await {
+            match RendezvousMessage::deserialize(&mut &buf[..n]) {
+                Ok(RendezvousMessage::Register { .. }) => {
+                    if !peers.contains(&sender) && peers.len() < 2 {
+                        peers.push(sender);
+                    }
+                    if let [first, second] = peers[..] {
+                        let [first_observed, second_observed] = advertised.unwrap_or([first, second]);
+                        for (target, observed) in [(first, second_observed), (second, first_observed)] {
+                            let endpoints = RendezvousMessage::PeerEndpoints { observed: observed.to_string(), local: Vec::new() };
+                            socket.send_to(&endpoints.serialize(), target).await.unwrap();
+                        }
+                    }
+                }
+                Ok(RendezvousMessage::Relay { session, payload }) if peers.contains(&sender) => {
+                    let target = *peers.iter().find(|peer| **peer != sender).unwrap();
+                    socket.send_to(&RendezvousMessage::Relay { session, payload }.serialize(), target).await.unwrap();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn connect(advertise_closed_ports: bool) -> (PeerConnection, PeerConnection) {
+        let rendezvous = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let rendezvous_addr = rendezvous.local_addr().unwrap();
+        let advertised = if advertise_closed_ports { Some([closed_port().await, closed_port().await]) } else { None };
+        tokio::spawn(fake_rendezvous(rendezvous, advertised));
+
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (first, second) = tokio::join!(
+            PeerConnection::establish(first, rendezvous_addr, SESSION, TIMEOUT),
+            PeerConnection::establish(second, rendezvous_addr, SESSION, TIMEOUT),
+        );
+        (first.unwrap(), second.unwrap())
+    }
+
+    async fn closed_port() -> SocketAddr {
+        UdpSocket::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn punches_a_direct_path() {
+        let (first, second) = connect(false).await;
+        assert_eq!(first.path(), PeerPath::Direct(second.socket.local_addr().unwrap()));
+        assert_eq!(second.path(), PeerPath::Direct(first.socket.local_addr().unwrap()));
+
+        first.send(b"hello".to_vec()).await.unwrap();
+        assert_eq!(second.recv().await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn relays_if_punching_fails() {
+        let (first, second) = connect(true).await;
+        assert!(matches!(first.path(), PeerPath::Relayed(_)));
+
+        first.send(b"hello".to_vec()).await.unwrap();
+        assert_eq!(second.recv().await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn ignores_strangers_that_know_the_session() {
+        let (first, second) = connect(true).await;
+        let stranger = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = second.socket.local_addr().unwrap();
+        stranger.send_to(&RendezvousMessage::Punch { session: SESSION }.serialize(), target).await.unwrap();
+        stranger.send_to(&RendezvousMessage::Data { session: SESSION, payload: b"forged".to_vec() }.serialize(), target).await.unwrap();
+        stranger.send_to(&RendezvousMessage::Relay { session: SESSION, payload: b"forged".to_vec() }.serialize(), target).await.unwrap();
+
+        first.send(b"hello".to_vec()).await.unwrap();
+        assert_eq!(second.recv().await.unwrap(), b"hello");
+        assert!(matches!(second.path(), PeerPath::Relayed(_)));
+    }
+}
@@ -1,15 +1,112 @@
+mod chat;
 mod client;
+mod console;
+mod discovery;
+mod hole_punching;
 mod message_resolver;
 mod network_interface;
+mod prediction;
+mod pubsub;
+mod replication;
+mod server_list;
+mod time_sync;
+mod tui;
 
+use std::time::Duration;
 use common::SERVER_ADDR;
+use common::discovery::ServerInfo;
+use common::registry::ServerFilter;
+use common::transport::{ServerAddr, TransportKind};
 use crate::client::Client;
+use crate::console::Console;
+use crate::network_interface::config::ConnectionConfig;
+use crate::tui::Tui;
 
+/// How long to wait for servers to answer a discovery query.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+const USAGE: &str = "\
+Usage: client [options]
+Options:
+  --script <path>                             read the commands from a file
+  --tui                                       full screen interface
+  --discover                                  list the servers in the local network instead of connecting
+  --registry <address>                        list the servers known to the registry instead of connecting
+  --id-file <path>                            log in with the user id stored in the file, and store the id there
+  --quic <server name> <certificate path>     connect over quic, trusting only the certificate stored by the server";
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let client = Client::new(SERVER_ADDR).await?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(Options { frontend, transport, list, connection }) = parse_args(&args) else {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    };
+
+    match list {
+        Some(ServerSource::LocalNetwork) => {
+            for server in discovery::discover(DISCOVERY_TIMEOUT).await? {
+                print_server(&server.info, &server.addresses());
+            }
+            return Ok(());
+        }
+        Some(ServerSource::Registry(registry)) => {
+            for server in server_list::query_registry(registry, ServerFilter::default()).await? {
+                print_server(&server.info, &server.addresses());
+            }
+            return Ok(());
+        }
+        None => {}
+    }
 
-    client.run();
+    let client = Client::with_transport_kind(transport, SERVER_ADDR, connection).await?;
+    match frontend {
+        Frontend::Console => client.run(Console::interactive()?).await,
+        Frontend::Script(path) => client.run(Console::script(path)?).await,
+        Frontend::Tui => Tui::new()?.run(client).await?,
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+enum Frontend {
+    Console,
+    /// Reads the commands from the file.
+    Script(String),
+    Tui,
+}
+
+/// Where to look for servers when only listing them.
+enum ServerSource {
+    LocalNetwork,
+    /// The address of the registry.
+    Registry(String),
+}
+
+struct Options {
+    frontend: Frontend,
+    transport: TransportKind,
+    list: Option<ServerSource>,
+    connection: ConnectionConfig,
+}
+
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut options = Options { frontend: Frontend::Console, transport: TransportKind::TcpUdp, list: None, connection: ConnectionConfig::default() };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--script" => options.frontend = Frontend::Script(args.next()?.clone()),
+            "--tui" => options.frontend = Frontend::Tui,
+            "--discover" => options.list = Some(ServerSource::LocalNetwork),
+            "--registry" => options.list = Some(ServerSource::Registry(args.next()?.clone())),
+            "--id-file" => options.connection.id_file = Some(args.next()?.into()),
+            "--quic" => options.transport = TransportKind::Quic { server_name: args.next()?.clone(), certificate_path: args.next()?.into() },
+            _ => return None,
+        }
+    }
+    Some(options)
+}
+
+fn print_server(info: &ServerInfo, addresses: &[ServerAddr]) {
+    let addresses: Vec<String> = addresses.iter().map(|addr| addr.reliable.to_string()).collect();
+    println!("{} ({}/{} players, version {}): {}", info.name, info.player_count, info.capacity, info.version, addresses.join(", "));
+}
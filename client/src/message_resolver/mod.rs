@@ -1,25 +1,106 @@
-use common::message::{ServerMessage, ServerTcpMessage, ServerUdpMessage};
+use common::interest::Interest;
+use common::message::{ClientTcpMessage, ClientUdpMessage, ServerMessage, ServerTcpMessage, ServerUdpMessage};
+use crate::chat::ChatLine;
 use crate::client::Client;
+use crate::network_interface::{ClientError, ClientEvent, ConnectionState, DisconnectReason};
 
 impl Client {
     pub fn handle_incoming_messages(&mut self) {
-        while let Some(message) = self.network_interface.incoming_message() {
-            match message {
-                ServerMessage::Tcp(msg) => self.handle_tcp_message(msg),
-                ServerMessage::Udp(msg) => self.handle_udp_message(msg),
+        while let Some(event) = self.network_interface.incoming_message() {
+            match event {
+                ClientEvent::Connected { user_id } => {
+                    self.logged_in();
+                    self.chat.notice(format!("Connected to the server as user {user_id}"));
+                }
+                ClientEvent::Reconnected { user_id } => {
+                    self.logged_in();
+                    self.chat.notice(format!("Reconnected to the server as user {user_id}"));
+                }
+                ClientEvent::Message(ServerMessage::Tcp(msg)) => self.handle_tcp_message(msg),
+                ClientEvent::Message(ServerMessage::Udp(msg)) => self.handle_udp_message(msg),
+                ClientEvent::Disconnected { reason: DisconnectReason::Kicked(reason) } => {
+                    self.connection = ConnectionState::Kicked;
+                    self.chat.users.clear();
+                    self.chat.notice(format!("The server closed the connection: {reason}"));
+                }
+                ClientEvent::Disconnected { reason } => {
+                    // The server forgets who is online with us, and tells us again after reconnecting.
+                    self.connection = ConnectionState::Disconnected;
+                    self.chat.users.clear();
+                    self.chat.notice(format!("Lost the connection to the server: {reason:?}"));
+                }
+                ClientEvent::Reconnecting { attempt } => {
+                    self.connection = ConnectionState::Reconnecting { attempt };
+                    self.chat.notice(format!("Reconnecting to the server, attempt {attempt}"));
+                }
+                ClientEvent::QualityChanged(quality) => self.chat.notice(format!("Connection quality: {quality:?}")),
+                ClientEvent::Error(error) => {
+                    if matches!(error, ClientError::GaveUp) {
+                        self.connection = ConnectionState::GaveUp;
+                    }
+                    self.chat.notice(format!("Connection error: {error:?}"));
+                }
             }
         }
     }
 
+    /// Starts over after a login. The server sends a full snapshot and might have been restarted.
+    fn logged_in(&mut self) {
+        self.world.reset_history();
+        self.time_sync = Default::default();
+        self.connection = ConnectionState::Connected;
+        // The server dropped the subscriptions of the previous connection.
+        for room in &self.chat.rooms {
+            self.network_interface.send_tcp(ClientTcpMessage::Subscribe(Interest::Room(room.clone())));
+        }
+        for pattern in &self.pubsub.subscriptions {
+            self.network_interface.send_tcp(ClientTcpMessage::Subscribe(Interest::Topic(pattern.clone())));
+        }
+    }
+
     fn handle_tcp_message(&mut self, message: ServerTcpMessage) {
         match message {
-            _ => unimplemented!(),
+            ServerTcpMessage::Text(text) => self.chat.notice(text),
+            ServerTcpMessage::Chat { topic, from, text } => self.chat.push(ChatLine::Room { room: topic, from, text }),
+            ServerTcpMessage::PrivateMessage { from, text } => self.chat.push(ChatLine::Private { from, text }),
+            ServerTcpMessage::UserOnline { id, name } => { self.chat.users.insert(id, name); }
+            ServerTcpMessage::UserOffline(id) => { self.chat.users.remove(&id); }
+            ServerTcpMessage::EntityEntered(entity) => self.world.interest_changed(entity, true),
+            ServerTcpMessage::EntityLeft(entity) => self.world.interest_changed(entity, false),
+            ServerTcpMessage::Published(publication) => self.pubsub.push(publication),
+            // Normally unwrapped by the network manager already.
+            ServerTcpMessage::Unreliable(message) => self.handle_udp_message(message),
+            // Ids are assigned during the login, responses go straight to their calls and kicks end the connection,
+            // all in the network manager.
+            ServerTcpMessage::AssignUserId(_) | ServerTcpMessage::Response { .. } | ServerTcpMessage::Kicked(_) => {}
+            // This client does not connect over unix sockets.
+            ServerTcpMessage::UnixDatagramPath(_) => {}
         }
     }
 
     fn handle_udp_message(&mut self, message: ServerUdpMessage) {
         match message {
-            _ => unimplemented!(),
+            ServerUdpMessage::Snapshot(snapshot) => {
+                let last_processed_input = snapshot.last_processed_input;
+                if let Some(tick) = self.world.apply_snapshot(snapshot) {
+                    self.network_interface.send_udp(ClientUdpMessage::AcknowledgeSnapshot(tick));
+                    self.prediction.reconcile(self.world.state(), last_processed_input);
+                }
+            }
+            ServerUdpMessage::SnapshotPart(part) => {
+                if let Some(snapshot) = self.world.receive_part(part) {
+                    self.handle_udp_message(ServerUdpMessage::Snapshot(snapshot));
+                }
+            }
+            ServerUdpMessage::TimeResponse(response) => self.time_sync.handle_response(response),
+            ServerUdpMessage::Published(publication) => self.pubsub.push(publication),
+            ServerUdpMessage::Timestamped(timestamp, message) => {
+                self.last_server_timestamp = Some(timestamp);
+                self.handle_udp_message(*message);
+            }
+            ServerUdpMessage::ChatMessage(text) => self.chat.notice(text),
+            // Answered and measured by the network manager.
+            ServerUdpMessage::Ping(_) | ServerUdpMessage::Pong(_) => {}
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use common::{ReconnectToken, UserId};
+use crate::network_interface::retry_policy::RetryPolicy;
+
+/// How the client connects and stays connected to the server.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionConfig {
+    pub retry_policy: RetryPolicy,
+    /// Remember the user id and its reconnect token in this file, so that the next launch logs in with the same id.
+    pub id_file: Option<PathBuf>,
+}
+
+/// What the client needs to log in with the same user id again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub user_id: UserId,
+    pub token: ReconnectToken,
+}
+
+impl Credentials {
+    /// Reads credentials stored with `store`. A missing or unreadable file gives None.
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        let mut parts = content.split_whitespace();
+        let user_id = parts.next()?.parse().ok()?;
+        let token = parts.next()?.parse().ok()?;
+        Some(Self { user_id, token })
+    }
+
+    /// The token is a secret, so on unix only the owner may read the file.
+    pub fn store(&self, path: &Path) -> std::io::Result<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // The mode only applies to new files, an existing one might have been readable by others.
+            if path.exists() {
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        let mut file = options.open(path)?;
+        write!(file, "{} {}", self.user_id, self.token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id_file() -> PathBuf {
+        std::env::temp_dir().join(format!("client-id-{:x}", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn stored_credentials_load_again() {
+        let path = id_file();
+        let credentials = Credentials { user_id: 42, token: u64::MAX };
+        credentials.store(&path).unwrap();
+        assert_eq!(Credentials::load(&path), Some(credentials));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_or_broken_files_load_nothing() {
+        let path = id_file();
+        assert_eq!(Credentials::load(&path), None);
+        std::fs::write(&path, "42").unwrap();
+        assert_eq!(Credentials::load(&path), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_can_read_the_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = id_file();
+        std::fs::write(&path, "").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        Credentials { user_id: 1, token: 2 }.store(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,34 +1,144 @@
 mod network_manager;
+pub(crate) mod config;
+pub(crate) mod retry_policy;
+pub(crate) mod rpc;
 
-use std::net::ToSocketAddrs;
+use std::sync::{Arc, Mutex};
+use tokio::net::ToSocketAddrs;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::mpsc::error::TryRecvError;
+use common::link_conditioner::{LinkConditioner, NetworkConditions};
+use common::link_quality::{ConnectionQuality, ConnectionStats, LinkMonitor};
 use common::message::{ClientMessage, ClientTcpMessage, ClientUdpMessage, ServerMessage};
-use crate::network_interface::network_manager::NetworkManager;
+use common::transport::{ServerAddr, TcpUdpTransport, Transport};
+use common::UserId;
+use crate::network_interface::network_manager::{NetworkManager, NetworkManagerHandle, SharedLinkConditioner};
+use crate::network_interface::config::ConnectionConfig;
+use crate::network_interface::rpc::RpcClient;
+
+/// Everything that happens to the connection to the server, in order.
+#[derive(Debug)]
+pub enum ClientEvent {
+    /// Logged in for the first time.
+    Connected { user_id: UserId },
+    /// Logged in again after the connection was lost. The id stays the same, unless the server no longer knew it,
+    /// e.g. because it has been restarted. Either way, the server has forgotten the subscriptions of the previous connection.
+    Reconnected { user_id: UserId },
+    Message(ServerMessage),
+    /// The connection to the server has been lost. Reconnection attempts follow, unless the retry policy allows none
+    /// or the client has been kicked.
+    Disconnected { reason: DisconnectReason },
+    /// A reconnection attempt is about to start, counting from 1.
+    Reconnecting { attempt: u32 },
+    /// The measured quality of the connection crossed a threshold, or was measured for the first time since logging in.
+    QualityChanged(ConnectionQuality),
+    Error(ClientError),
+}
+
+#[derive(Debug)]
+pub enum DisconnectReason {
+    /// The server closed the connection, or it broke down while receiving.
+    Closed,
+    /// Sending or receiving over one of the sockets failed.
+    Io(std::io::Error),
+    /// The server closed the connection on purpose, for the given reason. No more events will follow.
+    Kicked(String),
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The connection is retried according to the retry policy.
+    ReconnectionFailed(std::io::Error),
+    /// The retry policy ran out of attempts. No more events will follow.
+    GaveUp,
+    /// The new id after a reconnection could not be stored in the id file. The connection is not affected.
+    IdNotStored(std::io::Error),
+}
+
+/// The state of the connection, as it follows from the latest events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    Disconnected,
+    Reconnecting { attempt: u32 },
+    GaveUp,
+    Kicked,
+}
 
 pub(super) struct NetworkInterface {
-    incoming_messages: UnboundedReceiver<ServerMessage>,
+    user_id: UserId,
+    incoming_messages: UnboundedReceiver<ClientEvent>,
     outgoing_messages: UnboundedSender<ClientMessage>,
+    link_monitor: Arc<Mutex<LinkMonitor>>,
+    link_conditioner: SharedLinkConditioner,
+    rpc: RpcClient,
+    /// Set once the network manager has given up or has been kicked, after which it stops.
+    finished: bool,
 }
 
 impl NetworkInterface {
     const ERROR_MSG: &str = "Clients Network Manager crashed unexpectedly";
     pub async fn create<A: ToSocketAddrs>(addr: A) -> std::io::Result<Self> {
-        let (outgoing_messages, incoming_messages) = NetworkManager::launch(addr).await?;
-        Ok(Self { incoming_messages, outgoing_messages })
+        Self::create_with_transport(TcpUdpTransport, addr, ConnectionConfig::default()).await
+    }
+
+    /// Connects to the server over the given transport instead of tcp and udp.
+    pub async fn create_with_transport<T: Transport, A: ToSocketAddrs>(transport: T, addr: A, config: ConnectionConfig) -> std::io::Result<Self> {
+        let addr = tokio::net::lookup_host(addr).await?.next().ok_or(std::io::ErrorKind::AddrNotAvailable)?;
+        Self::connect(transport, ServerAddr::from(addr), config).await
     }
 
+    /// Connects to a server that may use different addresses for the reliable and the unreliable channel. \
+    /// Fails if the id file is set but the credentials cannot be stored in it.
+    pub async fn connect<T: Transport>(transport: T, server_addr: ServerAddr, config: ConnectionConfig) -> std::io::Result<Self> {
+        let link_conditioner = SharedLinkConditioner::default();
+        let NetworkManagerHandle { credentials, outgoing_messages, incoming_messages, link_monitor, pending_calls } =
+            NetworkManager::launch(transport, server_addr, link_conditioner.clone(), config.retry_policy, config.id_file).await?;
+        let user_id = credentials.user_id;
+        let rpc = RpcClient::new(pending_calls, outgoing_messages.clone());
+        Ok(Self { user_id, incoming_messages, outgoing_messages, link_monitor, link_conditioner, rpc, finished: false })
+    }
+
+    /// The id the server knows this client by. It only changes when a reconnection needs a new one, see `ClientEvent::Reconnected`.
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    /// Calls methods on the server, independently of the client loop.
+    pub fn rpc(&self) -> RpcClient {
+        self.rpc.clone()
+    }
+
+    /// Messages sent after `GaveUp` or a kick are dropped.
     pub fn send_tcp(&mut self, msg: ClientTcpMessage){
-        self.outgoing_messages.send(ClientMessage::Tcp(msg)).expect(Self::ERROR_MSG)
+        let _ = self.outgoing_messages.send(ClientMessage::Tcp(msg));
     }
+    /// Messages sent after `GaveUp` or a kick are dropped.
     pub fn send_udp(&mut self, msg: ClientUdpMessage){
-        self.outgoing_messages.send(ClientMessage::Udp(msg)).expect(Self::ERROR_MSG)
+        let _ = self.outgoing_messages.send(ClientMessage::Udp(msg));
+    }
+
+    /// Simulates bad network conditions on the udp path. Meant for testing, None turns the simulation off.
+    pub fn set_link_conditions(&mut self, conditions: Option<NetworkConditions>) {
+        *self.link_conditioner.lock().unwrap() = conditions.map(LinkConditioner::new);
+    }
+
+    /// Round trip time, jitter and packet loss of the connection. None until the first probe has been answered.
+    pub fn connection_stats(&self) -> Option<ConnectionStats> {
+        self.link_monitor.lock().unwrap().stats()
     }
 
-    /// A return value of None means that no more Messages have been received _yet_.
-    pub fn incoming_message(&mut self) -> Option<ServerMessage> {
+    /// A return value of None means that no more Messages have been received _yet_, or none will follow after `GaveUp` or a kick.
+    pub fn incoming_message(&mut self) -> Option<ClientEvent> {
         match self.incoming_messages.try_recv() {
-            Ok(content) => Some(content),
+            Ok(event) => {
+                if let ClientEvent::Reconnected { user_id } = event {
+                    self.user_id = user_id;
+                }
+                self.finished |= matches!(event, ClientEvent::Error(ClientError::GaveUp) | ClientEvent::Disconnected { reason: DisconnectReason::Kicked(_) });
+                Some(event)
+            }
+            Err(TryRecvError::Disconnected) if self.finished => None,
             Err(TryRecvError::Disconnected) => panic!("{}", Self::ERROR_MSG),
             Err(TryRecvError::Empty) => None
         }
@@ -1,73 +1,457 @@
-use std::sync::Arc;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use serializeable::Serializeable;
 use tokio::io::AsyncWriteExt;
-use tokio::net::{TcpStream, ToSocketAddrs, UdpSocket};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver as Receiver, UnboundedReceiver, UnboundedSender as Sender, UnboundedSender};
-use common::message::{ClientMessage, ServerMessage, ServerTcpMessage, ServerUdpMessage};
-pub struct NetworkManager {
-    tcp: TcpStream,
-    udp: Arc<UdpSocket>,
+use common::link_conditioner::LinkConditioner;
+use common::link_quality::{ConnectionQuality, ConnectionStats, LinkMonitor};
+use common::message::{ClientMessage, ClientTcpMessage, ClientUdpMessage, ServerMessage, ServerTcpMessage, ServerUdpMessage};
+use common::message::client_message::ClientConnectionMessage;
+use common::message::send_message::TcpSendable;
+use common::message::server_message::ServerConnectionMessage;
+use common::transport::{DatagramSocket, ReliableStream, ServerAddr, Transport};
+use crate::network_interface::{ClientError, ClientEvent, DisconnectReason};
+use crate::network_interface::config::Credentials;
+use crate::network_interface::retry_policy::RetryPolicy;
+use crate::network_interface::rpc::{self, PendingCalls};
 
-    incoming_messages: Sender<ServerMessage>,
+/// Opt-in simulation of bad network conditions on the udp path, for testing.
+pub type SharedLinkConditioner = Arc<Mutex<Option<LinkConditioner>>>;
+
+/// Keeps the client connected to the server. A lost connection is reported and reestablished according to the retry policy,
+/// logging in with the same user id again, or with a new one if the server no longer knows it.
+pub struct NetworkManager<T: Transport> {
+    transport: T,
+    server_addr: ServerAddr,
+    retry_policy: RetryPolicy,
+    credentials: Option<Credentials>,
+    /// Where the credentials are remembered across launches.
+    id_file: Option<PathBuf>,
+    link_monitor: Arc<Mutex<LinkMonitor>>,
+    link_conditioner: SharedLinkConditioner,
+    pending_calls: PendingCalls,
+
+    incoming_messages: Sender<ClientEvent>,
     outgoing_messages: Receiver<ClientMessage>,
 }
 
-impl NetworkManager {
-    pub async fn launch<A: ToSocketAddrs>(server_addr: A) -> std::io::Result<(UnboundedSender<ClientMessage>, UnboundedReceiver<ServerMessage>)> {
+/// What the network interface gets to talk to a launched network manager.
+pub struct NetworkManagerHandle {
+    pub credentials: Credentials,
+    pub outgoing_messages: UnboundedSender<ClientMessage>,
+    pub incoming_messages: UnboundedReceiver<ClientEvent>,
+    pub link_monitor: Arc<Mutex<LinkMonitor>>,
+    pub pending_calls: PendingCalls,
+}
+
+/// The streams and socket of a single connection to the server.
+struct Connection<T: Transport> {
+    tcp_reader: <T::Stream as ReliableStream>::ReadHalf,
+    tcp_writer: <T::Stream as ReliableStream>::WriteHalf,
+    udp: UdpPath<T>,
+}
+
+enum ConnectionEnd {
+    Lost(DisconnectReason),
+    /// The network interface has been dropped.
+    Shutdown,
+}
+
+impl<T: Transport> NetworkManager<T> {
+    /// Fails if the first connection attempt fails, only later ones are retried. \
+    /// Logs in with the id stored in the id file if it is free, with a new one otherwise, and returns the credentials it got.
+    /// Also fails if they cannot be stored in the id file.
+    pub async fn launch(transport: T, server_addr: ServerAddr, link_conditioner: SharedLinkConditioner, retry_policy: RetryPolicy, id_file: Option<PathBuf>) -> io::Result<NetworkManagerHandle> {
         let (outgoing_messages_sender, outgoing_messages_receiver) = unbounded_channel();
         let (incoming_messages_sender, incoming_messages_receiver) = unbounded_channel();
+        let link_monitor = Arc::new(Mutex::new(LinkMonitor::default()));
+        let pending_calls = PendingCalls::default();
+
+        let mut manager = Self {
+            transport,
+            server_addr,
+            retry_policy,
+            // A missing or unreadable id file just means that the server hands out a new id.
+            credentials: id_file.as_deref().and_then(Credentials::load),
+            id_file,
+            link_monitor: link_monitor.clone(),
+            link_conditioner,
+            pending_calls: pending_calls.clone(),
+            incoming_messages: incoming_messages_sender,
+            outgoing_messages: outgoing_messages_receiver,
+        };
+        let connection = manager.connect(true).await?;
+        manager.store_credentials()?;
+        let credentials = manager.credentials.expect("logged in without a user id");
+        let _ = manager.incoming_messages.send(ClientEvent::Connected { user_id: credentials.user_id });
+        tokio::spawn(manager.run(connection));
+        Ok(NetworkManagerHandle {
+            credentials,
+            outgoing_messages: outgoing_messages_sender,
+            incoming_messages: incoming_messages_receiver,
+            link_monitor,
+            pending_calls,
+        })
+    }
+
+    /// A reconnecting client must not fall back to a new id, it would lose its identity.
+    async fn connect(&mut self, fall_back_to_new_id: bool) -> io::Result<Connection<T>> {
+        let (mut tcp, udp) = self.transport.connect(self.server_addr).await?;
+        self.credentials = Some(Self::login_procedure(&mut tcp, self.credentials, fall_back_to_new_id).await?);
+        // Measurements of a previous connection say nothing about this one.
+        *self.link_monitor.lock().unwrap() = LinkMonitor::default();
 
+        let (tcp_reader, tcp_writer) = tcp.into_split();
+        let udp = UdpPath { socket: Arc::new(udp), server_addr: self.server_addr.unreliable, link_conditioner: self.link_conditioner.clone() };
+        Ok(Connection { tcp_reader, tcp_writer, udp })
+    }
+
+    /// Asks for the given id if there is one, or for a new one otherwise. \
+    /// If the id is still in use, e.g. because the server has not noticed yet that the previous connection is gone,
+    /// this either asks for a new id or fails. If the server does not know the id, e.g. after a restart, it always asks for a new one.
+    async fn login_procedure(tcp: &mut T::Stream, mut credentials: Option<Credentials>, fall_back_to_new_id: bool) -> io::Result<Credentials> {
+        loop {
+            let request = match credentials {
+                Some(Credentials { user_id, token }) => ClientConnectionMessage::ConnectWithId { id: user_id, token },
+                None => ClientConnectionMessage::ConnectNew,
+            };
+            request.send(tcp).await?;
+            match ServerConnectionMessage::async_deserialize(tcp).await.map_err(|_| io::Error::from(io::ErrorKind::InvalidData))? {
+                ServerConnectionMessage::AssignUserId { id, token } => return Ok(Credentials { user_id: id, token }),
+                ServerConnectionMessage::AcknowledgeId => {
+                    return credentials.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "acknowledged an id that was not requested"));
+                }
+                ServerConnectionMessage::IdAlreadyInUse if fall_back_to_new_id && credentials.is_some() => credentials = None,
+                ServerConnectionMessage::IdAlreadyInUse => return Err(io::Error::new(io::ErrorKind::AddrInUse, "the user id is still in use")),
+                ServerConnectionMessage::UnknownId if credentials.is_some() => credentials = None,
+                ServerConnectionMessage::Refused(reason) => return Err(io::Error::new(io::ErrorKind::PermissionDenied, reason)),
+                response => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected response to the login: {response:?}"))),
+            }
+        }
+    }
+
+    /// Returns once the client is gone, the retry policy gives up or the server kicks the client.
+    async fn run(mut self, mut connection: Connection<T>) {
+        loop {
+            let end = self.run_connection(connection).await;
+            // The server forgets the calls of a connection, so their responses would never arrive.
+            // Their requests are not sent again either, see `send_messages`.
+            rpc::cancel_all(&self.pending_calls);
+            let reason = match end {
+                ConnectionEnd::Lost(reason) => reason,
+                ConnectionEnd::Shutdown => return,
+            };
+            let kicked = matches!(reason, DisconnectReason::Kicked(_));
+            // Without a receiver, there is no one left to reconnect for.
+            if self.incoming_messages.send(ClientEvent::Disconnected { reason }).is_err() || kicked {
+                return;
+            }
+            let previous = self.credentials;
+            match self.reconnect().await {
+                Some(new_connection) => {
+                    connection = new_connection;
+                    if self.credentials != previous {
+                        if let Err(e) = self.store_credentials() {
+                            let _ = self.incoming_messages.send(ClientEvent::Error(ClientError::IdNotStored(e)));
+                        }
+                    }
+                    let user_id = self.credentials.expect("logged in without a user id").user_id;
+                    if self.incoming_messages.send(ClientEvent::Reconnected { user_id }).is_err() {
+                        return;
+                    }
+                }
+                None => {
+                    // Calls made while reconnecting.
+                    rpc::cancel_all(&self.pending_calls);
+                    let _ = self.incoming_messages.send(ClientEvent::Error(ClientError::GaveUp));
+                    return;
+                }
+            }
+        }
+    }
 
-        let tcp = TcpStream::connect(&server_addr).await?;
-        let udp = Arc::new(UdpSocket::bind(tcp.local_addr()?).await?);
-        udp.connect(&server_addr).await?;
+    /// Remembers the credentials in the id file, if there is one.
+    fn store_credentials(&self) -> io::Result<()> {
+        match (&self.id_file, self.credentials) {
+            (Some(path), Some(credentials)) => credentials.store(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("failed to store the user id in {}: {e}", path.display()))),
+            _ => Ok(()),
+        }
+    }
 
-        Self{ tcp, udp, incoming_messages: incoming_messages_sender, outgoing_messages: outgoing_messages_receiver }.run();
-        Ok(
-            (
-                outgoing_messages_sender,
-                incoming_messages_receiver
-            )
-        )
+    /// Returns None once the retry policy runs out of attempts, the server refuses the login or the client is gone.
+    async fn reconnect(&mut self) -> Option<Connection<T>> {
+        for attempt in 0.. {
+            if self.retry_policy.max_attempts.is_some_and(|max_attempts| attempt >= max_attempts) {
+                break;
+            }
+            tokio::time::sleep(self.retry_policy.delay(attempt)).await;
+            if self.incoming_messages.send(ClientEvent::Reconnecting { attempt: attempt + 1 }).is_err() {
+                return None;
+            }
+            match self.connect(false).await {
+                Ok(connection) => return Some(connection),
+                Err(e) => {
+                    // E.g. banned, trying again would not change that.
+                    let refused = e.kind() == io::ErrorKind::PermissionDenied;
+                    if self.incoming_messages.send(ClientEvent::Error(ClientError::ReconnectionFailed(e))).is_err() || refused {
+                        return None;
+                    }
+                }
+            }
+        }
+        None
     }
-    fn run(self) {
-        let (tcp_reader, tcp_writer) = self.tcp.into_split();
 
-        tokio::spawn(Self::receive_udp(self.udp.clone(), self.incoming_messages.clone()));
-        tokio::spawn(Self::receive_tcp(tcp_reader, self.incoming_messages.clone()));
-        tokio::spawn(Self::send_messages(tcp_writer, self.udp.clone(), self.outgoing_messages));
+    /// Runs until the connection is lost. Messages sent in the meantime are queued for the next connection.
+    async fn run_connection(&mut self, connection: Connection<T>) -> ConnectionEnd {
+        let Connection { tcp_reader, tcp_writer, udp } = connection;
+        tokio::select! {
+            kick = Self::receive_tcp(tcp_reader, &self.incoming_messages, &self.pending_calls) => ConnectionEnd::Lost(kick.map_or(DisconnectReason::Closed, DisconnectReason::Kicked)),
+            e = Self::receive_udp(udp.clone(), self.incoming_messages.clone(), self.link_monitor.clone()) => ConnectionEnd::Lost(DisconnectReason::Io(e)),
+            _ = Self::probe(&udp, &self.link_monitor, &self.incoming_messages) => unreachable!("probing never ends"),
+            end = Self::send_messages(tcp_writer, &udp, &mut self.outgoing_messages, &self.pending_calls) => end,
+        }
     }
 
-    async fn receive_udp(udp: Arc<UdpSocket>, incoming_messages: Sender<ServerMessage>) {
+    /// Packets are handled once their simulated delay has passed, if a link conditioner is set.
+    /// Packets that do not come from the server are dropped. \
+    /// Returns once the socket fails.
+    async fn receive_udp(udp: UdpPath<T>, incoming_messages: Sender<ClientEvent>, link_monitor: Arc<Mutex<LinkMonitor>>) -> io::Error {
         let mut buf = [0u8; 2048];
         loop {
-            udp.recv(&mut buf).await.expect("failed to receive UDP packet");
-            let msg = ServerUdpMessage::async_deserialize(&mut &buf[..]).await.unwrap();
-            incoming_messages.send(ServerMessage::Udp(msg)).expect("message receiver hung up");
+            let n = match udp.socket.recv_from(&mut buf).await {
+                Ok((n, sender)) if sender == udp.server_addr => n,
+                Ok(_) => continue,
+                Err(e) => return e,
+            };
+            let Ok(msg) = ServerUdpMessage::deserialize(&mut &buf[..n]) else { continue };
+            let delays = udp.link_conditioner.lock().unwrap().as_mut().map(|conditioner| conditioner.incoming(n));
+            match delays {
+                None => Self::handle_udp(msg, &udp, &incoming_messages, &link_monitor).await,
+                Some(delays) => {
+                    for delay in delays {
+                        let (msg, udp, incoming_messages, link_monitor) = (msg.clone(), udp.clone(), incoming_messages.clone(), link_monitor.clone());
+                        tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            Self::handle_udp(msg, &udp, &incoming_messages, &link_monitor).await;
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pings are answered right away, so that the client loop does not distort the servers measurement.
+    async fn handle_udp(msg: ServerUdpMessage, udp: &UdpPath<T>, incoming_messages: &Sender<ClientEvent>, link_monitor: &Mutex<LinkMonitor>) {
+        match msg {
+            ServerUdpMessage::Ping(sequence) => udp.send(ClientUdpMessage::Pong(sequence).serialize()).await,
+            ServerUdpMessage::Pong(sequence) => link_monitor.lock().unwrap().pong_received(sequence),
+            // A gone client is noticed by the other tasks of the connection.
+            msg => { let _ = incoming_messages.send(ClientEvent::Message(ServerMessage::Udp(msg))); }
         }
     }
-    async fn receive_tcp(mut tcp_reader: OwnedReadHalf, incoming_messages: Sender<ServerMessage>) {
+
+    /// Regularly pings the server to measure the connection and reports whenever its quality changes. \
+    /// This will not return
+    async fn probe(udp: &UdpPath<T>, link_monitor: &Mutex<LinkMonitor>, incoming_messages: &Sender<ClientEvent>) {
+        let mut interval = tokio::time::interval(LinkMonitor::PROBE_INTERVAL);
+        let mut quality = None;
         loop {
-            let msg = ServerTcpMessage::async_deserialize(&mut tcp_reader).await.unwrap();
-            incoming_messages.send(ServerMessage::Tcp(msg)).expect("message receiver hung up");
+            interval.tick().await;
+            let (sequence, stats) = {
+                let mut link_monitor = link_monitor.lock().unwrap();
+                (link_monitor.next_probe(), link_monitor.stats())
+            };
+            if let Some(new_quality) = quality_change(&mut quality, stats) {
+                // A gone client is noticed by the other tasks of the connection.
+                let _ = incoming_messages.send(ClientEvent::QualityChanged(new_quality));
+            }
+            udp.send(ClientUdpMessage::Ping(sequence).serialize()).await;
         }
     }
 
-    async fn send_messages(mut tcp_writer: OwnedWriteHalf, udp_socket: Arc<UdpSocket>, mut outgoing_messages: Receiver<ClientMessage>) {
-        loop{
-            let msg = outgoing_messages.recv().await.unwrap();
+    /// Responses go straight to their calls. Returns once the connection is closed or the client is gone,
+    /// or with the reason if the server kicked the client.
+    async fn receive_tcp(mut tcp_reader: <T::Stream as ReliableStream>::ReadHalf, incoming_messages: &Sender<ClientEvent>, pending_calls: &PendingCalls) -> Option<String> {
+        while let Ok(msg) = ServerTcpMessage::async_deserialize(&mut tcp_reader).await {
             match msg {
+                ServerTcpMessage::Response { id, result } => rpc::complete(pending_calls, id, result),
+                ServerTcpMessage::Kicked(reason) => return Some(reason),
+                msg => {
+                    if incoming_messages.send(ClientEvent::Message(ServerMessage::from(msg))).is_err() {
+                        return None;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Requests whose call has already failed, e.g. because they were queued when the previous connection was lost, are dropped.
+    async fn send_messages(mut tcp_writer: <T::Stream as ReliableStream>::WriteHalf, udp: &UdpPath<T>, outgoing_messages: &mut Receiver<ClientMessage>, pending_calls: &PendingCalls) -> ConnectionEnd {
+        while let Some(msg) = outgoing_messages.recv().await {
+            match msg {
+                ClientMessage::Tcp(ClientTcpMessage::Request { id, .. }) if !pending_calls.lock().unwrap().contains_key(&id) => {}
                 ClientMessage::Tcp(tcp_message) => {
-                    tcp_writer.writable().await.unwrap();
                     let msg_bytes = tcp_message.serialize();
-                    tcp_writer.write_all(&msg_bytes).await.unwrap();
+                    if let Err(e) = tcp_writer.write_all(&msg_bytes).await {
+                        return ConnectionEnd::Lost(DisconnectReason::Io(e));
+                    }
                 }
                 ClientMessage::Udp(udp_message) => {
                     let msg_bytes = udp_message.serialize();
-                    udp_socket.send(&msg_bytes).await.unwrap();
+                    udp.send(msg_bytes).await;
                 }
             }
         }
+        ConnectionEnd::Shutdown
+    }
+}
+
+/// Returns the quality of the new measurement if it crossed a threshold since the last one, and remembers it.
+fn quality_change(quality: &mut Option<ConnectionQuality>, stats: Option<ConnectionStats>) -> Option<ConnectionQuality> {
+    let new_quality = stats.as_ref().map(ConnectionQuality::of);
+    if new_quality == *quality {
+        return None;
+    }
+    *quality = new_quality;
+    new_quality
+}
+
+/// The datagram socket together with everything needed to send over it.
+struct UdpPath<T: Transport> {
+    socket: Arc<T::Datagram>,
+    /// Where the unreliable messages are sent to.
+    server_addr: SocketAddr,
+    link_conditioner: SharedLinkConditioner,
+}
+
+// Derived Clone would require T: Clone.
+impl<T: Transport> Clone for UdpPath<T> {
+    fn clone(&self) -> Self {
+        Self { socket: self.socket.clone(), server_addr: self.server_addr, link_conditioner: self.link_conditioner.clone() }
+    }
+}
+
+impl<T: Transport> UdpPath<T> {
+    /// Sends a packet to the server, through the link conditioner if one is set.
+    async fn send(&self, bytes: Vec<u8>) {
+        let delays = self.link_conditioner.lock().unwrap().as_mut().map(|conditioner| conditioner.outgoing(bytes.len()));
+        let Some(delays) = delays else {
+            let _ = self.socket.send_to(&bytes, self.server_addr).await;
+            return;
+        };
+        for delay in delays {
+            let (socket, bytes, server_addr) = (self.socket.clone(), bytes.clone(), self.server_addr);
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let _ = socket.send_to(&bytes, server_addr).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use common::transport::{Listener, MemoryTransport};
+    use common::transport::memory::{MemoryListener, MemoryStream};
+    use super::*;
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy { initial_delay: Duration::from_millis(1), max_delay: Duration::from_millis(1), jitter: 0.0, max_attempts: Some(100), ..Default::default() }
     }
-}
\ No newline at end of file
+
+    /// Answers a login like a server that knows only the `known` credentials and hands out `new` to everyone else.
+    async fn fake_login(listener: &MemoryListener, known: Option<Credentials>, new: Credentials) -> MemoryStream {
+        let (mut tcp, _) = listener.accept().await.unwrap();
+        loop {
+            let response = match ClientConnectionMessage::async_deserialize(&mut tcp).await.unwrap() {
+                ClientConnectionMessage::ConnectWithId { id, token } if known == Some(Credentials { user_id: id, token }) => ServerConnectionMessage::AcknowledgeId,
+                ClientConnectionMessage::ConnectWithId { .. } => ServerConnectionMessage::UnknownId,
+                ClientConnectionMessage::ConnectNew => ServerConnectionMessage::AssignUserId { id: new.user_id, token: new.token },
+                request => panic!("unexpected login request {request:?}"),
+            };
+            let retry = matches!(response, ServerConnectionMessage::UnknownId);
+            response.send(&mut tcp).await.unwrap();
+            if !retry {
+                return tcp;
+            }
+        }
+    }
+
+    fn stats(rtt_ms: u64) -> Option<ConnectionStats> {
+        Some(ConnectionStats { rtt: Duration::from_millis(rtt_ms), jitter: Duration::ZERO, packet_loss: 0.0 })
+    }
+
+    #[test]
+    fn reports_the_quality_when_it_crosses_a_threshold() {
+        let mut quality = None;
+        assert_eq!(quality_change(&mut quality, None), None);
+        assert_eq!(quality_change(&mut quality, stats(20)), Some(ConnectionQuality::Good));
+        assert_eq!(quality_change(&mut quality, stats(90)), None);
+        assert_eq!(quality_change(&mut quality, stats(150)), Some(ConnectionQuality::Fair));
+        assert_eq!(quality_change(&mut quality, stats(300)), Some(ConnectionQuality::Poor));
+        assert_eq!(quality_change(&mut quality, stats(260)), None);
+        assert_eq!(quality_change(&mut quality, stats(50)), Some(ConnectionQuality::Good));
+    }
+
+    /// The first answered probe tells the quality of the connection.
+    #[tokio::test]
+    async fn reports_the_quality_of_the_connection() {
+        let transport = MemoryTransport::default();
+        let addr = ServerAddr::from(SocketAddr::from(([127, 0, 0, 2], 4000)));
+        let credentials = Credentials { user_id: 1, token: 11 };
+        let (listener, datagram) = transport.bind(addr).await.unwrap();
+        let (handle, _tcp) = tokio::join!(
+            NetworkManager::launch(transport.clone(), addr, SharedLinkConditioner::default(), fast_retries(), None),
+            fake_login(&listener, None, credentials),
+        );
+        let mut handle = handle.unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            loop {
+                let (n, sender) = datagram.recv_from(&mut buf).await.unwrap();
+                if let Ok(ClientUdpMessage::Ping(sequence)) = ClientUdpMessage::deserialize(&mut &buf[..n]) {
+                    datagram.send_to(&ServerUdpMessage::Pong(sequence).serialize(), sender).await.unwrap();
+                }
+            }
+        });
+
+        assert!(matches!(handle.incoming_messages.recv().await, Some(ClientEvent::Connected { user_id: 1 })));
+        assert!(matches!(handle.incoming_messages.recv().await, Some(ClientEvent::QualityChanged(ConnectionQuality::Good))));
+    }
+
+    #[tokio::test]
+    async fn logs_in_anew_after_a_server_restart() {
+        let transport = MemoryTransport::default();
+        let addr = ServerAddr::from(SocketAddr::from(([127, 0, 0, 1], 4000)));
+        let id_file = std::env::temp_dir().join(format!("client-id-{:x}", rand::random::<u64>()));
+        let first = Credentials { user_id: 1, token: 11 };
+        let second = Credentials { user_id: 2, token: 22 };
+
+        let (listener, datagram) = transport.bind(addr).await.unwrap();
+        let (handle, tcp) = tokio::join!(
+            NetworkManager::launch(transport.clone(), addr, SharedLinkConditioner::default(), fast_retries(), Some(id_file.clone())),
+            fake_login(&listener, None, first),
+        );
+        let mut handle = handle.unwrap();
+        assert_eq!(handle.credentials, first);
+        assert_eq!(Credentials::load(&id_file), Some(first));
+
+        // The restarted server listens on the same address, but has forgotten every id.
+        drop((tcp, listener, datagram));
+        let (listener, _datagram) = transport.bind(addr).await.unwrap();
+        let _tcp = fake_login(&listener, None, second).await;
+
+        assert!(matches!(handle.incoming_messages.recv().await, Some(ClientEvent::Connected { user_id: 1 })));
+        assert!(matches!(handle.incoming_messages.recv().await, Some(ClientEvent::Disconnected { reason: DisconnectReason::Closed })));
+        assert!(matches!(handle.incoming_messages.recv().await, Some(ClientEvent::Reconnecting { attempt: 1 })));
+        assert!(matches!(handle.incoming_messages.recv().await, Some(ClientEvent::Reconnected { user_id: 2 })));
+        assert_eq!(Credentials::load(&id_file), Some(second));
+        std::fs::remove_file(id_file).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+/// How the client retries after losing the connection to the server. \
+/// The delay grows exponentially with every failed attempt, up to `max_delay`,
+/// and is shortened by a random amount so that clients do not all retry at the same time after a server restart.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Values below 1 are treated as 1, the delay never shrinks.
+    pub multiplier: f64,
+    /// The fraction of the delay that is randomized, between 0 and 1.
+    pub jitter: f64,
+    /// None retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before the given attempt, starting at 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.max(1.0).powi(attempt.min(i32::MAX as u32) as i32);
+        let delay = delay.min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0) * rand::random::<f64>();
+        // Only reached by a nonsensical policy, e.g. a jitter that is not a number.
+        Duration::try_from_secs_f64(delay * (1.0 - jitter)).unwrap_or(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn without_jitter() -> RetryPolicy {
+        RetryPolicy { jitter: 0.0, ..Default::default() }
+    }
+
+    #[test]
+    fn grows_exponentially_up_to_the_maximum() {
+        let policy = without_jitter();
+        assert_eq!(policy.delay(0), Duration::from_millis(250));
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+        assert_eq!(policy.delay(20), policy.max_delay);
+        assert_eq!(policy.delay(u32::MAX), policy.max_delay);
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = RetryPolicy { jitter: 0.5, ..Default::default() };
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1), "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn small_and_negative_multipliers_keep_the_delay() {
+        for multiplier in [0.5, 0.0, -2.0, f64::NAN] {
+            let policy = RetryPolicy { multiplier, ..without_jitter() };
+            assert_eq!(policy.delay(3), Duration::from_millis(250));
+        }
+    }
+
+    #[test]
+    fn invalid_jitter_falls_back_to_the_maximum() {
+        let policy = RetryPolicy { jitter: f64::NAN, ..Default::default() };
+        assert_eq!(policy.delay(0), policy.max_delay);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use serializeable::Serializeable;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+use common::message::{ClientMessage, ClientTcpMessage};
+use common::rpc::{CallId, Rpc, RpcCaller, RpcError, RpcResult};
+
+/// The calls that wait for their response. Shared with the network manager, which completes them.
+pub(crate) type PendingCalls = Arc<Mutex<HashMap<CallId, oneshot::Sender<RpcResult>>>>;
+
+/// Calls methods on the server. Cheap to clone, and calls complete without the client loop running.
+#[derive(Clone)]
+pub struct RpcClient {
+    next_id: Arc<AtomicU32>,
+    pending: PendingCalls,
+    outgoing_messages: UnboundedSender<ClientMessage>,
+    timeout: Duration,
+}
+
+impl RpcClient {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub(crate) fn new(pending: PendingCalls, outgoing_messages: UnboundedSender<ClientMessage>) -> Self {
+        Self { next_id: Default::default(), pending, outgoing_messages, timeout: Self::DEFAULT_TIMEOUT }
+    }
+
+    /// How long calls wait for their response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl RpcCaller for RpcClient {
+    /// Sends the request and waits for the response. \
+    /// Fails with `Disconnected` if the connection is lost in the meantime, and with `Timeout` if the server takes too long. \
+    /// The request is queued right away, before the returned future is first polled.
+    fn call<R: Rpc>(&self, request: R) -> impl Future<Output = Result<R::Response, RpcError>> + Send {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let message = ClientTcpMessage::Request { id, method: R::METHOD.to_string(), payload: request.serialize() };
+        let sent = self.outgoing_messages.send(ClientMessage::Tcp(message)).is_ok();
+        let (pending, timeout) = (self.pending.clone(), self.timeout);
+
+        async move {
+            if !sent {
+                pending.lock().unwrap().remove(&id);
+                return Err(RpcError::Disconnected);
+            }
+            let result = match tokio::time::timeout(timeout, receiver).await {
+                Ok(Ok(result)) => result,
+                // The network manager dropped the call when the connection was lost.
+                Ok(Err(_)) => return Err(RpcError::Disconnected),
+                Err(_) => {
+                    pending.lock().unwrap().remove(&id);
+                    return Err(RpcError::Timeout);
+                }
+            };
+            match result {
+                RpcResult::Ok(payload) => R::Response::deserialize(&mut &payload[..]).map_err(|_| RpcError::InvalidPayload),
+                RpcResult::Err(e) => Err(e),
+            }
+        }
+    }
+}
+
+/// Hands the result to the call it belongs to. Results of calls that have timed out are dropped.
+pub(crate) fn complete(pending: &PendingCalls, id: CallId, result: RpcResult) {
+    if let Some(call) = pending.lock().unwrap().remove(&id) {
+        let _ = call.send(result);
+    }
+}
+
+/// Fails every call that is still waiting with `Disconnected`.
+pub(crate) fn cancel_all(pending: &PendingCalls) {
+    pending.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use super::*;
+
+    #[derive(Serializeable, Debug)]
+    struct Double(u32);
+
+    impl Rpc for Double {
+        const METHOD: &'static str = "double";
+        type Response = u32;
+    }
+
+    fn client() -> (RpcClient, UnboundedReceiver<ClientMessage>) {
+        let (sender, receiver) = unbounded_channel();
+        (RpcClient::new(PendingCalls::default(), sender), receiver)
+    }
+
+    /// The id and the request of the next call that was sent.
+    async fn next_request(outgoing: &mut UnboundedReceiver<ClientMessage>) -> (CallId, Double) {
+        match outgoing.recv().await {
+            Some(ClientMessage::Tcp(ClientTcpMessage::Request { id, method, payload })) => {
+                assert_eq!(method, Double::METHOD);
+                (id, Double::deserialize(&mut &payload[..]).unwrap())
+            }
+            message => panic!("expected a request, got {message:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn matches_responses_with_their_calls() {
+        let (client, mut outgoing) = client();
+        let answer = async {
+            let requests = [next_request(&mut outgoing).await, next_request(&mut outgoing).await];
+            for (id, Double(n)) in requests.into_iter().rev() {
+                complete(&client.pending, id, RpcResult::Ok((n * 2).serialize()));
+            }
+        };
+        let (first, second, ()) = tokio::join!(client.call(Double(1)), client.call(Double(2)), answer);
+        assert_eq!(first, Ok(2));
+        assert_eq!(second, Ok(4));
+        assert!(client.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn passes_errors_on() {
+        let (client, mut outgoing) = client();
+        let answer = async {
+            let (id, _) = next_request(&mut outgoing).await;
+            complete(&client.pending, id, RpcResult::Err(RpcError::Failed("no".to_string())));
+        };
+        let (result, ()) = tokio::join!(client.call(Double(1)), answer);
+        assert_eq!(result, Err(RpcError::Failed("no".to_string())));
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let (client, _outgoing) = client();
+        let client = client.with_timeout(Duration::from_millis(10));
+        assert_eq!(client.call(Double(1)).await, Err(RpcError::Timeout));
+        assert!(client.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_calls_fail_with_disconnected() {
+        let (client, mut outgoing) = client();
+        let cancel = async {
+            next_request(&mut outgoing).await;
+            cancel_all(&client.pending);
+        };
+        let (result, ()) = tokio::join!(client.call(Double(1)), cancel);
+        assert_eq!(result, Err(RpcError::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn fails_without_a_network_manager() {
+        let (client, outgoing) = client();
+        drop(outgoing);
+        assert_eq!(client.call(Double(1)).await, Err(RpcError::Disconnected));
+        assert!(client.pending.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use serializeable::Serializeable;
+use common::InputSequence;
+use common::replication::{EntityId, Replicated, WorldState};
+
+type Simulation = Box<dyn Fn(&mut WorldState, &[u8]) + Send>;
+
+/// Applies the clients own inputs locally instead of waiting for the server. \
+/// Every authoritative snapshot rewinds the prediction to the servers state and replays the inputs it has not processed yet.
+#[derive(Default)]
+pub struct Prediction {
+    next_sequence: InputSequence,
+    /// Inputs that have been sent, but are not reflected in an authoritative state yet.
+    pending: VecDeque<(InputSequence, Vec<u8>)>,
+    predicted: WorldState,
+    simulation: Option<Simulation>,
+}
+
+impl Prediction {
+    /// Sets the function that advances the world by a single input. It has to match the simulation on the server.
+    pub fn set_simulation<I: Serializeable>(&mut self, simulate: impl Fn(&mut WorldState, I) + Send + 'static) {
+        self.simulation = Some(Box::new(move |world, data| {
+            if let Ok(input) = I::deserialize(&mut &data[..]) {
+                simulate(world, input);
+            }
+        }));
+    }
+
+    /// Applies an input to the predicted world and returns the sequence number it has to be sent with.
+    pub fn predict(&mut self, input: Vec<u8>) -> InputSequence {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        if let Some(simulate) = &self.simulation {
+            simulate(&mut self.predicted, &input);
+        }
+        self.pending.push_back((sequence, input));
+        sequence
+    }
+
+    /// Rewinds to the authoritative state and replays every input the server has not processed yet.
+    pub fn reconcile(&mut self, authoritative: &WorldState, last_processed: Option<InputSequence>) {
+        if let Some(last_processed) = last_processed {
+            while self.pending.front().is_some_and(|(sequence, _)| *sequence <= last_processed) {
+                self.pending.pop_front();
+            }
+        }
+        self.predicted = authoritative.clone();
+        if let Some(simulate) = &self.simulation {
+            for (_, input) in &self.pending {
+                simulate(&mut self.predicted, input);
+            }
+        }
+    }
+
+    pub fn predicted(&self) -> &WorldState {
+        &self.predicted
+    }
+
+    pub fn component<C: Replicated>(&self, entity: EntityId) -> Option<C> {
+        self.predicted.component(entity)
+    }
+
+    pub fn pending_inputs(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::avatar::{Move, Position};
+    use super::*;
+
+    const AVATAR: EntityId = 0;
+
+    fn avatar_at(position: Position) -> WorldState {
+        let mut world = WorldState::default();
+        world.insert_entity(AVATAR);
+        world.set_component(AVATAR, &position);
+        world
+    }
+
+    fn prediction() -> Prediction {
+        let mut prediction = Prediction::default();
+        prediction.set_simulation(|world: &mut WorldState, step: Move| {
+            if let Some(position) = world.component::<Position>(AVATAR) {
+                world.set_component(AVATAR, &position.moved(step));
+            }
+        });
+        prediction.reconcile(&avatar_at(Position::SPAWN), None);
+        prediction
+    }
+
+    #[test]
+    fn replays_the_inputs_the_server_has_not_processed() {
+        let mut prediction = prediction();
+        for step in [Move::Right, Move::Right, Move::Down] {
+            prediction.predict(step.serialize());
+        }
+        let Position { x, y } = Position::SPAWN;
+        assert_eq!(prediction.component::<Position>(AVATAR), Some(Position { x: x + 2, y: y + 1 }));
+
+        // The server processed the first input, and something else pushed the avatar up meanwhile.
+        prediction.reconcile(&avatar_at(Position { x: x + 1, y: y - 5 }), Some(0));
+        assert_eq!(prediction.pending_inputs(), 2);
+        assert_eq!(prediction.component::<Position>(AVATAR), Some(Position { x: x + 2, y: y - 4 }));
+
+        prediction.reconcile(&avatar_at(Position { x: x + 2, y: y - 4 }), Some(2));
+        assert_eq!(prediction.pending_inputs(), 0);
+        assert_eq!(prediction.component::<Position>(AVATAR), Some(Position { x: x + 2, y: y - 4 }));
+    }
+
+    #[test]
+    fn snapshots_without_processed_inputs_keep_every_input() {
+        let mut prediction = prediction();
+        assert_eq!(prediction.predict(Move::Left.serialize()), 0);
+        assert_eq!(prediction.predict(Move::Left.serialize()), 1);
+
+        prediction.reconcile(&avatar_at(Position::SPAWN), None);
+        assert_eq!(prediction.pending_inputs(), 2);
+        assert_eq!(prediction.component::<Position>(AVATAR), Some(Position { x: Position::SPAWN.x - 2, y: Position::SPAWN.y }));
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::{BTreeSet, VecDeque};
+use common::interest::Interest;
+use common::message::ClientTcpMessage;
+use common::pubsub::Publication;
+use crate::chat::ChatLine;
+use crate::client::Client;
+
+/// The topics the client subscribed to, which are subscribed again after a reconnection,
+/// and the publications that have not been polled yet.
+#[derive(Default)]
+pub struct PubSub {
+    pub(crate) subscriptions: BTreeSet<String>,
+    publications: VecDeque<Publication>,
+}
+
+impl PubSub {
+    /// Publications beyond this are dropped, oldest first, if nobody polls them.
+    pub const MAX_PUBLICATIONS: usize = 1000;
+
+    pub(crate) fn push(&mut self, publication: Publication) {
+        if self.publications.len() == Self::MAX_PUBLICATIONS {
+            self.publications.pop_front();
+        }
+        self.publications.push_back(publication);
+    }
+}
+
+impl Client {
+    /// Subscribes to a topic, or to every topic that matches a pattern, see `common::pubsub`.
+    pub fn subscribe(&mut self, pattern: impl Into<String>) {
+        let pattern = pattern.into();
+        if self.pubsub.subscriptions.insert(pattern.clone()) {
+            self.network_interface.send_tcp(ClientTcpMessage::Subscribe(Interest::Topic(pattern)));
+        }
+    }
+
+    pub fn unsubscribe(&mut self, pattern: &str) {
+        if self.pubsub.subscriptions.remove(pattern) {
+            self.network_interface.send_tcp(ClientTcpMessage::Unsubscribe(Interest::Topic(pattern.to_string())));
+        }
+    }
+
+    /// The server drops the publication unless it permits this client to publish to the topic.
+    pub fn publish(&mut self, topic: impl Into<String>, payload: Vec<u8>) {
+        self.network_interface.send_tcp(ClientTcpMessage::Publish { topic: topic.into(), payload });
+    }
+
+    /// The oldest publication that has not been polled yet.
+    pub fn poll_publication(&mut self) -> Option<Publication> {
+        self.pubsub.publications.pop_front()
+    }
+
+    /// Polls every publication and adds it to the chat, with the payload shown as text.
+    pub fn show_publications(&mut self) {
+        while let Some(Publication { topic, publisher, payload }) = self.poll_publication() {
+            let from = match publisher {
+                Some(id) => self.chat.users.get(&id).cloned().unwrap_or_else(|| format!("user{id}")),
+                None => "server".to_string(),
+            };
+            self.chat.push(ChatLine::Published { topic, from, text: String::from_utf8_lossy(&payload).into_owned() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_the_oldest_publications_beyond_the_cap() {
+        let mut pubsub = PubSub::default();
+        for i in 0..PubSub::MAX_PUBLICATIONS + 1 {
+            pubsub.push(Publication { topic: format!("topic/{i}"), publisher: None, payload: Vec::new() });
+        }
+        assert_eq!(pubsub.publications.len(), PubSub::MAX_PUBLICATIONS);
+        assert_eq!(pubsub.publications.front().unwrap().topic, "topic/1");
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, VecDeque};
+use serializeable::Serializeable;
+use common::replication::{EntityId, Replicated, Snapshot, SnapshotPart, Tick, WorldState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicationEvent {
+    Spawned(EntityId),
+    Updated(EntityId),
+    Despawned(EntityId),
+    /// The entity has become relevant to this client, see `common::interest`.
+    Entered(EntityId),
+    /// The entity is no longer relevant to this client. It disappears from the world with the next snapshot.
+    Left(EntityId),
+}
+
+/// The clients copy of the servers replicated entities, kept up to date by applying snapshots.
+#[derive(Default)]
+pub struct ClientWorld {
+    current: WorldState,
+    latest_tick: Option<Tick>,
+    /// Recently applied states, which later snapshots may be relative to.
+    received: VecDeque<(Tick, WorldState)>,
+    /// Unread events. The oldest ones are dropped once `MAX_EVENTS` is reached.
+    events: VecDeque<ReplicationEvent>,
+    /// Parts of snapshots too large for a single datagram, by tick, until all of them have arrived.
+    partial: BTreeMap<Tick, Vec<Option<Vec<u8>>>>,
+}
+
+impl ClientWorld {
+    const MAX_RECEIVED: usize = 64;
+    const MAX_EVENTS: usize = 1024;
+    /// Incomplete snapshots are given up once this many newer ones are being assembled.
+    const MAX_PARTIAL: usize = 8;
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.current.contains(entity)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.current.entities()
+    }
+
+    pub fn component<C: Replicated>(&self, entity: EntityId) -> Option<C> {
+        self.current.component(entity)
+    }
+
+    pub fn state(&self) -> &WorldState {
+        &self.current
+    }
+
+    /// A return value of None means that no more events have happened _yet_.
+    pub fn poll_event(&mut self) -> Option<ReplicationEvent> {
+        self.events.pop_front()
+    }
+
+    /// Forgets every received snapshot, so that the next full snapshot is applied no matter its tick. \
+    /// The current state is kept, so that the changes it brings still raise events.
+    pub fn reset_history(&mut self) {
+        self.latest_tick = None;
+        self.received.clear();
+    }
+
+    /// Raises the event for an entity the server started or stopped sending to this client.
+    pub fn interest_changed(&mut self, entity: EntityId, relevant: bool) {
+        self.push_event(if relevant { ReplicationEvent::Entered(entity) } else { ReplicationEvent::Left(entity) });
+    }
+
+    /// Collects the parts of a split snapshot and returns the snapshot once the last one has arrived. \
+    /// Parts of snapshots older than the latest applied one are dropped.
+    pub fn receive_part(&mut self, part: SnapshotPart) -> Option<Snapshot> {
+        if self.latest_tick.is_some_and(|latest| latest >= part.tick) || part.index >= part.count {
+            return None;
+        }
+        let parts = self.partial.entry(part.tick).or_insert_with(|| vec![None; part.count as usize]);
+        if parts.len() != part.count as usize {
+            return None;
+        }
+        parts[part.index as usize] = Some(part.data);
+        if parts.iter().all(Option::is_some) {
+            let bytes: Vec<u8> = self.partial.remove(&part.tick)?.into_iter().flatten().flatten().collect();
+            self.partial.retain(|tick, _| *tick > part.tick);
+            return Snapshot::deserialize(&mut &bytes[..]).ok();
+        }
+        while self.partial.len() > Self::MAX_PARTIAL {
+            self.partial.pop_first();
+        }
+        None
+    }
+
+    /// Applies a snapshot and returns the tick that should be acknowledged to the server. \
+    /// Snapshots that arrive out of order or whose baseline is no longer known are dropped.
+    pub fn apply_snapshot(&mut self, snapshot: Snapshot) -> Option<Tick> {
+        if self.latest_tick.is_some_and(|latest| latest >= snapshot.tick) {
+            return None;
+        }
+        let mut state = match snapshot.baseline {
+            Some(baseline) => self.received.iter().find(|(tick, _)| *tick == baseline)?.1.clone(),
+            None => WorldState::default(),
+        };
+        state.apply(&snapshot);
+
+        self.raise_events(&state);
+        self.current = state.clone();
+        self.latest_tick = Some(snapshot.tick);
+
+        // The server will never use a baseline older than the one it just used.
+        if let Some(baseline) = snapshot.baseline {
+            self.received.retain(|(tick, _)| *tick >= baseline);
+        }
+        self.received.push_back((snapshot.tick, state));
+        if self.received.len() > Self::MAX_RECEIVED {
+            self.received.pop_front();
+        }
+        Some(snapshot.tick)
+    }
+
+    fn raise_events(&mut self, new_state: &WorldState) {
+        for entity in new_state.entities() {
+            if !self.current.contains(entity) {
+                self.push_event(ReplicationEvent::Spawned(entity));
+            } else if !self.current.same_entity(new_state, entity) {
+                self.push_event(ReplicationEvent::Updated(entity));
+            }
+        }
+        let despawned: Vec<EntityId> = self.current.entities().filter(|entity| !new_state.contains(*entity)).collect();
+        for entity in despawned {
+            self.push_event(ReplicationEvent::Despawned(entity));
+        }
+    }
+
+    fn push_event(&mut self, event: ReplicationEvent) {
+        if self.events.len() >= Self::MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::avatar::Position;
+    use super::*;
+
+    /// A full snapshot with enough entities that it does not fit into a single part.
+    fn large_snapshot(tick: Tick) -> (Snapshot, Vec<SnapshotPart>) {
+        let mut world = WorldState::default();
+        for entity in 0..500 {
+            world.insert_entity(entity);
+            world.set_component(entity, &Position { x: entity as u16, y: tick as u16 });
+        }
+        let snapshot = world.delta_from(&WorldState::default(), tick, None);
+        let parts = SnapshotPart::split(tick, &snapshot.serialize());
+        assert!(parts.len() > 1);
+        (snapshot, parts)
+    }
+
+    #[test]
+    fn reassembles_parts_in_any_order() {
+        let mut world = ClientWorld::default();
+        let (snapshot, mut parts) = large_snapshot(1);
+        let first = parts.remove(0);
+        for part in parts.into_iter().rev() {
+            assert!(world.receive_part(part).is_none());
+        }
+        let reassembled = world.receive_part(first).expect("all parts arrived");
+        assert_eq!(reassembled.serialize(), snapshot.serialize());
+
+        assert_eq!(world.apply_snapshot(reassembled), Some(1));
+        assert_eq!(world.entities().count(), 500);
+        assert_eq!(world.component::<Position>(7), Some(Position { x: 7, y: 1 }));
+        assert!(matches!(world.poll_event(), Some(ReplicationEvent::Spawned(_))));
+    }
+
+    #[test]
+    fn drops_parts_of_outdated_snapshots() {
+        let mut world = ClientWorld::default();
+        let (_, old_parts) = large_snapshot(1);
+        let (_, new_parts) = large_snapshot(2);
+        let mut old_parts = old_parts.into_iter();
+        assert!(world.receive_part(old_parts.next().unwrap()).is_none());
+
+        let snapshot = new_parts.into_iter().find_map(|part| world.receive_part(part)).expect("all parts arrived");
+        assert_eq!(world.apply_snapshot(snapshot), Some(2));
+        assert!(old_parts.all(|part| world.receive_part(part).is_none()));
+        assert_eq!(world.component::<Position>(7), Some(Position { x: 7, y: 2 }));
+    }
+
+    #[test]
+    fn applies_deltas_against_the_acknowledged_baseline() {
+        let mut world = ClientWorld::default();
+        let mut server = WorldState::default();
+        server.insert_entity(0);
+        server.set_component(0, &Position::SPAWN);
+        server.insert_entity(1);
+        world.apply_snapshot(server.delta_from(&WorldState::default(), 1, None));
+        while world.poll_event().is_some() {}
+
+        let baseline = server.clone();
+        server.set_component(0, &Position { x: 1, y: 2 });
+        server.remove_entity(1);
+        assert_eq!(world.apply_snapshot(server.delta_from(&baseline, 3, Some(1))), Some(3));
+        assert_eq!(world.state(), &server);
+        let mut events = vec![world.poll_event().unwrap(), world.poll_event().unwrap()];
+        events.sort_by_key(|event| matches!(event, ReplicationEvent::Despawned(_)));
+        assert_eq!(events, vec![ReplicationEvent::Updated(0), ReplicationEvent::Despawned(1)]);
+
+        assert_eq!(world.apply_snapshot(server.delta_from(&baseline, 2, Some(1))), None, "older than the applied snapshot");
+        assert_eq!(world.apply_snapshot(server.delta_from(&baseline, 4, Some(0))), None, "unknown baseline");
+    }
+
+    #[test]
+    fn entities_that_left_disappear_with_the_next_snapshot() {
+        let mut world = ClientWorld::default();
+        let mut visible = WorldState::default();
+        visible.insert_entity(0);
+        visible.set_component(0, &Position::SPAWN);
+        world.apply_snapshot(visible.delta_from(&WorldState::default(), 1, None));
+        assert_eq!(world.poll_event(), Some(ReplicationEvent::Spawned(0)));
+
+        world.interest_changed(0, false);
+        assert_eq!(world.poll_event(), Some(ReplicationEvent::Left(0)));
+        assert!(world.contains(0));
+        world.apply_snapshot(WorldState::default().delta_from(&visible, 2, Some(1)));
+        assert_eq!(world.poll_event(), Some(ReplicationEvent::Despawned(0)));
+        assert!(!world.contains(0));
+    }
+}
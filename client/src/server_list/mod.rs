@@ -0,0 +1,13 @@
+use std::io;
+use tokio::net::ToSocketAddrs;
+use common::registry::{self, RegisteredServer, RegistryRequest, RegistryResponse, ServerFilter};
+
+/// Asks the registry for the live servers that match the filter.
+pub async fn query_registry<A: ToSocketAddrs>(registry: A, filter: ServerFilter) -> io::Result<Vec<RegisteredServer>> {
+    match registry::request(registry, RegistryRequest::Query(filter)).await? {
+        RegistryResponse::Servers(entries) => entries.into_iter()
+            .map(|entry| RegisteredServer::try_from(entry).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+            .collect(),
+        response => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected response from the registry: {response:?}"))),
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use common::replication::Tick;
+use common::time_sync::{ServerMicros, TimeResponse};
+use common::TICK_INTERVAL;
+
+struct Sample {
+    /// Server time minus local time, in microseconds.
+    offset: f64,
+    round_trip: f64,
+    local_time: f64,
+}
+
+/// Estimates the servers clock through an NTP-style exchange of time requests. \
+/// Of the recent samples, the one with the lowest round trip time is trusted the most, since it suffered the least from jitter.
+/// The drift between both clocks is estimated by fitting a line through the offsets of all samples.
+pub struct TimeSync {
+    epoch: Instant,
+    samples: VecDeque<Sample>,
+    last_request: Option<Instant>,
+    /// The servers last known tick and the server time it started at.
+    last_tick: Option<(Tick, ServerMicros)>,
+}
+
+impl Default for TimeSync {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            samples: VecDeque::new(),
+            last_request: None,
+            last_tick: None,
+        }
+    }
+}
+
+impl TimeSync {
+    const MAX_SAMPLES: usize = 16;
+    /// Until this many samples have been collected, requests are sent at the faster interval.
+    const INITIAL_SAMPLES: usize = 5;
+    const INITIAL_INTERVAL: Duration = Duration::from_millis(100);
+    const RESYNC_INTERVAL: Duration = Duration::from_secs(2);
+
+    fn local_micros(&self) -> u64 {
+        self.epoch.elapsed().as_micros() as u64
+    }
+
+    /// Returns the send time for a new time request, if one is due.
+    pub fn poll_request(&mut self) -> Option<u64> {
+        let interval = if self.samples.len() < Self::INITIAL_SAMPLES { Self::INITIAL_INTERVAL } else { Self::RESYNC_INTERVAL };
+        if self.last_request.is_some_and(|last| last.elapsed() < interval) {
+            return None;
+        }
+        self.last_request = Some(Instant::now());
+        Some(self.local_micros())
+    }
+
+    pub fn handle_response(&mut self, response: TimeResponse) {
+        let received_at = self.local_micros();
+        if received_at < response.client_send_time {
+            return;
+        }
+        let round_trip = (received_at - response.client_send_time) as f64
+            - (response.server_send_time as f64 - response.server_receive_time as f64);
+        let offset = ((response.server_receive_time as f64 - response.client_send_time as f64)
+            + (response.server_send_time as f64 - received_at as f64)) / 2.0;
+
+        self.samples.push_back(Sample { offset, round_trip: round_trip.max(0.0), local_time: received_at as f64 });
+        if self.samples.len() > Self::MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        if self.last_tick.is_none_or(|(tick, _)| tick <= response.tick) {
+            self.last_tick = Some((response.tick, response.tick_started_at));
+        }
+    }
+
+    /// The estimated current server time, measured since the server started. None until the first response arrived.
+    pub fn server_time(&self) -> Option<Duration> {
+        let now = self.local_micros() as f64;
+        let best = self.samples.iter().min_by(|a, b| a.round_trip.total_cmp(&b.round_trip))?;
+        let offset = best.offset + self.drift() * (now - best.local_time);
+        Some(Duration::from_micros((now + offset).max(0.0) as u64))
+    }
+
+    pub fn estimated_server_tick(&self) -> Option<Tick> {
+        let (tick, tick_started_at) = self.last_tick?;
+        let since_tick = (self.server_time()?.as_micros() as u64).saturating_sub(tick_started_at);
+        Some(tick + (since_tick / TICK_INTERVAL.as_micros() as u64) as Tick)
+    }
+
+    /// The best estimate of the round trip time to the server.
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        let best = self.samples.iter().map(|sample| sample.round_trip).min_by(f64::total_cmp)?;
+        Some(Duration::from_micros(best as u64))
+    }
+
+    /// How much the offset changes per microsecond of local time.
+    fn drift(&self) -> f64 {
+        let n = self.samples.len() as f64;
+        if n < 2.0 {
+            return 0.0;
+        }
+        let mean_time = self.samples.iter().map(|s| s.local_time).sum::<f64>() / n;
+        let mean_offset = self.samples.iter().map(|s| s.offset).sum::<f64>() / n;
+        let covariance: f64 = self.samples.iter().map(|s| (s.local_time - mean_time) * (s.offset - mean_offset)).sum();
+        let variance: f64 = self.samples.iter().map(|s| (s.local_time - mean_time).powi(2)).sum();
+        if variance == 0.0 { 0.0 } else { covariance / variance }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The server clock is this far ahead of the local one.
+    const OFFSET: u64 = 5_000_000;
+    const PROCESSING: u64 = 10_000;
+
+    fn time_sync() -> TimeSync {
+        TimeSync { epoch: Instant::now() - Duration::from_secs(10), ..Default::default() }
+    }
+
+    /// Answers a request that was sent `one_way` microseconds before the server received it and took as long to come back,
+    /// as if the response arrived just now.
+    fn respond(sync: &mut TimeSync, one_way: u64, tick: Tick, tick_started_at: ServerMicros) {
+        let client_send_time = sync.local_micros() - 2 * one_way - PROCESSING;
+        let server_receive_time = client_send_time + OFFSET + one_way;
+        let response = TimeResponse {
+            client_send_time,
+            server_receive_time,
+            server_send_time: server_receive_time + PROCESSING,
+            tick,
+            tick_started_at,
+        };
+        sync.handle_response(response);
+    }
+
+    fn assert_close(actual: u64, expected: u64) {
+        assert!(actual.abs_diff(expected) < 2_000, "{actual} is not close to {expected}");
+    }
+
+    #[test]
+    fn estimates_the_offset_and_round_trip_time() {
+        let mut sync = time_sync();
+        assert!(sync.server_time().is_none() && sync.round_trip_time().is_none());
+
+        respond(&mut sync, 15_000, 0, 0);
+        let expected = sync.local_micros() + OFFSET;
+        assert_close(sync.server_time().unwrap().as_micros() as u64, expected);
+        assert_close(sync.round_trip_time().unwrap().as_micros() as u64, 30_000);
+    }
+
+    #[test]
+    fn trusts_the_fastest_exchange() {
+        let mut sync = time_sync();
+        respond(&mut sync, 45_000, 0, 0);
+        respond(&mut sync, 15_000, 0, 0);
+        respond(&mut sync, 60_000, 0, 0);
+        assert_close(sync.round_trip_time().unwrap().as_micros() as u64, 30_000);
+        assert_close(sync.server_time().unwrap().as_micros() as u64, sync.local_micros() + OFFSET);
+    }
+
+    #[test]
+    fn extrapolates_the_server_tick() {
+        let mut sync = time_sync();
+        respond(&mut sync, 15_000, 0, 0);
+        let server_now = sync.server_time().unwrap().as_micros() as u64;
+        // Halfway between two ticks, so that the small errors of the estimate do not matter.
+        let tick_started_at = server_now - 3 * TICK_INTERVAL.as_micros() as u64 - TICK_INTERVAL.as_micros() as u64 / 2;
+        respond(&mut sync, 15_000, 100, tick_started_at);
+        assert_eq!(sync.estimated_server_tick(), Some(103));
+
+        respond(&mut sync, 15_000, 90, 0);
+        assert_eq!(sync.estimated_server_tick(), Some(103), "older ticks are ignored");
+    }
+}
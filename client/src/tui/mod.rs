@@ -0,0 +1,110 @@
+mod view;
+
+use std::io::{self, Stdout};
+use std::time::Duration;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use crate::chat::ChatLine;
+use crate::client::Client;
+use crate::console::Command;
+
+pub use view::{draw, View};
+
+/// A full screen chat ui, drawn on the alternate screen until it is dropped.
+pub struct Tui {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    keys: UnboundedReceiver<KeyEvent>,
+    input: String,
+    /// The latest lines the chat produced, at most `MAX_LINES`.
+    lines: Vec<ChatLine>,
+}
+
+impl Tui {
+    const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+    const MAX_LINES: usize = 1000;
+
+    pub fn new() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        let (sender, keys) = mpsc::unbounded_channel();
+        // A thread rather than a task, since the blocking read would keep the runtime from shutting down.
+        std::thread::spawn(move || Self::read_keys(sender));
+        Ok(Self { terminal, keys, input: String::new(), lines: Vec::new() })
+    }
+
+    /// Runs until the user quits.
+    pub async fn run(mut self, mut client: Client) -> io::Result<()> {
+        loop {
+            client.update();
+            client.show_publications();
+            while let Some(line) = client.chat.poll_line() {
+                self.push_line(line);
+            }
+            // Nothing reacts to the replicated entities yet, so their events are only drained.
+            while client.world.poll_event().is_some() {}
+            while let Ok(key) = self.keys.try_recv() {
+                match key.code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char(c) => self.input.push(c),
+                    KeyCode::Backspace => { self.input.pop(); }
+                    KeyCode::Enter if !self.input.trim().is_empty() => {
+                        match Command::parse(&std::mem::take(&mut self.input)) {
+                            Ok(Command::Quit) => return Ok(()),
+                            Ok(command) => client.execute(command),
+                            Err(e) => self.push_line(ChatLine::Notice(e)),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            let view = View {
+                lines: &self.lines,
+                chat: &client.chat,
+                input: &self.input,
+                connection: client.connection,
+                rtt: client.network_interface.connection_stats().map(|stats| stats.rtt),
+            };
+            self.terminal.draw(|frame| draw(frame, &view))?;
+            tokio::time::sleep(Self::FRAME_INTERVAL).await;
+        }
+    }
+
+    /// Forgets the oldest line once there are too many.
+    fn push_line(&mut self, line: ChatLine) {
+        if self.lines.len() >= Self::MAX_LINES {
+            self.lines.remove(0);
+        }
+        self.lines.push(line);
+    }
+
+    fn read_keys(sender: UnboundedSender<KeyEvent>) {
+        loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if sender.send(key).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Could not read from the terminal: {e}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+}
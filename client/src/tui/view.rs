@@ -0,0 +1,152 @@
+use std::time::Duration;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
+use ratatui::Frame;
+use crate::chat::{Chat, ChatLine};
+use crate::network_interface::ConnectionState;
+
+/// Everything the terminal ui shows. Rendering only depends on this, so it can be checked with ratatui's TestBackend.
+pub struct View<'a> {
+    /// The chat history, oldest first.
+    pub lines: &'a [ChatLine],
+    pub chat: &'a Chat,
+    pub input: &'a str,
+    pub connection: ConnectionState,
+    pub rtt: Option<Duration>,
+}
+
+pub fn draw(frame: &mut Frame, view: &View) {
+    let [main, input, status] = Layout::vertical([Constraint::Min(3), Constraint::Length(3), Constraint::Length(1)]).areas(frame.area());
+    let [rooms, messages, users] = Layout::horizontal([Constraint::Length(16), Constraint::Min(20), Constraint::Length(20)]).areas(main);
+
+    draw_rooms(frame, rooms, view.chat);
+    draw_messages(frame, messages, view.lines);
+    draw_users(frame, users, view.chat);
+    frame.render_widget(Paragraph::new(view.input).block(Block::default().borders(Borders::ALL).title("Input")), input);
+    // The cursor goes behind the typed text, inside the border. It stops at the border once the text is wider than the box.
+    let typed = u16::try_from(view.input.chars().count()).unwrap_or(u16::MAX);
+    let cursor_x = input.x.saturating_add(1).saturating_add(typed).min(input.right().saturating_sub(2));
+    frame.set_cursor_position((cursor_x, input.y + 1));
+    draw_status(frame, status, view);
+}
+
+fn draw_rooms(frame: &mut Frame, area: Rect, chat: &Chat) {
+    let items = chat.rooms.iter().map(|room| {
+        let style = match &chat.room {
+            Some(current) if current == room => Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+            _ => Style::default(),
+        };
+        ListItem::new(room.as_str()).style(style)
+    });
+    frame.render_widget(List::new(items).block(Block::default().borders(Borders::ALL).title("Rooms")), area);
+}
+
+fn draw_messages(frame: &mut Frame, area: Rect, lines: &[ChatLine]) {
+    // Only the newest lines that fit are shown.
+    let height = area.height.saturating_sub(2) as usize;
+    let shown = lines[lines.len().saturating_sub(height)..].iter().map(|line| match line {
+        ChatLine::Room { room, from, text } => Line::from(vec![
+            Span::styled(format!("[{room}] "), Style::default().fg(Color::DarkGray)),
+            Span::styled(format!("{from}: "), Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(text.as_str()),
+        ]),
+        ChatLine::Private { .. } => Line::styled(line.to_string(), Style::default().fg(Color::Magenta)),
+        ChatLine::Published { .. } => Line::styled(line.to_string(), Style::default().fg(Color::Cyan)),
+        ChatLine::Notice(text) => Line::styled(text.as_str(), Style::default().fg(Color::Yellow)),
+    });
+    frame.render_widget(Paragraph::new(shown.collect::<Vec<_>>()).block(Block::default().borders(Borders::ALL).title("Messages")), area);
+}
+
+fn draw_users(frame: &mut Frame, area: Rect, chat: &Chat) {
+    let items = chat.users.values().map(|name| ListItem::new(name.as_str()));
+    let title = format!("Users ({})", chat.users.len());
+    frame.render_widget(List::new(items).block(Block::default().borders(Borders::ALL).title(title)), area);
+}
+
+fn draw_status(frame: &mut Frame, area: Rect, view: &View) {
+    let (state, color) = match view.connection {
+        ConnectionState::Connected => ("Connected".to_string(), Color::Green),
+        ConnectionState::Disconnected => ("Disconnected".to_string(), Color::Red),
+        ConnectionState::Reconnecting { attempt } => (format!("Reconnecting (attempt {attempt})"), Color::Yellow),
+        ConnectionState::GaveUp => ("Gave up reconnecting".to_string(), Color::Red),
+        ConnectionState::Kicked => ("Kicked by the server".to_string(), Color::Red),
+    };
+    let rtt = match view.rtt {
+        Some(rtt) => format!("RTT {} ms", rtt.as_millis()),
+        None => "RTT -".to_string(),
+    };
+    let room = view.chat.room.as_deref().unwrap_or("no room");
+    let status = Line::from(vec![
+        Span::styled(state, Style::default().fg(color)),
+        Span::raw(format!(" | {rtt} | {room} | /name /join /msg /quit")),
+    ]);
+    frame.render_widget(Paragraph::new(status).style(Style::default().bg(Color::Black)), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::layout::Position;
+    use ratatui::Terminal;
+    use super::*;
+
+    const WIDTH: u16 = 80;
+    const HEIGHT: u16 = 12;
+
+    fn render(view: &View) -> (Vec<String>, Position) {
+        let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+        terminal.draw(|frame| draw(frame, view)).unwrap();
+        let rows = terminal.backend().buffer().content()
+            .chunks(WIDTH as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        (rows, terminal.get_cursor_position().unwrap())
+    }
+
+    fn chat() -> Chat {
+        let mut chat = Chat::default();
+        chat.users.insert(1, "alice".to_string());
+        chat.rooms.push("lobby".to_string());
+        chat.room = Some("lobby".to_string());
+        chat
+    }
+
+    fn view<'a>(lines: &'a [ChatLine], chat: &'a Chat, input: &'a str) -> View<'a> {
+        View { lines, chat, input, connection: ConnectionState::Connected, rtt: Some(Duration::from_millis(42)) }
+    }
+
+    #[test]
+    fn shows_the_chat_and_the_connection() {
+        let lines = [ChatLine::Room { room: "lobby".to_string(), from: "alice".to_string(), text: "hi".to_string() }];
+        let chat = chat();
+        let (rows, _) = render(&view(&lines, &chat, "typed"));
+        let screen = rows.join("\n");
+        for expected in ["Rooms", "lobby", "[lobby] alice: hi", "Users (1)", "alice", "typed", "Connected", "RTT 42 ms"] {
+            assert!(screen.contains(expected), "{expected:?} is missing from\n{screen}");
+        }
+    }
+
+    #[test]
+    fn shows_only_the_newest_lines_that_fit() {
+        let lines: Vec<ChatLine> = (0..20).map(|i| ChatLine::Notice(format!("notice {i}"))).collect();
+        let chat = chat();
+        let (rows, _) = render(&view(&lines, &chat, ""));
+        let screen = rows.join("\n");
+        // The messages box is 8 rows high, 6 of them inside the border.
+        assert!(screen.contains("notice 19") && screen.contains("notice 14"), "{screen}");
+        assert!(!screen.contains("notice 13"), "{screen}");
+    }
+
+    #[test]
+    fn places_the_cursor_behind_the_input() {
+        let chat = chat();
+        let (_, cursor) = render(&view(&[], &chat, "abc"));
+        assert_eq!(cursor, Position::new(4, HEIGHT - 3));
+
+        let long_input = "x".repeat(u16::MAX as usize + 10);
+        let (_, cursor) = render(&view(&[], &chat, &long_input));
+        assert_eq!(cursor, Position::new(WIDTH - 2, HEIGHT - 3));
+    }
+}
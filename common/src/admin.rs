@@ -0,0 +1,102 @@
+use std::io;
+use serializeable::Serializeable;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use crate::message::client_message::ClientConnectionMessage;
+use crate::message::send_message::TcpSendable;
+use crate::message::server_message::ServerConnectionMessage;
+use crate::UserId;
+
+/// What an admin key allows. Every role may do everything the roles before it may.
+#[derive(Serializeable, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdminRole {
+    /// Stats, user lists, kicks and bans.
+    Moderator,
+    /// Also reloading the admin keys and shutting the server down.
+    Owner,
+}
+
+impl AdminRole {
+    pub fn permits(&self, request: &AdminRequest) -> bool {
+        match request {
+            AdminRequest::Stats | AdminRequest::Users | AdminRequest::Kick { .. } | AdminRequest::Ban { .. } | AdminRequest::Unban(_) => true,
+            AdminRequest::ReloadAdminKeys | AdminRequest::Shutdown => *self >= AdminRole::Owner,
+        }
+    }
+}
+
+/// Sent over an admin session, see [`AdminSession`]. Every request is answered with exactly one response.
+#[derive(Serializeable, Debug, Clone)]
+pub enum AdminRequest {
+    Stats,
+    Users,
+    Kick { id: UserId, reason: Option<String> },
+    /// Kicks the user and refuses every later connection from its ip address.
+    Ban { id: UserId },
+    /// Takes the ip address as text.
+    Unban(String),
+    /// Reads the admin keys file of the server again. Nothing else is reloaded.
+    ReloadAdminKeys,
+    Shutdown,
+}
+
+#[derive(Serializeable, Debug, Clone)]
+pub enum AdminResponse {
+    Stats(ServerStats),
+    Users(Vec<UserSummary>),
+    /// The request succeeded, with a description of what happened.
+    Done(String),
+    Error(String),
+}
+
+#[derive(Serializeable, Debug, Clone)]
+pub struct ServerStats {
+    pub uptime_secs: u64,
+    pub tick: u32,
+    pub users: u32,
+    pub rooms: u32,
+    /// Banned user ids and ip addresses together.
+    pub bans: u32,
+}
+
+#[derive(Serializeable, Debug, Clone)]
+pub struct UserSummary {
+    pub id: UserId,
+    pub name: String,
+    pub address: String,
+    /// None until the first probe has been answered.
+    pub rtt_ms: Option<u32>,
+}
+
+/// A connection to the reliable address of a server, authenticated with an admin key instead of logged in as a user.
+pub struct AdminSession {
+    tcp: TcpStream,
+    role: AdminRole,
+}
+
+impl AdminSession {
+    /// Fails with `PermissionDenied` if the server does not know the key.
+    pub async fn connect<A: ToSocketAddrs>(addr: A, key: String) -> io::Result<Self> {
+        let mut tcp = TcpStream::connect(addr).await?;
+        ClientConnectionMessage::Admin { key }.send(&mut tcp).await?;
+        let response = ServerConnectionMessage::async_deserialize(&mut tcp).await.map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        match response {
+            ServerConnectionMessage::AdminAccepted(role) => Ok(Self { tcp, role }),
+            ServerConnectionMessage::AdminRejected => Err(io::Error::new(io::ErrorKind::PermissionDenied, "the server rejected the admin key")),
+            ServerConnectionMessage::Refused(reason) => Err(io::Error::new(io::ErrorKind::PermissionDenied, reason)),
+            response => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected response to the admin login: {response:?}"))),
+        }
+    }
+
+    /// What the server granted the key.
+    pub fn role(&self) -> AdminRole {
+        self.role
+    }
+
+    pub async fn request(&mut self, request: AdminRequest) -> io::Result<AdminResponse> {
+        request.send(&mut self.tcp).await?;
+        AdminResponse::async_deserialize(&mut self.tcp).await.map_err(|_| io::ErrorKind::InvalidData.into())
+    }
+}
+
+impl TcpSendable for AdminRequest {}
+impl TcpSendable for AdminResponse {}
@@ -0,0 +1,48 @@
+use serializeable::Serializeable;
+use crate::interest::RegionId;
+use crate::replication::{ComponentId, Replicated};
+
+/// Where an avatar stands. Every connected user has an avatar, replicated to the clients it is relevant to.
+#[derive(Serializeable, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Replicated for Position {
+    const COMPONENT_ID: ComponentId = 0;
+}
+
+impl Position {
+    /// Where new avatars appear, in the middle of the world.
+    pub const SPAWN: Position = Position { x: u16::MAX / 2, y: u16::MAX / 2 };
+    /// The width and height of the square regions the world is split into, see `crate::interest`.
+    pub const REGION_SIZE: u16 = 256;
+
+    /// Regions are numbered row by row, starting in the top left corner.
+    pub fn region(self) -> RegionId {
+        let regions_per_row = (u16::MAX / Self::REGION_SIZE) as RegionId + 1;
+        (self.y / Self::REGION_SIZE) as RegionId * regions_per_row + (self.x / Self::REGION_SIZE) as RegionId
+    }
+
+    /// Where a step leaves the avatar. Avatars stop at the edges of the world. \
+    /// The server and the predicting client both use this, so that their results agree.
+    pub fn moved(self, step: Move) -> Position {
+        let Position { x, y } = self;
+        match step {
+            Move::Up => Position { x, y: y.saturating_sub(1) },
+            Move::Down => Position { x, y: y.saturating_add(1) },
+            Move::Left => Position { x: x.saturating_sub(1), y },
+            Move::Right => Position { x: x.saturating_add(1), y },
+        }
+    }
+}
+
+/// The input clients send to move their avatar by one step.
+#[derive(Serializeable, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    Up,
+    Down,
+    Left,
+    Right,
+}
@@ -0,0 +1,106 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use serializeable::Serializeable;
+use crate::transport::ServerAddr;
+
+/// Servers with discovery enabled listen for queries on this port.
+pub const DISCOVERY_PORT: u16 = 25552;
+/// Queries are broadcast and also sent to this multicast group, for networks that filter broadcasts.
+pub const DISCOVERY_MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 42, 99);
+
+#[derive(Serializeable, Debug, Clone)]
+pub enum DiscoveryMessage {
+    /// Sent by clients looking for servers in the local network.
+    Query,
+    Info(ServerInfo),
+}
+
+/// What a server tells about itself when it is discovered.
+#[derive(Serializeable, Debug, Clone)]
+pub struct ServerInfo {
+    pub name: String,
+    pub version: String,
+    pub player_count: u32,
+    pub capacity: u32,
+    /// Every address the server listens on, as it was bound.
+    pub endpoints: Vec<ServerEndpoint>,
+}
+
+impl ServerInfo {
+    /// The addresses to connect to, as advertised. \
+    /// Endpoints bound to every interface, e.g. `0.0.0.0`, are reachable at `seen_at`, the ip the info came from.
+    /// They are left out if `seen_at` is of the other ip version, just like endpoints that cannot be parsed.
+    pub fn addresses(&self, seen_at: IpAddr) -> Vec<ServerAddr> {
+        let seen_at = seen_at.to_canonical();
+        let resolve = |addr: &str| {
+            let mut addr: SocketAddr = addr.parse().ok()?;
+            if addr.ip().is_unspecified() {
+                if addr.is_ipv4() != seen_at.is_ipv4() {
+                    return None;
+                }
+                addr.set_ip(seen_at);
+            }
+            Some(addr)
+        };
+        self.endpoints.iter()
+            .filter_map(|endpoint| Some(ServerAddr { reliable: resolve(&endpoint.reliable)?, unreliable: resolve(&endpoint.unreliable)? }))
+            .collect()
+    }
+}
+
+/// An address the server listens on. Addresses are sent as strings.
+#[derive(Serializeable, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerEndpoint {
+    pub reliable: String,
+    pub unreliable: String,
+}
+
+impl From<ServerAddr> for ServerEndpoint {
+    fn from(addr: ServerAddr) -> Self {
+        Self { reliable: addr.reliable.to_string(), unreliable: addr.unreliable.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(endpoints: &[(&str, &str)]) -> ServerInfo {
+        ServerInfo {
+            name: "test".to_string(),
+            version: "1".to_string(),
+            player_count: 0,
+            capacity: 8,
+            endpoints: endpoints.iter()
+                .map(|(reliable, unreliable)| ServerEndpoint { reliable: reliable.to_string(), unreliable: unreliable.to_string() })
+                .collect(),
+        }
+    }
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn specific_endpoints_are_kept() {
+        let info = info(&[("192.168.1.5:25550", "192.168.1.5:25551"), ("[::1]:25550", "[::1]:25550")]);
+        let addresses = info.addresses("10.0.0.1".parse().unwrap());
+        assert_eq!(addresses, vec![
+            ServerAddr { reliable: addr("192.168.1.5:25550"), unreliable: addr("192.168.1.5:25551") },
+            ServerAddr::from(addr("[::1]:25550")),
+        ]);
+    }
+
+    #[test]
+    fn unspecified_endpoints_take_the_ip_they_were_seen_at() {
+        let info = info(&[("0.0.0.0:25550", "0.0.0.0:25550"), ("[::]:25553", "[::]:25553")]);
+        assert_eq!(info.addresses("10.0.0.1".parse().unwrap()), vec![ServerAddr::from(addr("10.0.0.1:25550"))]);
+        assert_eq!(info.addresses("fe80::1".parse().unwrap()), vec![ServerAddr::from(addr("[fe80::1]:25553"))]);
+        assert_eq!(info.addresses("::ffff:10.0.0.1".parse().unwrap()), vec![ServerAddr::from(addr("10.0.0.1:25550"))]);
+    }
+
+    #[test]
+    fn invalid_endpoints_are_skipped() {
+        let info = info(&[("not an address", "0.0.0.0:25550")]);
+        assert!(info.addresses("10.0.0.1".parse().unwrap()).is_empty());
+    }
+}
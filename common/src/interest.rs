@@ -0,0 +1,16 @@
+use serializeable::Serializeable;
+use crate::replication::EntityId;
+
+/// Identifies an area of the world. How the world is split into regions is up to the application.
+pub type RegionId = u32;
+
+/// Something a client can subscribe to, so that the server sends it the related entities and broadcasts.
+#[derive(Serializeable, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Interest {
+    Region(RegionId),
+    /// A topic, or a pattern of topics, see `crate::pubsub`.
+    Topic(String),
+    Entity(EntityId),
+    /// A chat room. Unlike topics, rooms are only matched exactly.
+    Room(String),
+}
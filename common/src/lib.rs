@@ -1,9 +1,34 @@
 use std::io::Write;
-
+use std::time::Duration;
+
+pub mod admin;
+pub mod avatar;
+pub mod discovery;
+pub mod interest;
+pub mod link_conditioner;
+pub mod link_quality;
+pub mod logger;
 pub mod message;
+pub mod pubsub;
+pub mod registry;
+pub mod rendezvous;
+pub mod replication;
+pub mod rpc;
+mod service;
+pub mod services;
+pub mod time_sync;
+pub mod transport;
 pub type UserId = u64;
+/// Proves that a client logging in with an existing id is the one the id was assigned to. Keep it secret.
+pub type ReconnectToken = u64;
+/// Numbers the inputs a client sends, so the server can tell it which ones it has processed.
+pub type InputSequence = u32;
 
 pub const SERVER_ADDR: &str = "0.0.0.0:25550";
+/// Where the server accepts websocket clients, e.g. browser based dashboards.
+pub const WEBSOCKET_ADDR: &str = "0.0.0.0:25551";
+/// The time between two server ticks.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(10);
 
 
 
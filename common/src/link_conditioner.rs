@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How a simulated network treats the packets going in one direction.
+#[derive(Debug, Clone, Default)]
+pub struct LinkConditions {
+    pub latency: Duration,
+    /// Every packet is delayed by a random amount up to this on top of the latency.
+    pub jitter: Duration,
+    /// Probabilities between 0 and 1.
+    pub packet_loss: f64,
+    pub duplication: f64,
+    /// The probability of a packet being held back long enough for later packets to overtake it.
+    pub reordering: f64,
+    /// In bytes per second. Packets queue up behind each other once it is exceeded.
+    pub bandwidth: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConditions {
+    pub incoming: LinkConditions,
+    pub outgoing: LinkConditions,
+    /// Runs with the same seed and the same traffic drop and delay the same packets.
+    pub seed: u64,
+}
+
+/// Simulates a bad network for testing, by deciding for every packet whether and when it gets delivered.
+pub struct LinkConditioner {
+    incoming: ConditionedDirection,
+    outgoing: ConditionedDirection,
+}
+
+struct ConditionedDirection {
+    conditions: LinkConditions,
+    rng: StdRng,
+    busy_until: Option<Instant>,
+}
+
+impl LinkConditioner {
+    pub fn new(conditions: NetworkConditions) -> Self {
+        Self {
+            incoming: ConditionedDirection::new(conditions.incoming, conditions.seed),
+            outgoing: ConditionedDirection::new(conditions.outgoing, conditions.seed.wrapping_add(1)),
+        }
+    }
+
+    /// Returns the delay of every copy of an incoming packet that should be delivered. Empty if it got lost.
+    pub fn incoming(&mut self, len: usize) -> Vec<Duration> {
+        self.incoming.schedule(len)
+    }
+
+    /// Returns the delay of every copy of an outgoing packet that should be sent. Empty if it got lost.
+    pub fn outgoing(&mut self, len: usize) -> Vec<Duration> {
+        self.outgoing.schedule(len)
+    }
+}
+
+impl ConditionedDirection {
+    fn new(conditions: LinkConditions, seed: u64) -> Self {
+        Self { conditions, rng: StdRng::seed_from_u64(seed), busy_until: None }
+    }
+
+    fn schedule(&mut self, len: usize) -> Vec<Duration> {
+        if self.rng.gen_bool(self.conditions.packet_loss.clamp(0.0, 1.0)) {
+            return Vec::new();
+        }
+        let queueing = self.queueing_delay(len);
+        let copies = if self.rng.gen_bool(self.conditions.duplication.clamp(0.0, 1.0)) { 2 } else { 1 };
+        (0..copies).map(|_| queueing + self.transit_delay()).collect()
+    }
+
+    fn transit_delay(&mut self) -> Duration {
+        let jitter = self.conditions.jitter.mul_f64(self.rng.gen::<f64>());
+        let mut delay = self.conditions.latency + jitter;
+        if self.rng.gen_bool(self.conditions.reordering.clamp(0.0, 1.0)) {
+            delay += self.conditions.latency + self.conditions.jitter + Duration::from_millis(self.rng.gen_range(1..=20));
+        }
+        delay
+    }
+
+    /// Time the packet waits until the simulated link is free, when a bandwidth cap is set.
+    fn queueing_delay(&mut self, len: usize) -> Duration {
+        let Some(bandwidth) = self.conditions.bandwidth else { return Duration::ZERO };
+        let now = Instant::now();
+        let start = self.busy_until.filter(|busy_until| *busy_until > now).unwrap_or(now);
+        let busy_until = start + Duration::from_secs_f64(len as f64 / bandwidth.max(1) as f64);
+        self.busy_until = Some(busy_until);
+        busy_until - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conditioner(outgoing: LinkConditions) -> LinkConditioner {
+        LinkConditioner::new(NetworkConditions { outgoing, seed: 7, ..Default::default() })
+    }
+
+    #[test]
+    fn a_perfect_link_delivers_everything_at_once() {
+        let mut conditioner = conditioner(LinkConditions::default());
+        for _ in 0..100 {
+            assert_eq!(conditioner.outgoing(100), vec![Duration::ZERO]);
+            assert_eq!(conditioner.incoming(100), vec![Duration::ZERO]);
+        }
+    }
+
+    #[test]
+    fn drops_about_as_many_packets_as_configured() {
+        let mut lossy = conditioner(LinkConditions { packet_loss: 0.3, ..Default::default() });
+        let dropped = (0..10_000).filter(|_| lossy.outgoing(100).is_empty()).count();
+        assert!((2_500..3_500).contains(&dropped), "dropped {dropped} of 10000 packets");
+        assert_eq!(lossy.incoming(100).len(), 1, "only the outgoing direction is lossy");
+
+        let mut dead = conditioner(LinkConditions { packet_loss: 1.0, ..Default::default() });
+        assert!((0..100).all(|_| dead.outgoing(100).is_empty()));
+    }
+
+    #[test]
+    fn delays_by_the_latency_plus_some_jitter() {
+        let (latency, jitter) = (Duration::from_millis(50), Duration::from_millis(20));
+        let mut conditioner = conditioner(LinkConditions { latency, jitter, ..Default::default() });
+        let delays: Vec<Duration> = (0..1_000).flat_map(|_| conditioner.outgoing(100)).collect();
+        assert_eq!(delays.len(), 1_000);
+        assert!(delays.iter().all(|delay| (latency..=latency + jitter).contains(delay)));
+        assert!(delays.iter().any(|delay| *delay > latency + jitter / 2) && delays.iter().any(|delay| *delay < latency + jitter / 2));
+    }
+
+    #[test]
+    fn reordered_packets_are_held_back_past_the_others() {
+        let (latency, jitter) = (Duration::from_millis(50), Duration::from_millis(20));
+        let mut conditioner = conditioner(LinkConditions { latency, jitter, reordering: 1.0, ..Default::default() });
+        assert!((0..100).flat_map(|_| conditioner.outgoing(100)).all(|delay| delay > 2 * latency + jitter));
+    }
+
+    #[test]
+    fn duplicates_packets() {
+        let mut conditioner = conditioner(LinkConditions { duplication: 1.0, ..Default::default() });
+        assert_eq!(conditioner.outgoing(100).len(), 2);
+    }
+
+    #[test]
+    fn packets_queue_up_behind_a_bandwidth_cap() {
+        let mut conditioner = conditioner(LinkConditions { bandwidth: Some(1_000), ..Default::default() });
+        let first = conditioner.outgoing(100)[0];
+        let second = conditioner.outgoing(100)[0];
+        assert!(first <= Duration::from_millis(100));
+        assert!(second > Duration::from_millis(150), "the second packet waits for the first, but only waited {second:?}");
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_results() {
+        let conditions = LinkConditions { latency: Duration::from_millis(10), jitter: Duration::from_millis(10), packet_loss: 0.5, ..Default::default() };
+        let (mut a, mut b) = (conditioner(conditions.clone()), conditioner(conditions));
+        for _ in 0..100 {
+            assert_eq!(a.outgoing(100), b.outgoing(100));
+        }
+    }
+}
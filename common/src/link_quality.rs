@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionStats {
+    /// The smoothed round trip time.
+    pub rtt: Duration,
+    /// The smoothed deviation of the round trip time.
+    pub jitter: Duration,
+    /// The share of recent probes that went unanswered, between 0 and 1.
+    pub packet_loss: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConnectionQuality {
+    Good,
+    Fair,
+    Poor,
+}
+
+impl ConnectionQuality {
+    pub fn of(stats: &ConnectionStats) -> Self {
+        if stats.rtt > Duration::from_millis(250) || stats.jitter > Duration::from_millis(50) || stats.packet_loss > 0.1 {
+            ConnectionQuality::Poor
+        } else if stats.rtt > Duration::from_millis(100) || stats.jitter > Duration::from_millis(20) || stats.packet_loss > 0.02 {
+            ConnectionQuality::Fair
+        } else {
+            ConnectionQuality::Good
+        }
+    }
+}
+
+/// Measures a connection by sending numbered ping probes and matching the pongs that come back. \
+/// Round trip time and jitter are smoothed the way TCP does it (RFC 6298).
+#[derive(Default)]
+pub struct LinkMonitor {
+    next_sequence: u32,
+    in_flight: VecDeque<(u32, Instant)>,
+    /// Whether each of the most recently resolved probes got answered.
+    outcomes: VecDeque<bool>,
+    smoothed_rtt: Option<f64>,
+    rtt_variation: f64,
+}
+
+impl LinkMonitor {
+    pub const PROBE_INTERVAL: Duration = Duration::from_millis(500);
+    /// Probes that have not been answered after this long count as lost.
+    const LOSS_TIMEOUT: Duration = Duration::from_secs(2);
+    const LOSS_WINDOW: usize = 32;
+
+    /// Registers a new probe and returns the sequence number to send it with.
+    pub fn next_probe(&mut self) -> u32 {
+        self.expire_lost_probes();
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.in_flight.push_back((sequence, Instant::now()));
+        sequence
+    }
+
+    pub fn pong_received(&mut self, sequence: u32) {
+        let Some(position) = self.in_flight.iter().position(|(s, _)| *s == sequence) else { return };
+        let (_, sent_at) = self.in_flight.remove(position).unwrap();
+        let rtt = sent_at.elapsed().as_secs_f64();
+
+        match self.smoothed_rtt {
+            None => {
+                self.smoothed_rtt = Some(rtt);
+                self.rtt_variation = rtt / 2.0;
+            }
+            Some(smoothed) => {
+                self.rtt_variation = 0.75 * self.rtt_variation + 0.25 * (smoothed - rtt).abs();
+                self.smoothed_rtt = Some(0.875 * smoothed + 0.125 * rtt);
+            }
+        }
+        self.record_outcome(true);
+    }
+
+    /// None until the first pong has been received.
+    pub fn stats(&self) -> Option<ConnectionStats> {
+        let rtt = self.smoothed_rtt?;
+        let lost = self.outcomes.iter().filter(|answered| !**answered).count();
+        Some(ConnectionStats {
+            rtt: Duration::from_secs_f64(rtt),
+            jitter: Duration::from_secs_f64(self.rtt_variation),
+            packet_loss: lost as f32 / self.outcomes.len().max(1) as f32,
+        })
+    }
+
+    fn expire_lost_probes(&mut self) {
+        while self.in_flight.front().is_some_and(|(_, sent_at)| sent_at.elapsed() > Self::LOSS_TIMEOUT) {
+            self.in_flight.pop_front();
+            self.record_outcome(false);
+        }
+    }
+
+    fn record_outcome(&mut self, answered: bool) {
+        self.outcomes.push_back(answered);
+        if self.outcomes.len() > Self::LOSS_WINDOW {
+            self.outcomes.pop_front();
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Prints log records to stderr, so they do not mix with the output of the binaries, e.g. the admin console. \
+/// The level can be changed at any time with `log::set_max_level`.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Call once at startup.
+pub fn init(level: LevelFilter) {
+    log::set_logger(&StderrLogger).expect("the logger has already been set");
+    log::set_max_level(level);
+}
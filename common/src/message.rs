@@ -16,4 +16,24 @@ pub enum ServerMessage{
 pub enum ClientMessage{
     Tcp(ClientTcpMessage),
     Udp(ClientUdpMessage),
-}
\ No newline at end of file
+}
+
+/// Unwraps messages that only took the reliable channel as a fallback.
+impl From<ServerTcpMessage> for ServerMessage {
+    fn from(message: ServerTcpMessage) -> Self {
+        match message {
+            ServerTcpMessage::Unreliable(message) => ServerMessage::Udp(message),
+            message => ServerMessage::Tcp(message),
+        }
+    }
+}
+
+/// Unwraps messages that only took the reliable channel as a fallback.
+impl From<ClientTcpMessage> for ClientMessage {
+    fn from(message: ClientTcpMessage) -> Self {
+        match message {
+            ClientTcpMessage::Unreliable(message) => ClientMessage::Udp(message),
+            message => ClientMessage::Tcp(message),
+        }
+    }
+}
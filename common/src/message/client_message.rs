@@ -1,20 +1,48 @@
 use serializeable::Serializeable;
-use crate::UserId;
+use crate::{InputSequence, ReconnectToken, UserId};
+use crate::interest::Interest;
+use crate::replication::Tick;
+use crate::rpc::CallId;
 
 #[derive(Serializeable, Debug)]
 pub enum ClientTcpMessage {
     Text(String),
+    Subscribe(Interest),
+    Unsubscribe(Interest),
+    /// The name other users see in chat.
+    SetName(String),
+    /// Sent to every user subscribed to the topic.
+    Chat { topic: String, text: String },
+    /// Sent to the user with that name.
+    PrivateMessage { to: String, text: String },
+    /// Publishes the payload to the subscribers of the topic, if the server allows this client to, see `crate::pubsub`.
+    Publish { topic: String, payload: Vec<u8> },
+    /// Calls a method on the server, see `crate::rpc`. The payload is the serialized request.
+    Request { id: CallId, method: String, payload: Vec<u8> },
+    /// An unreliable message sent over the reliable channel, for clients that have no unreliable one.
+    Unreliable(ClientUdpMessage),
 }
 
 #[derive(Serializeable, Debug)]
 pub enum ClientConnectionMessage{
     ConnectNew,
-    ConnectWithId(UserId),
+    /// Reclaims an id, with the token it was assigned with.
+    ConnectWithId { id: UserId, token: ReconnectToken },
+    /// Opens an admin session instead of logging in as a user, see `crate::admin`.
+    Admin { key: String },
 }
 
 
-#[derive(Serializeable, Debug)]
+#[derive(Serializeable, Debug, Clone)]
 pub enum ClientUdpMessage {
     ChatMessage(String),
+    /// The client has received and applied the snapshot of this tick.
+    AcknowledgeSnapshot(Tick),
+    /// A serialized player input, see `InputSequence`.
+    Input(InputSequence, Vec<u8>),
+    /// Asks for the servers time. Contains the send time in the clients clock.
+    TimeRequest(u64),
+    Ping(u32),
+    Pong(u32),
 }
 
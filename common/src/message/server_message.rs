@@ -1,23 +1,67 @@
 use serializeable::Serializeable;
-use crate::UserId;
+use crate::{ReconnectToken, UserId};
+use crate::admin::AdminRole;
+use crate::pubsub::Publication;
+use crate::replication::{EntityId, Snapshot, SnapshotPart};
+use crate::rpc::{CallId, RpcResult};
+use crate::time_sync::{TimeResponse, Timestamp};
 
-#[derive(Serializeable, Debug)]
+#[derive(Serializeable, Debug, Clone)]
 pub enum ServerTcpMessage {
     Text(String),
     AssignUserId(UserId),
-    
+    Chat { topic: String, from: String, text: String },
+    PrivateMessage { from: String, text: String },
+    /// The user connected or changed its name.
+    UserOnline { id: UserId, name: String },
+    UserOffline(UserId),
+    /// The entity has become relevant to the client, through its subscriptions.
+    EntityEntered(EntityId),
+    /// The entity is no longer relevant to the client and has been left out of its snapshots.
+    EntityLeft(EntityId),
+    /// A message of a subscribed topic whose delivery mode is reliable.
+    Published(Publication),
+    /// Answers the request with the same id.
+    Response { id: CallId, result: RpcResult },
+    /// An unreliable message sent over the reliable channel, for clients that have no unreliable one.
+    Unreliable(ServerUdpMessage),
+    /// The first message to clients connected over a unix socket: where to bind the datagram socket,
+    /// see `crate::transport::unix`.
+    UnixDatagramPath(String),
+    /// Sent right before the server closes the connection, with the reason. The client must not reconnect.
+    Kicked(String),
 }
 /// Used when a client is connecting
 #[derive(Serializeable, Debug)]
 pub enum ServerConnectionMessage{
-    AssignUserId(UserId),
+    /// The token is needed to log in with the same id again.
+    AssignUserId { id: UserId, token: ReconnectToken },
     AcknowledgeId,
+    /// The id is still in use, e.g. because the server has not noticed yet that the previous connection is gone.
     IdAlreadyInUse,
+    AdminAccepted(AdminRole),
+    /// The connection is closed afterwards.
+    AdminRejected,
+    /// The address or the requested id is banned, with the reason. The connection is closed afterwards.
+    Refused(String),
+    /// The server does not know the id, or the token does not match it, e.g. because the server has been restarted
+    /// or the reservation ran out. Only a new id can be requested.
+    UnknownId,
 }
 
 
-#[derive(Serializeable, Debug)]
+#[derive(Serializeable, Debug, Clone)]
 pub enum ServerUdpMessage {
     ChatMessage(String),
+    Snapshot(Snapshot),
+    /// Sent instead of a snapshot that does not fit into a single datagram.
+    SnapshotPart(SnapshotPart),
+    TimeResponse(TimeResponse),
+    /// Wraps a message with the server time it was sent at.
+    Timestamped(Timestamp, Box<ServerUdpMessage>),
+    Ping(u32),
+    Pong(u32),
+    /// A message of a subscribed topic whose delivery mode is unreliable.
+    Published(Publication),
 }
 
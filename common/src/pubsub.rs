@@ -0,0 +1,78 @@
+use serializeable::Serializeable;
+use crate::UserId;
+
+/// Topics are split into segments by `/`, e.g. `game/positions/7`. \
+/// In a pattern, `*` matches exactly one segment and a trailing `**` matches all remaining segments, including none.
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    let mut topic_segments = topic.split('/');
+    for segment in pattern.split('/') {
+        if segment == "**" {
+            return true;
+        }
+        match topic_segments.next() {
+            Some(topic_segment) if segment == "*" || segment == topic_segment => {}
+            _ => return false,
+        }
+    }
+    topic_segments.next().is_none()
+}
+
+/// Patterns can be subscribed to, but not published to.
+pub fn is_pattern(topic: &str) -> bool {
+    topic.split('/').any(|segment| segment == "*" || segment == "**")
+}
+
+/// How the messages of a topic reach its subscribers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryMode {
+    /// In order and without losses, over the reliable channel.
+    #[default]
+    Reliable,
+    /// Over the unreliable channel, for frequent updates that are superseded by the next one anyway.
+    Unreliable,
+}
+
+/// A message published to a topic, as received by a subscriber.
+#[derive(Serializeable, Debug, Clone)]
+pub struct Publication {
+    pub topic: String,
+    /// None if the server itself published it.
+    pub publisher: Option<UserId>,
+    pub payload: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_topics() {
+        assert!(topic_matches("game/positions", "game/positions"));
+        assert!(!topic_matches("game/positions", "game/positions/7"));
+        assert!(!topic_matches("game/positions/7", "game/positions"));
+        assert!(!topic_matches("game/scores", "game/positions"));
+    }
+
+    #[test]
+    fn a_star_matches_one_segment() {
+        assert!(topic_matches("game/*/7", "game/positions/7"));
+        assert!(!topic_matches("game/*", "game/positions/7"));
+        assert!(!topic_matches("game/*", "game"));
+    }
+
+    #[test]
+    fn a_double_star_matches_the_rest() {
+        assert!(topic_matches("game/**", "game/positions/7"));
+        assert!(topic_matches("game/**", "game"));
+        assert!(topic_matches("**", "anything/at/all"));
+        assert!(!topic_matches("game/**", "news/today"));
+    }
+
+    #[test]
+    fn recognizes_patterns() {
+        assert!(is_pattern("game/*/7"));
+        assert!(is_pattern("game/**"));
+        assert!(!is_pattern("game/positions/7"));
+        assert!(!is_pattern("game/a*b"));
+    }
+}
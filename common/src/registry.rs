@@ -0,0 +1,100 @@
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use serializeable::Serializeable;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time::error::Elapsed;
+use tokio::time::timeout;
+use crate::discovery::ServerInfo;
+use crate::message::send_message::TcpSendable;
+use crate::transport::ServerAddr;
+
+/// Where the registry listens by default.
+pub const REGISTRY_ADDR: &str = "0.0.0.0:25560";
+/// How often registered servers send a heartbeat.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Servers whose last heartbeat is older than this are no longer listed.
+pub const REGISTRATION_EXPIRY: Duration = Duration::from_secs(30);
+/// How long connecting, sending the request and receiving the response may take each.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Every request is sent over its own tcp connection and answered with exactly one response.
+#[derive(Serializeable, Debug)]
+pub enum RegistryRequest {
+    /// Registers the server or refreshes its registration. It is identified by its address and endpoints.
+    Heartbeat(ServerInfo),
+    Query(ServerFilter),
+}
+
+#[derive(Serializeable, Debug)]
+pub enum RegistryResponse {
+    Registered,
+    /// The heartbeat was not accepted, e.g. because its ip already registered too many servers.
+    Rejected(String),
+    Servers(Vec<RegistryEntry>),
+}
+
+/// Every set condition has to hold for a server to be listed.
+#[derive(Serializeable, Debug, Clone, Default)]
+pub struct ServerFilter {
+    pub name_contains: Option<String>,
+    pub version: Option<String>,
+    pub not_full: bool,
+}
+
+impl ServerFilter {
+    pub fn matches(&self, info: &ServerInfo) -> bool {
+        self.name_contains.as_ref().is_none_or(|name| info.name.contains(name.as_str()))
+            && self.version.as_ref().is_none_or(|version| *version == info.version)
+            && (!self.not_full || info.player_count < info.capacity)
+    }
+}
+
+/// A listed server as it is sent. The ip is sent as a string, see `RegisteredServer`.
+#[derive(Serializeable, Debug, Clone)]
+pub struct RegistryEntry {
+    pub ip: String,
+    pub info: ServerInfo,
+}
+
+impl From<RegisteredServer> for RegistryEntry {
+    fn from(server: RegisteredServer) -> Self {
+        Self { ip: server.ip.to_string(), info: server.info }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegisteredServer {
+    /// The ip the registry saw the heartbeats coming from.
+    pub ip: IpAddr,
+    pub info: ServerInfo,
+}
+
+impl RegisteredServer {
+    /// The addresses to connect to, see `ServerInfo::addresses`.
+    pub fn addresses(&self) -> Vec<ServerAddr> {
+        self.info.addresses(self.ip)
+    }
+}
+
+impl TryFrom<RegistryEntry> for RegisteredServer {
+    type Error = std::net::AddrParseError;
+
+    fn try_from(entry: RegistryEntry) -> Result<Self, Self::Error> {
+        Ok(Self { ip: entry.ip.parse()?, info: entry.info })
+    }
+}
+
+impl TcpSendable for RegistryRequest {}
+impl TcpSendable for RegistryResponse {}
+
+/// Sends a single request to the registry and waits for its response. \
+/// Fails with `io::ErrorKind::TimedOut` if a step takes longer than `REQUEST_TIMEOUT`.
+pub async fn request<A: ToSocketAddrs>(registry: A, request: RegistryRequest) -> io::Result<RegistryResponse> {
+    let timed_out = |_: Elapsed| io::Error::from(io::ErrorKind::TimedOut);
+    let mut tcp = timeout(REQUEST_TIMEOUT, TcpStream::connect(registry)).await.map_err(timed_out)??;
+    timeout(REQUEST_TIMEOUT, request.send(&mut tcp)).await.map_err(timed_out)??;
+    timeout(REQUEST_TIMEOUT, RegistryResponse::async_deserialize(&mut tcp)).await
+        .map_err(timed_out)?
+        .map_err(|_| io::ErrorKind::InvalidData.into())
+}
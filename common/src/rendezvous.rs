@@ -0,0 +1,34 @@
+use std::time::Duration;
+use serializeable::Serializeable;
+
+/// Where the rendezvous listens by default.
+pub const RENDEZVOUS_ADDR: &str = "0.0.0.0:25570";
+/// Sessions without any registration, keepalive or relayed packet for this long are forgotten.
+pub const SESSION_EXPIRY: Duration = Duration::from_secs(60);
+/// How often relayed peers tell the rendezvous that they are still there.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Two peers that want to talk to each other agree on a session id beforehand, e.g. through the registry or a chat.
+pub type SessionId = u64;
+
+/// Addresses are sent as strings, e.g. "203.0.113.7:40000".
+#[derive(Serializeable, Debug, Clone)]
+pub enum RendezvousMessage {
+    /// Peer to rendezvous. Repeated until the other peer has registered as well. \
+    /// The local addresses let peers in the same network reach each other without going through their NAT.
+    Register { session: SessionId, local_addresses: Vec<String> },
+    /// Rendezvous to peer, once both peers of the session have registered.
+    PeerEndpoints { observed: String, local: Vec<String> },
+    /// Peer to peer. Sent to every known endpoint of the other peer at the same time from both sides,
+    /// which opens a path through both NATs.
+    Punch { session: SessionId },
+    /// Peer to peer. Answers a punch, confirming that the path works in both directions.
+    PunchAck { session: SessionId },
+    /// Peer to peer, once a direct path is open.
+    Data { session: SessionId, payload: Vec<u8> },
+    /// Peer to rendezvous and rendezvous to peer, if no direct path could be opened. \
+    /// The rendezvous forwards the payload to the other peer of the session.
+    Relay { session: SessionId, payload: Vec<u8> },
+    /// Peer to rendezvous, while relaying. Keeps the session from expiring when no payloads are sent.
+    KeepAlive { session: SessionId },
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use serializeable::Serializeable;
+use crate::InputSequence;
+
+pub type EntityId = u32;
+pub type ComponentId = u16;
+pub type Tick = u32;
+
+/// A component whose state is synchronized from the server to its clients. \
+/// Every replicated component type needs its own `COMPONENT_ID`.
+pub trait Replicated: Serializeable + Sized {
+    const COMPONENT_ID: ComponentId;
+}
+
+#[derive(Serializeable, Debug, Clone)]
+pub struct ComponentData {
+    pub component: ComponentId,
+    pub data: Vec<u8>,
+}
+
+/// The changes of a single entity relative to the baseline of its snapshot.
+#[derive(Serializeable, Debug, Clone)]
+pub struct EntityDelta {
+    pub entity: EntityId,
+    pub changed: Vec<ComponentData>,
+    pub removed: Vec<ComponentId>,
+}
+
+/// The state of all replicated entities at `tick`, delta encoded against an earlier snapshot.
+#[derive(Serializeable, Debug, Clone)]
+pub struct Snapshot {
+    pub tick: Tick,
+    /// The tick of the snapshot this one is relative to. None means it is relative to an empty world.
+    pub baseline: Option<Tick>,
+    pub entities: Vec<EntityDelta>,
+    pub despawned: Vec<EntityId>,
+    /// The last input of the receiving client that is reflected in this snapshot.
+    pub last_processed_input: Option<InputSequence>,
+}
+
+impl Snapshot {
+    /// Whether applying the snapshot changes nothing about the entities.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty() && self.despawned.is_empty()
+    }
+}
+
+/// A piece of a snapshot that is too large for a single datagram. \
+/// The pieces of a snapshot share its tick and are put back together in the order of their index.
+#[derive(Serializeable, Debug, Clone)]
+pub struct SnapshotPart {
+    pub tick: Tick,
+    pub index: u16,
+    pub count: u16,
+    pub data: Vec<u8>,
+}
+
+impl SnapshotPart {
+    /// Keeps a part together with its headers well below the usual MTU of 1500 bytes,
+    /// so that it is neither fragmented nor truncated by a 2048 byte receive buffer.
+    pub const MAX_SIZE: usize = 1024;
+
+    /// Splits a serialized snapshot into parts of at most `MAX_SIZE` bytes.
+    pub fn split(tick: Tick, bytes: &[u8]) -> Vec<SnapshotPart> {
+        let chunks = bytes.chunks(Self::MAX_SIZE);
+        let count = chunks.len() as u16;
+        chunks.enumerate()
+            .map(|(index, data)| SnapshotPart { tick, index: index as u16, count, data: data.to_vec() })
+            .collect()
+    }
+}
+
+/// The serialized components of every replicated entity.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct WorldState {
+    entities: HashMap<EntityId, HashMap<ComponentId, Vec<u8>>>,
+}
+
+impl WorldState {
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.entities.contains_key(&entity)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.entities.keys().copied()
+    }
+
+    pub fn insert_entity(&mut self, entity: EntityId) {
+        self.entities.entry(entity).or_default();
+    }
+
+    pub fn remove_entity(&mut self, entity: EntityId) -> bool {
+        self.entities.remove(&entity).is_some()
+    }
+
+    /// Returns None if the entity does not exist, does not have the component or the component could not be deserialized.
+    pub fn component<C: Replicated>(&self, entity: EntityId) -> Option<C> {
+        let data = self.entities.get(&entity)?.get(&C::COMPONENT_ID)?;
+        C::deserialize(&mut &data[..]).ok()
+    }
+
+    /// Returns false if the entity does not exist.
+    pub fn set_component<C: Replicated>(&mut self, entity: EntityId, component: &C) -> bool {
+        match self.entities.get_mut(&entity) {
+            Some(components) => {
+                components.insert(C::COMPONENT_ID, component.serialize());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove_component<C: Replicated>(&mut self, entity: EntityId) {
+        if let Some(components) = self.entities.get_mut(&entity) {
+            components.remove(&C::COMPONENT_ID);
+        }
+    }
+
+    /// Returns true if the entity exists in both states with identical components.
+    pub fn same_entity(&self, other: &WorldState, entity: EntityId) -> bool {
+        match (self.entities.get(&entity), other.entities.get(&entity)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Returns a copy that only contains the entities for which `keep` returns true.
+    pub fn filtered(&self, keep: impl Fn(EntityId) -> bool) -> WorldState {
+        let entities = self.entities.iter()
+            .filter(|(entity, _)| keep(**entity))
+            .map(|(entity, components)| (*entity, components.clone()))
+            .collect();
+        WorldState { entities }
+    }
+
+    /// Builds a snapshot that turns `baseline` into `self`.
+    pub fn delta_from(&self, baseline: &WorldState, tick: Tick, baseline_tick: Option<Tick>) -> Snapshot {
+        let mut entities = Vec::new();
+        for (entity, components) in &self.entities {
+            let old_components = baseline.entities.get(entity);
+            let changed: Vec<ComponentData> = components.iter()
+                .filter(|(id, data)| old_components.and_then(|old| old.get(id)) != Some(data))
+                .map(|(id, data)| ComponentData { component: *id, data: data.clone() })
+                .collect();
+            let removed: Vec<ComponentId> = old_components.into_iter()
+                .flat_map(|old| old.keys())
+                .filter(|id| !components.contains_key(id))
+                .copied()
+                .collect();
+
+            if old_components.is_none() || !changed.is_empty() || !removed.is_empty() {
+                entities.push(EntityDelta { entity: *entity, changed, removed });
+            }
+        }
+        let despawned = baseline.entities().filter(|entity| !self.contains(*entity)).collect();
+
+        Snapshot { tick, baseline: baseline_tick, entities, despawned, last_processed_input: None }
+    }
+
+    /// Applies the changes of a snapshot. `self` has to be the state of the snapshots baseline.
+    pub fn apply(&mut self, snapshot: &Snapshot) {
+        for entity in &snapshot.despawned {
+            self.entities.remove(entity);
+        }
+        for delta in &snapshot.entities {
+            let components = self.entities.entry(delta.entity).or_default();
+            for id in &delta.removed {
+                components.remove(id);
+            }
+            for component in &delta.changed {
+                components.insert(component.component, component.data.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::avatar::Position;
+    use super::*;
+
+    /// A second component type, so that removing one component can be told apart from despawning the entity.
+    #[derive(Serializeable, Debug, Clone, PartialEq)]
+    struct Name(String);
+
+    impl Replicated for Name {
+        const COMPONENT_ID: ComponentId = 1;
+    }
+
+    fn world(entities: &[(EntityId, Position)]) -> WorldState {
+        let mut world = WorldState::default();
+        for (entity, position) in entities {
+            world.insert_entity(*entity);
+            world.set_component(*entity, position);
+        }
+        world
+    }
+
+    #[test]
+    fn a_delta_turns_its_baseline_into_the_new_state() {
+        let mut baseline = world(&[(0, Position { x: 1, y: 1 }), (1, Position { x: 2, y: 2 }), (2, Position { x: 3, y: 3 })]);
+        baseline.set_component(1, &Name("named".to_string()));
+        let mut new = world(&[(0, Position { x: 1, y: 1 }), (1, Position { x: 2, y: 5 }), (3, Position { x: 4, y: 4 })]);
+
+        let snapshot = new.delta_from(&baseline, 8, Some(7));
+        assert_eq!((snapshot.tick, snapshot.baseline), (8, Some(7)));
+        assert_eq!(snapshot.despawned, vec![2]);
+        let mut changed: Vec<EntityId> = snapshot.entities.iter().map(|delta| delta.entity).collect();
+        changed.sort();
+        assert_eq!(changed, vec![1, 3], "unchanged entities are left out");
+        let delta = snapshot.entities.iter().find(|delta| delta.entity == 1).unwrap();
+        assert_eq!(delta.removed, vec![Name::COMPONENT_ID]);
+
+        let mut applied = baseline.clone();
+        applied.apply(&snapshot);
+        assert_eq!(applied, new);
+
+        new.remove_component::<Position>(3);
+        let snapshot = new.delta_from(&baseline, 9, Some(7));
+        let mut applied = baseline;
+        applied.apply(&snapshot);
+        assert!(applied.contains(3), "an entity without components still exists");
+        assert_eq!(applied.component::<Position>(3), None);
+    }
+
+    #[test]
+    fn a_delta_from_nothing_contains_everything() {
+        let new = world(&[(0, Position::SPAWN), (1, Position::SPAWN)]);
+        let snapshot = new.delta_from(&WorldState::default(), 0, None);
+        assert_eq!(snapshot.entities.len(), 2);
+        assert!(snapshot.despawned.is_empty());
+
+        let mut applied = WorldState::default();
+        applied.apply(&snapshot);
+        assert_eq!(applied, new);
+        assert!(new.delta_from(&new, 1, Some(0)).is_empty());
+    }
+
+    #[test]
+    fn split_parts_hold_the_whole_snapshot() {
+        let bytes: Vec<u8> = (0..SnapshotPart::MAX_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let parts = SnapshotPart::split(3, &bytes);
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| part.tick == 3 && part.count == 3 && part.data.len() <= SnapshotPart::MAX_SIZE));
+        assert_eq!(parts.into_iter().flat_map(|part| part.data).collect::<Vec<u8>>(), bytes);
+    }
+}
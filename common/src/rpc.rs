@@ -0,0 +1,56 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use serializeable::Serializeable;
+
+/// Numbers the calls of a client, so that every response can be matched with its request.
+pub type CallId = u32;
+
+/// A request that the server answers with a response. \
+/// Requests are sent as `ClientTcpMessage::Request` and answered with `ServerTcpMessage::Response`.
+pub trait Rpc: Serializeable + Send {
+    /// Selects the handler on the server, has to be unique.
+    const METHOD: &'static str;
+    type Response: Serializeable + Send;
+}
+
+/// The serialized response, or why there is none.
+#[derive(Serializeable, Debug, Clone)]
+pub enum RpcResult {
+    Ok(Vec<u8>),
+    Err(RpcError),
+}
+
+#[derive(Serializeable, Debug, Clone, PartialEq, Eq)]
+pub enum RpcError {
+    /// The server has no handler for the method.
+    UnknownMethod(String),
+    /// The request or the response could not be deserialized.
+    InvalidPayload,
+    /// The handler refused the request, with its reason.
+    Failed(String),
+    /// No response arrived in time. Only produced by the client.
+    Timeout,
+    /// The connection was lost before the response arrived. Only produced by the client.
+    Disconnected,
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::UnknownMethod(method) => write!(f, "the server does not know the method {method}"),
+            RpcError::InvalidPayload => write!(f, "the request or response could not be deserialized"),
+            RpcError::Failed(reason) => write!(f, "{reason}"),
+            RpcError::Timeout => write!(f, "the server did not answer in time"),
+            RpcError::Disconnected => write!(f, "the connection was lost before the server answered"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Sends requests to the server and waits for their responses. \
+/// Implemented by the client, used by the clients that `service!` generates.
+/// The calls are `Send`, so they can be spawned as tasks.
+pub trait RpcCaller {
+    fn call<R: Rpc>(&self, request: R) -> impl Future<Output = Result<R::Response, RpcError>> + Send;
+}
@@ -0,0 +1,161 @@
+/// Defines a service once for both sides. Expands to a module named after the service, containing:
+/// - `Request` and `Response`, with one variant per call, named after its request type. They are sent as a single rpc method.
+/// - `Handler`, the trait the server implements, with one method per call, and `Request::dispatch` which calls it.
+/// - `Client`, with one async method per call, on top of any [`RpcCaller`](crate::rpc::RpcCaller).
+///
+/// Adding or changing a call changes the trait and the client, so both sides fail to build until they are updated.
+///
+/// ```ignore
+/// service! {
+///     /// Queries about the users of the server.
+///     pub service directory {
+///         fn whois(Whois) -> UserId;
+///     }
+/// }
+/// ```
+/// Request types have to be plain identifiers, every type used has to be in scope where the macro is invoked.
+#[macro_export]
+macro_rules! service {
+    (
+        $(#[$meta:meta])*
+        $vis:vis service $name:ident {
+            $(
+                $(#[$call_meta:meta])*
+                fn $call:ident($request:ident) -> $response:ty;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis mod $name {
+            use super::*;
+
+            #[derive(serializeable::Serializeable, Debug, Clone)]
+            pub enum Request {
+                $($request($request),)*
+            }
+
+            #[derive(serializeable::Serializeable, Debug, Clone)]
+            pub enum Response {
+                $($request($response),)*
+            }
+
+            impl $crate::rpc::Rpc for Request {
+                const METHOD: &'static str = stringify!($name);
+                type Response = Response;
+            }
+
+            /// Implemented by the server, with one method per call.
+            pub trait Handler {
+                $(
+                    $(#[$call_meta])*
+                    fn $call(&mut self, caller: $crate::UserId, request: $request) -> Result<$response, $crate::rpc::RpcError>;
+                )*
+            }
+
+            impl Request {
+                /// Hands the request to the method of the handler that answers it.
+                pub fn dispatch<H: Handler + ?Sized>(self, handler: &mut H, caller: $crate::UserId) -> Result<Response, $crate::rpc::RpcError> {
+                    match self {
+                        $(Request::$request(request) => handler.$call(caller, request).map(Response::$request),)*
+                    }
+                }
+            }
+
+            /// Calls the service on the server.
+            pub struct Client<C> {
+                caller: C,
+            }
+
+            impl<C: $crate::rpc::RpcCaller> Client<C> {
+                pub fn new(caller: C) -> Self {
+                    Self { caller }
+                }
+
+                $(
+                    $(#[$call_meta])*
+                    pub async fn $call(&self, request: $request) -> Result<$response, $crate::rpc::RpcError> {
+                        match $crate::rpc::RpcCaller::call(&self.caller, Request::$request(request)).await? {
+                            Response::$request(response) => Ok(response),
+                            // A server that answers with the response of another call does not speak the same protocol.
+                            #[allow(unreachable_patterns)]
+                            _ => Err($crate::rpc::RpcError::InvalidPayload),
+                        }
+                    }
+                )*
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::sync::Mutex;
+    use serializeable::Serializeable;
+    use crate::rpc::{Rpc, RpcCaller, RpcError};
+    use crate::UserId;
+
+    #[derive(Serializeable, Debug, Clone)]
+    pub struct Add {
+        pub a: u32,
+        pub b: u32,
+    }
+
+    #[derive(Serializeable, Debug, Clone)]
+    pub struct Greet(pub String);
+
+    crate::service! {
+        pub service calculator {
+            fn add(Add) -> u32;
+            fn greet(Greet) -> String;
+        }
+    }
+
+    struct Calculator;
+
+    impl calculator::Handler for Calculator {
+        fn add(&mut self, _: UserId, Add { a, b }: Add) -> Result<u32, RpcError> {
+            a.checked_add(b).ok_or_else(|| RpcError::Failed("overflow".to_string()))
+        }
+
+        fn greet(&mut self, caller: UserId, Greet(name): Greet) -> Result<String, RpcError> {
+            Ok(format!("hello {name}, you are user {caller}"))
+        }
+    }
+
+    /// Serializes every call like the client does and dispatches it like the server does, as user 7.
+    struct Loopback(Mutex<Calculator>);
+
+    impl RpcCaller for Loopback {
+        fn call<R: Rpc>(&self, request: R) -> impl Future<Output = Result<R::Response, RpcError>> + Send {
+            assert_eq!(R::METHOD, calculator::Request::METHOD);
+            let payload = request.serialize();
+            let result = calculator::Request::deserialize(&mut &payload[..])
+                .map_err(|_| RpcError::InvalidPayload)
+                .and_then(|request| request.dispatch(&mut *self.0.lock().unwrap(), 7))
+                .and_then(|response| R::Response::deserialize(&mut &response.serialize()[..]).map_err(|_| RpcError::InvalidPayload));
+            async move { result }
+        }
+    }
+
+    fn client() -> calculator::Client<Loopback> {
+        calculator::Client::new(Loopback(Mutex::new(Calculator)))
+    }
+
+    #[tokio::test]
+    async fn calls_round_trip_through_dispatch() {
+        let client = client();
+        assert_eq!(client.add(Add { a: 2, b: 3 }).await, Ok(5));
+        assert_eq!(client.greet(Greet("alice".to_string())).await, Ok("hello alice, you are user 7".to_string()));
+    }
+
+    #[tokio::test]
+    async fn handler_errors_reach_the_caller() {
+        assert_eq!(client().add(Add { a: u32::MAX, b: 1 }).await, Err(RpcError::Failed("overflow".to_string())));
+    }
+
+    #[test]
+    fn every_call_uses_the_method_of_the_service() {
+        assert_eq!(calculator::Request::METHOD, "calculator");
+    }
+}
@@ -0,0 +1,29 @@
+use serializeable::Serializeable;
+use crate::service;
+use crate::UserId;
+
+/// Looks up the id of the user with that name.
+#[derive(Serializeable, Debug, Clone)]
+pub struct Whois {
+    pub name: String,
+}
+
+/// The topics and patterns the caller is subscribed to, see `crate::pubsub`.
+#[derive(Serializeable, Debug, Clone)]
+pub struct Subscriptions;
+
+/// How many users would receive a message published to the topic.
+#[derive(Serializeable, Debug, Clone)]
+pub struct SubscriberCount {
+    pub topic: String,
+}
+
+service! {
+    /// Queries about the users and topics of the server, answered by every server.
+    pub service directory {
+        fn whois(Whois) -> UserId;
+        /// The topics and patterns of the caller, sorted.
+        fn subscriptions(Subscriptions) -> Vec<String>;
+        fn subscriber_count(SubscriberCount) -> u32;
+    }
+}
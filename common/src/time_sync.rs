@@ -0,0 +1,23 @@
+use serializeable::Serializeable;
+use crate::replication::Tick;
+
+/// Microseconds since the server started.
+pub type ServerMicros = u64;
+
+/// The point in server time a message was sent at.
+#[derive(Serializeable, Debug, Clone, Copy)]
+pub struct Timestamp {
+    pub time: ServerMicros,
+    pub tick: Tick,
+}
+
+/// The servers answer to a `ClientUdpMessage::TimeRequest`. \
+/// The client send time is echoed in the clients own clock, everything else is in server time.
+#[derive(Serializeable, Debug, Clone, Copy)]
+pub struct TimeResponse {
+    pub client_send_time: u64,
+    pub server_receive_time: ServerMicros,
+    pub server_send_time: ServerMicros,
+    pub tick: Tick,
+    pub tick_started_at: ServerMicros,
+}
@@ -0,0 +1,76 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub use crate::transport::memory::MemoryTransport;
+pub use crate::transport::quic::QuicTransport;
+pub use crate::transport::tcp_udp::TcpUdpTransport;
+
+pub mod memory;
+pub mod quic;
+pub mod tcp_udp;
+pub mod unix;
+
+/// Selects the transport at runtime, e.g. from a config file.
+#[derive(Debug, Clone, Default)]
+pub enum TransportKind {
+    #[default]
+    TcpUdp,
+    /// The server generates a self signed certificate for `server_name` and stores it at `certificate_path`,
+    /// clients read it from there and trust nothing else.
+    Quic { server_name: String, certificate_path: PathBuf },
+}
+
+/// The reliable, ordered channel between client and server.
+pub trait ReliableStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    type ReadHalf: AsyncRead + Unpin + Send + 'static;
+    type WriteHalf: AsyncWrite + Unpin + Send + 'static;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+    fn into_split(self) -> (Self::ReadHalf, Self::WriteHalf);
+}
+
+/// Where a server listens for the reliable and the unreliable channel. Usually both are the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ServerAddr {
+    pub reliable: SocketAddr,
+    pub unreliable: SocketAddr,
+}
+
+impl From<SocketAddr> for ServerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self { reliable: addr, unreliable: addr }
+    }
+}
+
+/// The unreliable channel between client and server.
+pub trait DatagramSocket: Send + Sync + 'static {
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> impl Future<Output = io::Result<usize>> + Send;
+    fn recv_from(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<(usize, SocketAddr)>> + Send;
+}
+
+/// Accepts the reliable streams of new clients.
+pub trait Listener: Send + Sync + 'static {
+    type Stream: ReliableStream;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Stream, SocketAddr)>> + Send;
+}
+
+/// Everything client and server need to talk to each other. \
+/// A client connects with a stream and a datagram socket that share the same local address,
+/// which is how the server tells which client a datagram came from.
+pub trait Transport: Send + Sync + 'static {
+    type Stream: ReliableStream;
+    type Datagram: DatagramSocket;
+    type Listener: Listener<Stream = Self::Stream>;
+
+    /// Used by the server. Port 0 binds to an ephemeral port, ask the listener and socket for the actual addresses.
+    fn bind(&self, addr: ServerAddr) -> impl Future<Output = io::Result<(Self::Listener, Self::Datagram)>> + Send;
+    /// Used by the client.
+    fn connect(&self, addr: ServerAddr) -> impl Future<Output = io::Result<(Self::Stream, Self::Datagram)>> + Send;
+}
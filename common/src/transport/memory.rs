@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf, ReadHalf, WriteHalf};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use crate::transport::{DatagramSocket, Listener, ReliableStream, ServerAddr, Transport};
+
+/// An in-process network made of channels, so that whole client/server sessions can run in tests without real sockets. \
+/// Clones share the same network. Datagrams to addresses nobody is bound to are dropped silently, just like udp.
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+    network: Arc<Mutex<MemoryNetwork>>,
+}
+
+#[derive(Default)]
+struct MemoryNetwork {
+    next_port: u16,
+    listeners: HashMap<SocketAddr, UnboundedSender<MemoryStream>>,
+    datagram_endpoints: HashMap<SocketAddr, UnboundedSender<(Vec<u8>, SocketAddr)>>,
+}
+
+impl MemoryNetwork {
+    const FIRST_EPHEMERAL_PORT: u16 = 49152;
+    const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+    fn ephemeral_addr(&mut self, ip: IpAddr) -> SocketAddr {
+        loop {
+            let port = Self::FIRST_EPHEMERAL_PORT.wrapping_add(self.next_port);
+            self.next_port = self.next_port.wrapping_add(1);
+            let addr = SocketAddr::new(ip, port);
+            if !self.listeners.contains_key(&addr) && !self.datagram_endpoints.contains_key(&addr) {
+                return addr;
+            }
+        }
+    }
+
+    fn datagram_socket(&mut self, addr: SocketAddr, network: &Arc<Mutex<MemoryNetwork>>) -> MemoryDatagram {
+        let (sender, inbox) = unbounded_channel();
+        self.datagram_endpoints.insert(addr, sender);
+        MemoryDatagram { local_addr: addr, inbox: tokio::sync::Mutex::new(inbox), network: network.clone() }
+    }
+}
+
+impl Transport for MemoryTransport {
+    type Stream = MemoryStream;
+    type Datagram = MemoryDatagram;
+    type Listener = MemoryListener;
+
+    async fn bind(&self, addr: ServerAddr) -> io::Result<(MemoryListener, MemoryDatagram)> {
+        let mut network = self.network.lock().unwrap();
+        let reliable = if addr.reliable.port() == 0 { network.ephemeral_addr(addr.reliable.ip()) } else { addr.reliable };
+        if network.listeners.contains_key(&reliable) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        let unreliable = if addr.unreliable.port() == 0 { network.ephemeral_addr(addr.unreliable.ip()) } else { addr.unreliable };
+        if network.datagram_endpoints.contains_key(&unreliable) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        let (sender, incoming) = unbounded_channel();
+        network.listeners.insert(reliable, sender);
+        let listener = MemoryListener { local_addr: reliable, incoming: tokio::sync::Mutex::new(incoming), network: self.network.clone() };
+        Ok((listener, network.datagram_socket(unreliable, &self.network)))
+    }
+
+    async fn connect(&self, addr: ServerAddr) -> io::Result<(MemoryStream, MemoryDatagram)> {
+        let addr = addr.reliable;
+        let mut network = self.network.lock().unwrap();
+        let listener = network.listeners.get(&addr).ok_or(io::ErrorKind::ConnectionRefused)?.clone();
+        let local_addr = network.ephemeral_addr(Ipv4Addr::LOCALHOST.into());
+
+        let (client_end, server_end) = tokio::io::duplex(MemoryNetwork::STREAM_BUFFER_SIZE);
+        listener.send(MemoryStream { inner: server_end, local_addr: addr, peer_addr: local_addr })
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+
+        let stream = MemoryStream { inner: client_end, local_addr, peer_addr: addr };
+        Ok((stream, network.datagram_socket(local_addr, &self.network)))
+    }
+}
+
+pub struct MemoryStream {
+    inner: DuplexStream,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+}
+
+impl ReliableStream for MemoryStream {
+    type ReadHalf = ReadHalf<DuplexStream>;
+    type WriteHalf = WriteHalf<DuplexStream>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+
+    fn into_split(self) -> (Self::ReadHalf, Self::WriteHalf) {
+        tokio::io::split(self.inner)
+    }
+}
+
+impl AsyncRead for MemoryStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for MemoryStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+pub struct MemoryDatagram {
+    local_addr: SocketAddr,
+    inbox: tokio::sync::Mutex<UnboundedReceiver<(Vec<u8>, SocketAddr)>>,
+    network: Arc<Mutex<MemoryNetwork>>,
+}
+
+impl DatagramSocket for MemoryDatagram {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        if let Some(endpoint) = self.network.lock().unwrap().datagram_endpoints.get(&target) {
+            let _ = endpoint.send((buf.to_vec(), self.local_addr));
+        }
+        Ok(buf.len())
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (datagram, sender) = self.inbox.lock().await.recv().await.ok_or(io::ErrorKind::ConnectionAborted)?;
+        // Like udp, whatever does not fit into the buffer is lost.
+        let n = datagram.len().min(buf.len());
+        buf[..n].copy_from_slice(&datagram[..n]);
+        Ok((n, sender))
+    }
+}
+
+impl Drop for MemoryDatagram {
+    fn drop(&mut self) {
+        if let Ok(mut network) = self.network.lock() {
+            network.datagram_endpoints.remove(&self.local_addr);
+        }
+    }
+}
+
+pub struct MemoryListener {
+    local_addr: SocketAddr,
+    incoming: tokio::sync::Mutex<UnboundedReceiver<MemoryStream>>,
+    network: Arc<Mutex<MemoryNetwork>>,
+}
+
+impl Listener for MemoryListener {
+    type Stream = MemoryStream;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    async fn accept(&self) -> io::Result<(MemoryStream, SocketAddr)> {
+        let stream = self.incoming.lock().await.recv().await.ok_or(io::ErrorKind::ConnectionAborted)?;
+        let peer_addr = stream.peer_addr;
+        Ok((stream, peer_addr))
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        if let Ok(mut network) = self.network.lock() {
+            network.listeners.remove(&self.local_addr);
+        }
+    }
+}
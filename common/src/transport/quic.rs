@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream, ServerConfig};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use crate::transport::{DatagramSocket, Listener, ReliableStream, ServerAddr, Transport};
+
+/// Quic for both channels: the reliable messages go over one bidirectional stream per connection,
+/// the unreliable ones are sent as quic datagrams. \
+/// Datagrams are told apart by the remote address of their connection, just like udp packets.
+pub struct QuicTransport {
+    server_name: String,
+    certificate: CertificateDer<'static>,
+    /// Only known to the server.
+    private_key: Option<Vec<u8>>,
+}
+
+impl QuicTransport {
+    /// For the server. Generates a self signed certificate for `server_name`, which clients have to trust.
+    pub fn self_signed(server_name: &str) -> Self {
+        let certified = rcgen::generate_simple_self_signed(vec![server_name.to_string()]).expect("failed to generate a certificate");
+        Self {
+            server_name: server_name.to_string(),
+            certificate: certified.cert.der().clone(),
+            private_key: Some(certified.key_pair.serialize_der()),
+        }
+    }
+
+    /// For the client. Only servers presenting this certificate for `server_name` are accepted.
+    pub fn trusting(server_name: &str, certificate: Vec<u8>) -> Self {
+        Self { server_name: server_name.to_string(), certificate: CertificateDer::from(certificate), private_key: None }
+    }
+
+    /// The DER encoded certificate, to be handed to the clients.
+    pub fn certificate(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    fn server_config(&self) -> io::Result<ServerConfig> {
+        let private_key = self.private_key.clone().ok_or_else(|| io::Error::other("only a self signed quic transport can be bound"))?;
+        let private_key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(private_key));
+        ServerConfig::with_single_cert(vec![self.certificate.clone()], private_key).map_err(io::Error::other)
+    }
+
+    fn client_config(&self) -> io::Result<ClientConfig> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(self.certificate.clone()).map_err(io::Error::other)?;
+        ClientConfig::with_root_certificates(Arc::new(roots)).map_err(io::Error::other)
+    }
+}
+
+impl Transport for QuicTransport {
+    type Stream = QuicStream;
+    type Datagram = QuicDatagram;
+    type Listener = QuicListener;
+
+    /// Both channels share one endpoint, so they need to be bound to the same address.
+    async fn bind(&self, addr: ServerAddr) -> io::Result<(QuicListener, QuicDatagram)> {
+        if addr.reliable != addr.unreliable {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "quic carries both channels on the same address"));
+        }
+        let endpoint = Endpoint::server(self.server_config()?, addr.reliable)?;
+        let datagram = QuicDatagram::new(endpoint.clone());
+        let listener = QuicListener::new(endpoint, datagram.connections.clone());
+        Ok((listener, datagram))
+    }
+
+    async fn connect(&self, addr: ServerAddr) -> io::Result<(QuicStream, QuicDatagram)> {
+        if addr.reliable != addr.unreliable {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "quic carries both channels on the same address"));
+        }
+        let addr = addr.reliable;
+        let unspecified: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let endpoint = Endpoint::client(unspecified)?;
+        let connection = endpoint
+            .connect_with(self.client_config()?, addr, &self.server_name)
+            .map_err(io::Error::other)?
+            .await
+            .map_err(io::Error::other)?;
+        let (send, recv) = connection.open_bi().await.map_err(io::Error::other)?;
+
+        let stream = QuicStream { send, recv, local_addr: endpoint.local_addr()?, peer_addr: addr };
+        let datagram = QuicDatagram::new(endpoint);
+        datagram.connections.add(connection);
+        Ok((stream, datagram))
+    }
+}
+
+/// The open connections of an endpoint, by remote address.
+#[derive(Clone)]
+struct QuicConnections {
+    by_addr: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    incoming_datagrams: UnboundedSender<(Vec<u8>, SocketAddr)>,
+}
+
+impl QuicConnections {
+    /// Forwards the datagrams of the connection to the datagram socket until it is closed.
+    fn add(&self, connection: Connection) {
+        let addr = connection.remote_address();
+        self.by_addr.lock().unwrap().insert(addr, connection.clone());
+        let connections = self.clone();
+        tokio::spawn(async move {
+            while let Ok(datagram) = connection.read_datagram().await {
+                if connections.incoming_datagrams.send((datagram.to_vec(), addr)).is_err() {
+                    break;
+                }
+            }
+            connections.by_addr.lock().unwrap().remove(&addr);
+        });
+    }
+}
+
+pub struct QuicStream {
+    send: SendStream,
+    recv: RecvStream,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+}
+
+impl ReliableStream for QuicStream {
+    type ReadHalf = RecvStream;
+    type WriteHalf = SendStream;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+
+    fn into_split(self) -> (RecvStream, SendStream) {
+        (self.recv, self.send)
+    }
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.send).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_shutdown(cx)
+    }
+}
+
+pub struct QuicDatagram {
+    /// Also keeps the endpoint alive for as long as the socket is used.
+    endpoint: Endpoint,
+    connections: QuicConnections,
+    inbox: tokio::sync::Mutex<UnboundedReceiver<(Vec<u8>, SocketAddr)>>,
+}
+
+impl QuicDatagram {
+    fn new(endpoint: Endpoint) -> Self {
+        let (incoming_datagrams, inbox) = unbounded_channel();
+        Self {
+            endpoint,
+            connections: QuicConnections { by_addr: Default::default(), incoming_datagrams },
+            inbox: tokio::sync::Mutex::new(inbox),
+        }
+    }
+}
+
+impl DatagramSocket for QuicDatagram {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    /// Datagrams to addresses without an open connection are dropped silently, just like udp.
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        let connection = self.connections.by_addr.lock().unwrap().get(&target).cloned();
+        if let Some(connection) = connection {
+            connection.send_datagram(buf.to_vec().into()).map_err(io::Error::other)?;
+        }
+        Ok(buf.len())
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (datagram, sender) = self.inbox.lock().await.recv().await.ok_or(io::ErrorKind::ConnectionAborted)?;
+        let n = datagram.len().min(buf.len());
+        buf[..n].copy_from_slice(&datagram[..n]);
+        Ok((n, sender))
+    }
+}
+
+/// Sets up every incoming connection in its own task, so that a peer that stalls during the handshake
+/// or never opens its stream holds up nobody else.
+pub struct QuicListener {
+    endpoint: Endpoint,
+    accepted: tokio::sync::Mutex<UnboundedReceiver<(QuicStream, SocketAddr)>>,
+    accept_loop: JoinHandle<()>,
+}
+
+impl QuicListener {
+    /// How long a peer may take for the handshake and to open its stream.
+    const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn new(endpoint: Endpoint, connections: QuicConnections) -> Self {
+        let (sender, accepted) = unbounded_channel();
+        let accept_loop = tokio::spawn(Self::accept_connections(endpoint.clone(), connections, sender));
+        Self { endpoint, accepted: tokio::sync::Mutex::new(accepted), accept_loop }
+    }
+
+    /// Returns once the endpoint is closed.
+    async fn accept_connections(endpoint: Endpoint, connections: QuicConnections, accepted: UnboundedSender<(QuicStream, SocketAddr)>) {
+        while let Some(incoming) = endpoint.accept().await {
+            let (endpoint, connections, accepted) = (endpoint.clone(), connections.clone(), accepted.clone());
+            tokio::spawn(async move {
+                let setup = async {
+                    let connection = incoming.await.ok()?;
+                    let (send, recv) = connection.accept_bi().await.ok()?;
+                    Some((connection, send, recv))
+                };
+                // Connections that fail or time out are dropped, which closes them.
+                let Ok(Some((connection, send, recv))) = tokio::time::timeout(Self::SETUP_TIMEOUT, setup).await else { return };
+                let Ok(local_addr) = endpoint.local_addr() else { return };
+                let peer_addr = connection.remote_address();
+                connections.add(connection);
+                let _ = accepted.send((QuicStream { send, recv, local_addr, peer_addr }, peer_addr));
+            });
+        }
+    }
+}
+
+impl Drop for QuicListener {
+    fn drop(&mut self) {
+        self.accept_loop.abort();
+    }
+}
+
+impl Listener for QuicListener {
+    type Stream = QuicStream;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    /// Waits for the next connection whose handshake succeeds and that opens its stream, in time.
+    async fn accept(&self) -> io::Result<(QuicStream, SocketAddr)> {
+        self.accepted.lock().await.recv().await.ok_or_else(|| io::ErrorKind::ConnectionAborted.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use super::*;
+
+    #[tokio::test]
+    async fn localhost_round_trip() {
+        let server = QuicTransport::self_signed("localhost");
+        let client = QuicTransport::trusting("localhost", server.certificate().to_vec());
+        let (listener, server_datagram) = server.bind(ServerAddr::from(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))).await.unwrap();
+        let addr = ServerAddr::from(listener.local_addr().unwrap());
+
+        let accept = tokio::spawn(async move {
+            let (stream, peer_addr) = listener.accept().await.unwrap();
+            (listener, stream, peer_addr)
+        });
+        let (mut client_stream, client_datagram) = client.connect(addr).await.unwrap();
+        // The server only learns about the stream once something has been written to it.
+        client_stream.write_all(b"hello").await.unwrap();
+        let (_listener, mut server_stream, peer_addr) = accept.await.unwrap();
+
+        let mut buf = [0u8; 5];
+        server_stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        server_stream.write_all(b"world").await.unwrap();
+        client_stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"world");
+
+        let mut buf = [0u8; 16];
+        client_datagram.send_to(b"ping", addr.unreliable).await.unwrap();
+        let (n, sender) = server_datagram.recv_from(&mut buf).await.unwrap();
+        assert_eq!((&buf[..n], sender), (&b"ping"[..], peer_addr));
+        server_datagram.send_to(b"pong", peer_addr).await.unwrap();
+        let (n, sender) = client_datagram.recv_from(&mut buf).await.unwrap();
+        assert_eq!((&buf[..n], sender), (&b"pong"[..], addr.unreliable));
+    }
+
+    #[tokio::test]
+    async fn a_quiet_peer_does_not_hold_up_others() {
+        let server = QuicTransport::self_signed("localhost");
+        let client = QuicTransport::trusting("localhost", server.certificate().to_vec());
+        let (listener, _server_datagram) = server.bind(ServerAddr::from(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))).await.unwrap();
+        let addr = ServerAddr::from(listener.local_addr().unwrap());
+
+        // Never writes, so the server never sees its stream.
+        let (_quiet_stream, _quiet_datagram) = client.connect(addr).await.unwrap();
+        let (mut client_stream, client_datagram) = client.connect(addr).await.unwrap();
+        client_stream.write_all(b"hello").await.unwrap();
+
+        let (_, peer_addr) = tokio::time::timeout(Duration::from_secs(5), listener.accept()).await
+            .expect("the quiet peer blocked the listener")
+            .unwrap();
+        assert_eq!(peer_addr.port(), client_datagram.local_addr().unwrap().port());
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+use std::net::SocketAddr;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use crate::transport::{DatagramSocket, Listener, ReliableStream, ServerAddr, Transport};
+
+/// Tcp for the reliable and udp for the unreliable channel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TcpUdpTransport;
+
+impl Transport for TcpUdpTransport {
+    type Stream = TcpStream;
+    type Datagram = UdpSocket;
+    type Listener = TcpListener;
+
+    async fn bind(&self, addr: ServerAddr) -> io::Result<(TcpListener, UdpSocket)> {
+        let tcp = TcpListener::from_std(bind_socket(addr.reliable, Type::STREAM, Protocol::TCP)?.into())?;
+        let udp = UdpSocket::from_std(bind_socket(addr.unreliable, Type::DGRAM, Protocol::UDP)?.into())?;
+        Ok((tcp, udp))
+    }
+
+    async fn connect(&self, addr: ServerAddr) -> io::Result<(TcpStream, UdpSocket)> {
+        let tcp = TcpStream::connect(addr.reliable).await?;
+        let udp = UdpSocket::bind(tcp.local_addr()?).await?;
+        Ok((tcp, udp))
+    }
+}
+
+/// Ipv6 sockets only accept ipv6, so that they can be bound next to an ipv4 socket on the same port.
+fn bind_socket(addr: SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    if ty == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    if ty == Type::STREAM {
+        socket.listen(1024)?;
+    }
+    Ok(socket)
+}
+
+impl ReliableStream for TcpStream {
+    type ReadHalf = OwnedReadHalf;
+    type WriteHalf = OwnedWriteHalf;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn into_split(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        TcpStream::into_split(self)
+    }
+}
+
+impl DatagramSocket for UdpSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, buf, target).await
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf).await
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpListener::local_addr(self)
+    }
+
+    async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        TcpListener::accept(self).await
+    }
+}
@@ -0,0 +1,22 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Clients on the same host can connect to the unix stream socket of a server at some `path`. \
+/// After the usual login over that stream, the server tells each client where to bind its datagram socket
+/// with `ServerTcpMessage::UnixDatagramPath`, which is how the server tells which client a datagram came from.
+pub fn server_datagram_path(path: &Path) -> PathBuf {
+    with_suffix(path, "dgram")
+}
+
+/// Where a logged in client receives the unreliable messages, see `server_datagram_path`. \
+/// The nonce is drawn at random for every connection, so that no other process can predict the path and bind it first.
+pub fn client_datagram_path(path: &Path, nonce: u64) -> PathBuf {
+    with_suffix(path, &format!("{nonce:016x}"))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(".");
+    path.push(suffix);
+    path.into()
+}
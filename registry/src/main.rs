@@ -0,0 +1,116 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use log::LevelFilter;
+use serializeable::Serializeable;
+use tokio::net::{TcpListener, TcpStream};
+use common::logger;
+use common::message::send_message::TcpSendable;
+use common::registry::{RegistryRequest, RegistryResponse, REGISTRY_ADDR, REQUEST_TIMEOUT};
+use crate::registry::{Registry, MAX_SERVERS_PER_IP};
+
+mod registry;
+
+/// Lists the live servers for clients. Takes the address to listen on as an optional argument.
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    logger::init(LevelFilter::Info);
+    let addr = std::env::args().nth(1).unwrap_or_else(|| REGISTRY_ADDR.to_string());
+    let listener = TcpListener::bind(&addr).await?;
+    log::info!("Registry listening on {}", listener.local_addr()?);
+    serve(listener).await
+}
+
+/// Answers requests until the listener breaks down. Connections that do not send a complete request in time are closed.
+async fn serve(listener: TcpListener) -> std::io::Result<()> {
+    let registry = Arc::new(Mutex::new(Registry::default()));
+    loop {
+        let (tcp, peer_addr) = listener.accept().await?;
+        tokio::spawn(handle_request(tcp, peer_addr, registry.clone()));
+    }
+}
+
+async fn handle_request(mut tcp: TcpStream, peer_addr: SocketAddr, registry: Arc<Mutex<Registry>>) {
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, RegistryRequest::async_deserialize(&mut tcp)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(_)) => {
+            log::debug!("Received an invalid request from {peer_addr}");
+            return;
+        }
+        Err(_) => {
+            log::debug!("{peer_addr} did not send a request in time");
+            return;
+        }
+    };
+    let response = match request {
+        RegistryRequest::Heartbeat(info) => {
+            let name = info.name.clone();
+            if registry.lock().unwrap().heartbeat(peer_addr.ip(), info) {
+                log::debug!("Heartbeat of {name} from {peer_addr}");
+                RegistryResponse::Registered
+            } else {
+                log::warn!("Rejected the heartbeat of {name} from {peer_addr}, its ip has too many servers registered");
+                RegistryResponse::Rejected(format!("at most {MAX_SERVERS_PER_IP} servers can be registered from the same ip"))
+            }
+        }
+        RegistryRequest::Query(filter) => {
+            let servers = registry.lock().unwrap().query(&filter);
+            RegistryResponse::Servers(servers.into_iter().map(Into::into).collect())
+        }
+    };
+    let _ = tokio::time::timeout(REQUEST_TIMEOUT, response.send(&mut tcp)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use common::discovery::{ServerEndpoint, ServerInfo};
+    use common::registry::{self, RegisteredServer, ServerFilter};
+    use super::*;
+
+    fn info(name: &str) -> ServerInfo {
+        ServerInfo {
+            name: name.to_string(),
+            version: "1".to_string(),
+            player_count: 0,
+            capacity: 8,
+            endpoints: vec![ServerEndpoint { reliable: "0.0.0.0:25550".to_string(), unreliable: "0.0.0.0:25550".to_string() }],
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_registered_servers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+
+        let registered = registry::request(addr, RegistryRequest::Heartbeat(info("registered"))).await.unwrap();
+        assert!(matches!(registered, RegistryResponse::Registered));
+
+        let filter = ServerFilter { name_contains: Some("regis".to_string()), ..Default::default() };
+        let RegistryResponse::Servers(entries) = registry::request(addr, RegistryRequest::Query(filter)).await.unwrap() else {
+            panic!("expected the list of servers");
+        };
+        let servers: Vec<RegisteredServer> = entries.into_iter().map(|entry| entry.try_into().unwrap()).collect();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].info.name, "registered");
+        assert_eq!(servers[0].addresses(), vec![common::transport::ServerAddr::from(SocketAddr::from(([127, 0, 0, 1], 25550)))]);
+
+        let filter = ServerFilter { name_contains: Some("other".to_string()), ..Default::default() };
+        let RegistryResponse::Servers(entries) = registry::request(addr, RegistryRequest::Query(filter)).await.unwrap() else {
+            panic!("expected the list of servers");
+        };
+        assert!(entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_silent_registry_times_out() {
+        // Accepts the connection, but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _connection = listener.accept().await;
+            std::future::pending::<()>().await
+        });
+        let error = registry::request(addr, RegistryRequest::Query(ServerFilter::default())).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
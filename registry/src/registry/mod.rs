@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+use common::discovery::{ServerEndpoint, ServerInfo};
+use common::registry::{RegisteredServer, ServerFilter, REGISTRATION_EXPIRY};
+
+/// How many servers may be registered from the same ip at once.
+pub const MAX_SERVERS_PER_IP: usize = 16;
+
+struct Registration {
+    info: ServerInfo,
+    last_heartbeat: Instant,
+}
+
+/// Every live server, identified by the ip its heartbeats come from and the endpoints it listens on.
+#[derive(Default)]
+pub struct Registry {
+    servers: HashMap<(IpAddr, Vec<ServerEndpoint>), Registration>,
+}
+
+impl Registry {
+    /// Registers the server or refreshes its registration. \
+    /// Returns false if the ip already has `MAX_SERVERS_PER_IP` other live registrations.
+    pub fn heartbeat(&mut self, ip: IpAddr, info: ServerInfo) -> bool {
+        self.expire();
+        let key = (ip, info.endpoints.clone());
+        if !self.servers.contains_key(&key) && self.servers.keys().filter(|(registered, _)| *registered == ip).count() >= MAX_SERVERS_PER_IP {
+            return false;
+        }
+        self.servers.insert(key, Registration { info, last_heartbeat: Instant::now() });
+        true
+    }
+
+    /// Stale registrations are dropped before the filter is applied.
+    pub fn query(&mut self, filter: &ServerFilter) -> Vec<RegisteredServer> {
+        self.expire();
+        self.servers.iter()
+            .filter(|(_, registration)| filter.matches(&registration.info))
+            .map(|((ip, _), registration)| RegisteredServer { ip: *ip, info: registration.info.clone() })
+            .collect()
+    }
+
+    fn expire(&mut self) {
+        self.servers.retain(|_, registration| registration.last_heartbeat.elapsed() < REGISTRATION_EXPIRY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn info(name: &str, port: u16) -> ServerInfo {
+        ServerInfo {
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            player_count: 1,
+            capacity: 2,
+            endpoints: vec![ServerEndpoint { reliable: format!("0.0.0.0:{port}"), unreliable: format!("0.0.0.0:{port}") }],
+        }
+    }
+
+    #[test]
+    fn heartbeats_refresh_the_registration() {
+        let mut registry = Registry::default();
+        assert!(registry.heartbeat(IP, info("old name", 1000)));
+        assert!(registry.heartbeat(IP, info("new name", 1000)));
+        let servers = registry.query(&ServerFilter::default());
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].ip, IP);
+        assert_eq!(servers[0].info.name, "new name");
+    }
+
+    #[test]
+    fn query_applies_the_filter() {
+        let mut registry = Registry::default();
+        registry.heartbeat(IP, info("first", 1000));
+        registry.heartbeat(IP, info("second", 1001));
+        let filter = ServerFilter { name_contains: Some("sec".to_string()), ..Default::default() };
+        let servers = registry.query(&filter);
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].info.name, "second");
+    }
+
+    #[test]
+    fn stale_registrations_expire() {
+        let mut registry = Registry::default();
+        registry.heartbeat(IP, info("server", 1000));
+        for registration in registry.servers.values_mut() {
+            registration.last_heartbeat -= REGISTRATION_EXPIRY;
+        }
+        assert!(registry.query(&ServerFilter::default()).is_empty());
+    }
+
+    #[test]
+    fn caps_registrations_per_ip() {
+        let mut registry = Registry::default();
+        for port in 0..MAX_SERVERS_PER_IP as u16 {
+            assert!(registry.heartbeat(IP, info("server", port)));
+        }
+        assert!(!registry.heartbeat(IP, info("server", MAX_SERVERS_PER_IP as u16)));
+        assert!(registry.heartbeat(IP, info("server", 0)), "known servers can still refresh");
+        assert!(registry.heartbeat(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)), info("server", 0)));
+    }
+}
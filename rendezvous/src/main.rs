@@ -0,0 +1,46 @@
+use log::LevelFilter;
+use serializeable::Serializeable;
+use tokio::net::UdpSocket;
+use common::logger;
+use common::rendezvous::{RendezvousMessage, RENDEZVOUS_ADDR};
+use crate::sessions::Sessions;
+
+mod sessions;
+
+/// Introduces peers to each other for udp hole punching and relays their packets if punching fails. \
+/// Takes the address to listen on as an optional argument.
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    logger::init(LevelFilter::Info);
+    let addr = std::env::args().nth(1).unwrap_or_else(|| RENDEZVOUS_ADDR.to_string());
+    let socket = UdpSocket::bind(&addr).await?;
+    log::info!("Rendezvous listening on {}", socket.local_addr()?);
+
+    let mut sessions = Sessions::default();
+    let mut buf = [0u8; 2048];
+    loop {
+        let (n, sender) = socket.recv_from(&mut buf).await?;
+        let Ok(message) = RendezvousMessage::deserialize(&mut &buf[..n]) else {
+            log::debug!("Received an invalid packet from {sender}");
+            continue;
+        };
+        match message {
+            RendezvousMessage::Register { session, local_addresses } => {
+                let Some([first, second]) = sessions.register(session, sender, local_addresses) else { continue };
+                for ((target, _), (observed, local)) in [(&first, &second), (&second, &first)] {
+                    let endpoints = RendezvousMessage::PeerEndpoints { observed: observed.to_string(), local: local.clone() };
+                    let _ = socket.send_to(&endpoints.serialize(), target).await;
+                }
+            }
+            RendezvousMessage::Relay { session, payload } => {
+                let Some(target) = sessions.relay_target(session, sender) else { continue };
+                let _ = socket.send_to(&RendezvousMessage::Relay { session, payload }.serialize(), target).await;
+            }
+            RendezvousMessage::KeepAlive { session } => {
+                // Only refreshes the session.
+                sessions.relay_target(session, sender);
+            }
+            _ => log::debug!("Received an unexpected message from {sender}: {message:?}"),
+        }
+    }
+}
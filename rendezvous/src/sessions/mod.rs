@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+use common::rendezvous::{SessionId, SESSION_EXPIRY};
+
+struct Peer {
+    observed: SocketAddr,
+    local_addresses: Vec<String>,
+}
+
+struct Session {
+    peers: Vec<Peer>,
+    last_activity: Instant,
+}
+
+/// The peers of every session, as seen by the rendezvous.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: HashMap<SessionId, Session>,
+}
+
+impl Sessions {
+    /// Returns both peers with their observed address and local addresses once the session is complete. \
+    /// A session has room for two peers, further ones are ignored.
+    pub fn register(&mut self, session: SessionId, observed: SocketAddr, local_addresses: Vec<String>) -> Option<[(SocketAddr, Vec<String>); 2]> {
+        self.expire();
+        let session = self.sessions.entry(session).or_insert_with(|| Session { peers: Vec::new(), last_activity: Instant::now() });
+        session.last_activity = Instant::now();
+        match session.peers.iter_mut().find(|peer| peer.observed == observed) {
+            Some(peer) => peer.local_addresses = local_addresses,
+            None if session.peers.len() < 2 => session.peers.push(Peer { observed, local_addresses }),
+            None => return None,
+        }
+        match &session.peers[..] {
+            [first, second] => Some([
+                (first.observed, first.local_addresses.clone()),
+                (second.observed, second.local_addresses.clone()),
+            ]),
+            _ => None,
+        }
+    }
+
+    /// The other peer of the session, if the sender is part of it. Counts as activity of the session.
+    pub fn relay_target(&mut self, session: SessionId, sender: SocketAddr) -> Option<SocketAddr> {
+        let session = self.sessions.get_mut(&session)?;
+        if !session.peers.iter().any(|peer| peer.observed == sender) {
+            return None;
+        }
+        session.last_activity = Instant::now();
+        session.peers.iter().find(|peer| peer.observed != sender).map(|peer| peer.observed)
+    }
+
+    fn expire(&mut self) {
+        self.sessions.retain(|_, session| session.last_activity.elapsed() < SESSION_EXPIRY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "192.0.2.1:1000";
+    const SECOND: &str = "198.51.100.1:2000";
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn introduces_both_peers() {
+        let mut sessions = Sessions::default();
+        assert!(sessions.register(1, addr(FIRST), vec!["10.0.0.1:1000".to_string()]).is_none());
+        let [first, second] = sessions.register(1, addr(SECOND), Vec::new()).unwrap();
+        assert_eq!(first, (addr(FIRST), vec!["10.0.0.1:1000".to_string()]));
+        assert_eq!(second, (addr(SECOND), Vec::new()));
+    }
+
+    #[test]
+    fn ignores_a_third_peer() {
+        let mut sessions = Sessions::default();
+        sessions.register(1, addr(FIRST), Vec::new());
+        sessions.register(1, addr(SECOND), Vec::new());
+        assert!(sessions.register(1, addr("203.0.113.1:3000"), Vec::new()).is_none());
+        assert_eq!(sessions.relay_target(1, addr("203.0.113.1:3000")), None);
+    }
+
+    #[test]
+    fn relays_only_between_the_peers() {
+        let mut sessions = Sessions::default();
+        sessions.register(1, addr(FIRST), Vec::new());
+        assert_eq!(sessions.relay_target(1, addr(FIRST)), None, "the other peer is not known yet");
+        sessions.register(1, addr(SECOND), Vec::new());
+        assert_eq!(sessions.relay_target(1, addr(FIRST)), Some(addr(SECOND)));
+        assert_eq!(sessions.relay_target(1, addr(SECOND)), Some(addr(FIRST)));
+        assert_eq!(sessions.relay_target(2, addr(FIRST)), None);
+    }
+
+    #[test]
+    fn forgets_idle_sessions() {
+        let mut sessions = Sessions::default();
+        sessions.register(1, addr(FIRST), Vec::new());
+        sessions.sessions.get_mut(&1).unwrap().last_activity -= SESSION_EXPIRY;
+        sessions.register(2, addr(SECOND), Vec::new());
+        assert!(!sessions.sessions.contains_key(&1));
+    }
+}
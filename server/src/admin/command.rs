@@ -0,0 +1,126 @@
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::time::Duration;
+use log::LevelFilter;
+use common::link_conditioner::{LinkConditions, NetworkConditions};
+use common::UserId;
+
+/// What an admin can ask the running server to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AdminCommand {
+    Help,
+    /// Lists the connected users with their addresses and connection stats.
+    Users,
+    Kick { id: UserId, reason: Option<String> },
+    /// Kicks the user and refuses every later connection from its ip address.
+    Ban { id: UserId },
+    Unban(IpAddr),
+    /// Sends the text to every connected user.
+    Announce(String),
+    /// Publishes the text to the topic as the server.
+    Publish { topic: String, text: String },
+    /// Lists the chat rooms that have members.
+    Rooms,
+    LogLevel(LevelFilter),
+    /// Reads the admin keys file again. The topics and the network config stay as they were started with.
+    ReloadKeys,
+    /// Simulates a bad network for a single user, or for everyone when `user` is None. A `link` of None ends the simulation.
+    Netsim { user: Option<UserId>, link: Option<SimulatedLink> },
+    Shutdown,
+}
+
+/// The bad network an admin simulates with the netsim command, the same in both directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SimulatedLink {
+    pub(crate) latency_ms: u64,
+    pub(crate) jitter_ms: u64,
+    pub(crate) loss_percent: u8,
+}
+
+impl SimulatedLink {
+    pub(crate) fn conditions(self, seed: u64) -> NetworkConditions {
+        let link = LinkConditions {
+            latency: Duration::from_millis(self.latency_ms),
+            jitter: Duration::from_millis(self.jitter_ms),
+            packet_loss: self.loss_percent as f64 / 100.0,
+            ..Default::default()
+        };
+        NetworkConditions { incoming: link.clone(), outgoing: link, seed }
+    }
+}
+
+impl Display for SimulatedLink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ms latency, {} ms jitter and {}% loss", self.latency_ms, self.jitter_ms, self.loss_percent)
+    }
+}
+
+impl AdminCommand {
+    pub(crate) const HELP: &str = "\
+users                   list the connected users
+kick <id> [reason]      disconnect a user
+ban <id>                disconnect a user and refuse its ip address
+unban <ip>              accept the ip address again
+announce <text>         send a message to every user
+publish <topic> <text>  publish a message to the subscribers of a topic
+rooms                   list the chat rooms and their member counts
+loglevel <level>        one of off, error, warn, info, debug, trace
+reloadkeys              read the admin keys file again
+netsim <id|all> <latency ms> [jitter ms] [loss %]
+                        simulate a bad network in both directions, for one user or everyone
+netsim <id|all> off     stop simulating
+shutdown                stop the server";
+
+    /// Parses a line, or explains why it is not a valid command.
+    pub(crate) fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, arguments) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let arguments = arguments.trim();
+        let user_id = |argument: &str| argument.parse::<UserId>().map_err(|_| format!("{argument} is not a user id"));
+        match name {
+            "help" => Ok(Self::Help),
+            "users" => Ok(Self::Users),
+            "kick" => {
+                let (id, reason) = arguments.split_once(char::is_whitespace).unwrap_or((arguments, ""));
+                let reason = Some(reason.trim().to_string()).filter(|reason| !reason.is_empty());
+                Ok(Self::Kick { id: user_id(id)?, reason })
+            }
+            "ban" => Ok(Self::Ban { id: user_id(arguments)? }),
+            "unban" => arguments.parse().map(Self::Unban).map_err(|_| format!("{arguments} is not an ip address")),
+            "announce" if !arguments.is_empty() => Ok(Self::Announce(arguments.to_string())),
+            "announce" => Err("Usage: announce <text>".to_string()),
+            "publish" => match arguments.split_once(char::is_whitespace) {
+                Some((topic, text)) if !text.trim().is_empty() => Ok(Self::Publish { topic: topic.to_string(), text: text.trim().to_string() }),
+                _ => Err("Usage: publish <topic> <text>".to_string()),
+            },
+            "rooms" => Ok(Self::Rooms),
+            "loglevel" => arguments.parse().map(Self::LogLevel).map_err(|_| format!("{arguments} is not a log level")),
+            "reloadkeys" => Ok(Self::ReloadKeys),
+            "netsim" => {
+                let mut arguments = arguments.split_whitespace();
+                let user = match arguments.next() {
+                    Some("all") => None,
+                    Some(id) => Some(user_id(id)?),
+                    None => return Err("Usage: netsim <id|all> <latency ms> [jitter ms] [loss %]".to_string()),
+                };
+                let arguments: Vec<&str> = arguments.collect();
+                let link = match arguments[..] {
+                    ["off"] => None,
+                    [latency, ref rest @ ..] if rest.len() <= 2 => {
+                        let number = |argument: &str| argument.parse::<u64>().map_err(|_| format!("{argument} is not a number"));
+                        let jitter_ms = rest.first().map(|jitter| number(jitter)).transpose()?.unwrap_or(0);
+                        let loss_percent = rest.get(1).map(|loss| number(loss)).transpose()?.unwrap_or(0);
+                        if loss_percent > 100 {
+                            return Err(format!("{loss_percent}% is not a probability"));
+                        }
+                        Some(SimulatedLink { latency_ms: number(latency)?, jitter_ms, loss_percent: loss_percent as u8 })
+                    }
+                    _ => return Err("Usage: netsim <id|all> <latency ms> [jitter ms] [loss %]".to_string()),
+                };
+                Ok(Self::Netsim { user, link })
+            }
+            "shutdown" => Ok(Self::Shutdown),
+            _ => Err(format!("Unknown command {name}, try help")),
+        }
+    }
+}
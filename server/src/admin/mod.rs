@@ -0,0 +1,220 @@
+mod command;
+
+use std::fmt::Write;
+use std::io::BufRead;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::sync::mpsc::error::TryRecvError;
+use common::admin::{AdminRequest, AdminResponse, ServerStats, UserSummary};
+use common::message::ServerTcpMessage;
+use common::pubsub::is_pattern;
+use common::UserId;
+use crate::server::Server;
+
+pub(crate) use command::AdminCommand;
+
+/// Reads admin commands from stdin on its own thread, so that the tick loop never waits for input.
+pub(crate) struct AdminConsole {
+    lines: UnboundedReceiver<String>,
+}
+
+impl AdminConsole {
+    pub(crate) fn stdin() -> Self {
+        let (sender, lines) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { return };
+                if !line.trim().is_empty() && sender.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { lines }
+    }
+
+    /// The next line that has been entered. None if there is none _yet_, or stdin has been closed.
+    fn poll_line(&mut self) -> Option<String> {
+        match self.lines.try_recv() {
+            Ok(line) => Some(line),
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => None,
+        }
+    }
+}
+
+impl Server {
+    /// Executes the commands entered since the last tick and prints their output.
+    pub(crate) fn handle_admin_commands(&mut self) {
+        while let Some(line) = self.admin_console.as_mut().and_then(AdminConsole::poll_line) {
+            match AdminCommand::parse(&line).and_then(|command| self.execute_admin(command)) {
+                Ok(output) => println!("{output}"),
+                Err(e) => println!("{e}"),
+            }
+        }
+    }
+
+    /// Answers the requests of remote admins received since the last tick.
+    pub(crate) fn handle_admin_calls(&mut self) {
+        while let Some(call) = self.network_interface.incoming_admin_call() {
+            let response = self.answer_admin(call.request.clone());
+            call.respond(response);
+        }
+    }
+
+    fn answer_admin(&mut self, request: AdminRequest) -> AdminResponse {
+        let command = match request {
+            AdminRequest::Stats => return AdminResponse::Stats(self.stats()),
+            AdminRequest::Users => return AdminResponse::Users(self.user_summaries()),
+            AdminRequest::Kick { id, reason } => AdminCommand::Kick { id, reason },
+            AdminRequest::Ban { id } => AdminCommand::Ban { id },
+            AdminRequest::Unban(ip) => match ip.parse() {
+                Ok(ip) => AdminCommand::Unban(ip),
+                Err(_) => return AdminResponse::Error(format!("{ip} is not an ip address")),
+            },
+            AdminRequest::ReloadAdminKeys => AdminCommand::ReloadKeys,
+            AdminRequest::Shutdown => AdminCommand::Shutdown,
+        };
+        match self.execute_admin(command) {
+            Ok(outcome) => AdminResponse::Done(outcome),
+            Err(e) => AdminResponse::Error(e),
+        }
+    }
+
+    /// Carries out the command and describes the outcome, or why it failed.
+    pub(crate) fn execute_admin(&mut self, command: AdminCommand) -> Result<String, String> {
+        Ok(match command {
+            AdminCommand::Help => AdminCommand::HELP.to_string(),
+            AdminCommand::Users => self.describe_users(),
+            AdminCommand::Kick { id, reason } => {
+                let Some(name) = self.state.users.get(&id).map(|user| user.name.clone()) else { return Err(format!("There is no user with id {id}")) };
+                let reason = match reason {
+                    Some(reason) => format!("You have been kicked: {reason}"),
+                    None => "You have been kicked".to_string(),
+                };
+                self.kick(id, reason);
+                format!("Kicked {name} ({id})")
+            }
+            AdminCommand::Ban { id } => {
+                let Some(user) = self.state.users.get(&id) else { return Err(format!("There is no user with id {id}")) };
+                let (name, ip) = (user.name.clone(), user.address.ip());
+                self.network_interface.ban(id, ip);
+                self.kick(id, "You have been banned".to_string());
+                match ip {
+                    Some(ip) => format!("Banned {name} ({id}) at {ip}"),
+                    // E.g. connected over a unix socket.
+                    None => format!("Banned {name} ({id}), who has no ip address to ban"),
+                }
+            }
+            AdminCommand::Unban(ip) => {
+                if !self.network_interface.unban(ip) {
+                    return Err(format!("{ip} is not banned"));
+                }
+                format!("Unbanned {ip}")
+            }
+            AdminCommand::Announce(text) => {
+                self.send_tcp_to_all(ServerTcpMessage::Text(format!("Announcement: {text}")));
+                format!("Announced to {} users", self.state.users.len())
+            }
+            AdminCommand::Publish { topic, text } => {
+                if is_pattern(&topic) {
+                    return Err(format!("Cannot publish to the pattern {topic}"));
+                }
+                let subscribers = self.publish(&topic, text.into_bytes());
+                format!("Published to {subscribers} subscribers of {topic}")
+            }
+            AdminCommand::Rooms => {
+                let rooms = self.interests.rooms();
+                if rooms.is_empty() {
+                    return "There are no rooms".to_string();
+                }
+                let mut out = String::new();
+                for (room, members) in rooms {
+                    let _ = writeln!(out, "{room}: {members} members");
+                }
+                out.trim_end().to_string()
+            }
+            AdminCommand::LogLevel(level) => {
+                log::set_max_level(level);
+                format!("Log level set to {level}")
+            }
+            AdminCommand::ReloadKeys => {
+                let keys = self.network_interface.reload_admin_keys().map_err(|e| format!("Could not reload the admin keys: {e}"))?;
+                format!("Loaded {keys} admin keys")
+            }
+            AdminCommand::Netsim { user: Some(id), link } => {
+                let Some(name) = self.state.users.get(&id).map(|user| user.name.clone()) else { return Err(format!("There is no user with id {id}")) };
+                self.network_interface.set_link_conditions(id, link.map(|link| link.conditions(id)));
+                match link {
+                    Some(link) => format!("Simulating {link} for {name} ({id})"),
+                    None => format!("Stopped simulating a bad network for {name} ({id})"),
+                }
+            }
+            AdminCommand::Netsim { user: None, link } => {
+                // The default seeds every users rng with their id, so give the connected users the same.
+                self.network_interface.set_default_link_conditions(link.map(|link| link.conditions(0)));
+                let ids: Vec<UserId> = self.state.users.keys().copied().collect();
+                for id in ids {
+                    self.network_interface.set_link_conditions(id, link.map(|link| link.conditions(id)));
+                }
+                match link {
+                    Some(link) => format!("Simulating {link} for everyone"),
+                    None => "Stopped simulating a bad network".to_string(),
+                }
+            }
+            AdminCommand::Shutdown => {
+                self.send_tcp_to_all(ServerTcpMessage::Text("The server is shutting down".to_string()));
+                let ids: Vec<UserId> = self.state.users.keys().copied().collect();
+                for id in ids {
+                    self.network_interface.disconnect(id);
+                }
+                self.running = false;
+                "Shutting down".to_string()
+            }
+        })
+    }
+
+    /// Tells the user why, then closes its connection. The client does not reconnect on its own.
+    fn kick(&mut self, id: UserId, reason: String) {
+        self.network_interface.send_tcp(ServerTcpMessage::Kicked(reason), id);
+        self.network_interface.disconnect(id);
+    }
+
+    fn stats(&self) -> ServerStats {
+        ServerStats {
+            uptime_secs: self.clock.now() / 1_000_000,
+            tick: self.tick,
+            users: self.state.users.len() as u32,
+            rooms: self.interests.rooms().len() as u32,
+            bans: self.network_interface.ban_count() as u32,
+        }
+    }
+
+    fn user_summaries(&self) -> Vec<UserSummary> {
+        let mut users: Vec<_> = self.state.users.values()
+            .map(|user| UserSummary {
+                id: user.id,
+                name: user.name.clone(),
+                address: user.address.to_string(),
+                rtt_ms: self.network_interface.connection_stats(user.id).map(|stats| stats.rtt.as_millis() as u32),
+            })
+            .collect();
+        users.sort_by_key(|user| user.id);
+        users
+    }
+
+    fn describe_users(&self) -> String {
+        if self.state.users.is_empty() {
+            return "No users are connected".to_string();
+        }
+        let mut users: Vec<_> = self.state.users.values().collect();
+        users.sort_by_key(|user| user.id);
+        let mut out = String::new();
+        for user in users {
+            let stats = match self.network_interface.connection_stats(user.id) {
+                Some(stats) => format!("rtt {} ms, jitter {} ms, loss {:.0}%", stats.rtt.as_millis(), stats.jitter.as_millis(), stats.packet_loss * 100.0),
+                None => "no stats yet".to_string(),
+            };
+            // User ids are random, so they take up to the 20 digits of a u64.
+            let _ = writeln!(out, "{:>20}  {:<16}  {:<24}  {stats}", user.id, user.name, user.address.to_string());
+        }
+        out.trim_end().to_string()
+    }
+}
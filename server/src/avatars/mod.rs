@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use common::avatar::{Move, Position};
+use common::interest::Interest;
+use common::replication::EntityId;
+use common::UserId;
+use crate::input_buffer::InputBuffer;
+use crate::interest::InterestManager;
+use crate::replication::Replication;
+
+/// The replicated avatar of every connected user, spawned when the user connects and despawned when they disconnect. \
+/// Avatars are placed in the region they stand in, so users only see the others in the regions they subscribed to.
+/// Their own avatar is always relevant to them.
+#[derive(Default)]
+pub(crate) struct Avatars {
+    by_user: HashMap<UserId, EntityId>,
+}
+
+impl Avatars {
+    /// Returns the existing avatar if the user already has one.
+    /// The user has to be added to the interest manager first.
+    pub(crate) fn spawn(&mut self, user: UserId, replication: &mut Replication, interests: &mut InterestManager) -> EntityId {
+        *self.by_user.entry(user).or_insert_with(|| {
+            let entity = replication.spawn();
+            replication.set_component(entity, &Position::SPAWN);
+            interests.set_region(entity, Position::SPAWN.region());
+            interests.subscribe(user, Interest::Entity(entity));
+            entity
+        })
+    }
+
+    /// Returns the despawned entity, or None if the user had no avatar.
+    pub(crate) fn despawn(&mut self, user: UserId, replication: &mut Replication) -> Option<EntityId> {
+        let entity = self.by_user.remove(&user)?;
+        replication.despawn(entity);
+        Some(entity)
+    }
+
+    /// Moves every avatar by the inputs its user sent since the last tick, which marks them as processed.
+    pub(crate) fn process_inputs(&self, inputs: &mut InputBuffer, replication: &mut Replication, interests: &mut InterestManager) {
+        for (user, entity) in &self.by_user {
+            let steps = inputs.take::<Move>(*user);
+            let Some(start) = replication.component::<Position>(*entity) else { continue };
+            let position = steps.into_iter().fold(start, Position::moved);
+            if position != start {
+                replication.set_component(*entity, &position);
+                interests.set_region(*entity, position.region());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serializeable::Serializeable;
+    use common::replication::{Snapshot, Tick, WorldState};
+    use crate::interest::InterestEvent;
+    use super::*;
+
+    #[derive(Default)]
+    struct World {
+        avatars: Avatars,
+        replication: Replication,
+        inputs: InputBuffer,
+        interests: InterestManager,
+    }
+
+    impl World {
+        fn connect(&mut self, user: UserId) -> EntityId {
+            self.replication.add_client(user);
+            self.inputs.add_client(user);
+            self.interests.add_client(user);
+            self.avatars.spawn(user, &mut self.replication, &mut self.interests)
+        }
+
+        /// Runs the simulation part of a server tick and returns the snapshot for the user.
+        fn tick(&mut self, tick: Tick, user: UserId) -> Option<Snapshot> {
+            self.avatars.process_inputs(&mut self.inputs, &mut self.replication, &mut self.interests);
+            self.interests.refresh(self.replication.entities());
+            let (interests, inputs) = (&self.interests, &self.inputs);
+            let snapshots = self.replication.snapshots(tick, |id, entity| interests.is_relevant(id, entity), |id| inputs.last_processed(id));
+            snapshots.into_iter().find(|(id, _)| *id == user).map(|(_, snapshot)| snapshot)
+        }
+    }
+
+    #[test]
+    fn users_get_one_avatar_until_they_leave() {
+        let mut world = World::default();
+        let entity = world.connect(1);
+        assert_eq!(world.avatars.spawn(1, &mut world.replication, &mut world.interests), entity);
+        assert_ne!(world.connect(2), entity);
+        assert_eq!(world.replication.component::<Position>(entity), Some(Position::SPAWN));
+
+        assert_eq!(world.avatars.despawn(1, &mut world.replication), Some(entity));
+        assert_eq!(world.avatars.despawn(1, &mut world.replication), None);
+        assert!(world.replication.entities().all(|other| other != entity));
+    }
+
+    #[test]
+    fn users_see_their_own_avatar_and_those_in_their_regions() {
+        let mut world = World::default();
+        let own = world.connect(1);
+        let other = world.connect(2);
+        let mut state = WorldState::default();
+        state.apply(&world.tick(0, 1).unwrap());
+        assert!(state.contains(own) && !state.contains(other));
+
+        world.interests.subscribe(1, Interest::Region(Position::SPAWN.region()));
+        state = WorldState::default();
+        state.apply(&world.tick(1, 1).unwrap());
+        assert_eq!(state.component::<Position>(other), Some(Position::SPAWN));
+    }
+
+    #[test]
+    fn processed_inputs_show_up_in_the_next_snapshot() {
+        let mut world = World::default();
+        let entity = world.connect(1);
+        world.inputs.push(1, 0, Move::Right.serialize());
+        world.inputs.push(1, 1, vec![0xff]);
+        world.inputs.push(1, 2, Move::Down.serialize());
+        world.inputs.push(1, 1, Move::Left.serialize());
+
+        let snapshot = world.tick(0, 1).unwrap();
+        assert_eq!(snapshot.last_processed_input, Some(2), "undecodable inputs count as processed, late ones are dropped");
+        let mut state = WorldState::default();
+        state.apply(&snapshot);
+        assert_eq!(state.component::<Position>(entity), Some(Position { x: Position::SPAWN.x + 1, y: Position::SPAWN.y + 1 }));
+    }
+
+    /// An avatar that walks out of the region a user watches leaves that users world.
+    #[test]
+    fn avatars_leaving_a_region_are_despawned_for_its_subscribers() {
+        let mut world = World::default();
+        world.connect(1);
+        let walker = world.connect(2);
+        world.interests.subscribe(1, Interest::Region(Position::SPAWN.region()));
+        let snapshot = world.tick(0, 1).unwrap();
+        assert!(snapshot.entities.iter().any(|delta| delta.entity == walker));
+        world.replication.acknowledge(1, 0);
+        while world.interests.poll_event().is_some() {}
+
+        let steps = Position::SPAWN.x % Position::REGION_SIZE;
+        for sequence in 0..=steps as u32 {
+            world.inputs.push(2, sequence, Move::Left.serialize());
+        }
+        let snapshot = world.tick(1, 1).unwrap();
+        let position = world.replication.component::<Position>(walker).unwrap();
+        assert_ne!(position.region(), Position::SPAWN.region());
+        assert_eq!(world.interests.poll_event(), Some(InterestEvent::Left { user: 1, entity: walker }));
+        assert_eq!(world.interests.poll_event(), None);
+        assert_eq!((snapshot.baseline, snapshot.despawned), (Some(0), vec![walker]));
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Instant;
+use common::replication::Tick;
+use common::time_sync::{ServerMicros, TimeResponse, Timestamp};
+
+/// The servers time and tick, shared between the tick loop and the network tasks.
+#[derive(Clone)]
+pub(crate) struct ServerClock {
+    inner: Arc<ClockState>,
+}
+
+struct ClockState {
+    epoch: Instant,
+    tick: AtomicU32,
+    tick_started_at: AtomicU64,
+}
+
+impl ServerClock {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Arc::new(ClockState {
+                epoch: Instant::now(),
+                tick: AtomicU32::new(0),
+                tick_started_at: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub(crate) fn now(&self) -> ServerMicros {
+        self.inner.epoch.elapsed().as_micros() as ServerMicros
+    }
+
+    pub(crate) fn tick(&self) -> Tick {
+        self.inner.tick.load(Ordering::Acquire)
+    }
+
+    /// Call this at the beginning of every tick.
+    pub(crate) fn start_tick(&self, tick: Tick) {
+        self.inner.tick_started_at.store(self.now(), Ordering::Release);
+        self.inner.tick.store(tick, Ordering::Release);
+    }
+
+    pub(crate) fn timestamp(&self) -> Timestamp {
+        Timestamp { time: self.now(), tick: self.tick() }
+    }
+
+    /// Answers a time request, `received_at` being the time the request arrived.
+    pub(crate) fn time_response(&self, client_send_time: u64, received_at: ServerMicros) -> TimeResponse {
+        TimeResponse {
+            client_send_time,
+            server_receive_time: received_at,
+            tick: self.tick(),
+            tick_started_at: self.inner.tick_started_at.load(Ordering::Acquire),
+            server_send_time: self.now(),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::{HashMap, VecDeque};
+use serializeable::Serializeable;
+use common::{InputSequence, UserId};
+
+/// Collects the inputs clients send over udp until the simulation processes them,
+/// and remembers the last processed input of every client so it can be echoed back.
+#[derive(Default)]
+pub(crate) struct InputBuffer {
+    clients: HashMap<UserId, ClientInputs>,
+}
+
+#[derive(Default)]
+struct ClientInputs {
+    pending: VecDeque<(InputSequence, Vec<u8>)>,
+    last_received: Option<InputSequence>,
+    last_processed: Option<InputSequence>,
+}
+
+impl InputBuffer {
+    pub(crate) fn add_client(&mut self, id: UserId) {
+        self.clients.insert(id, Default::default());
+    }
+
+    pub(crate) fn remove_client(&mut self, id: UserId) {
+        self.clients.remove(&id);
+    }
+
+    /// Inputs that arrive out of order or duplicated are dropped.
+    pub(crate) fn push(&mut self, id: UserId, sequence: InputSequence, input: Vec<u8>) {
+        let Some(client) = self.clients.get_mut(&id) else { return };
+        if client.last_received.is_some_and(|last| last >= sequence) {
+            return;
+        }
+        client.last_received = Some(sequence);
+        client.pending.push_back((sequence, input));
+    }
+
+    /// Takes all pending inputs of a client in the order they were sent and marks them as processed. \
+    /// Inputs that can not be deserialized as `I` are skipped.
+    pub(crate) fn take<I: Serializeable>(&mut self, id: UserId) -> Vec<I> {
+        let Some(client) = self.clients.get_mut(&id) else { return Vec::new() };
+        let mut inputs = Vec::with_capacity(client.pending.len());
+        while let Some((sequence, data)) = client.pending.pop_front() {
+            client.last_processed = Some(sequence);
+            if let Ok(input) = I::deserialize(&mut &data[..]) {
+                inputs.push(input);
+            }
+        }
+        inputs
+    }
+
+    pub(crate) fn last_processed(&self, id: UserId) -> Option<InputSequence> {
+        self.clients.get(&id)?.last_processed
+    }
+}
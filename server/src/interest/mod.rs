@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use common::interest::{Interest, RegionId};
+use common::pubsub::topic_matches;
+use common::replication::EntityId;
+use common::UserId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InterestEvent {
+    /// The entity has become relevant to the user.
+    Entered { user: UserId, entity: EntityId },
+    /// The entity is no longer relevant to the user.
+    Left { user: UserId, entity: EntityId },
+}
+
+/// Keeps track of what every client is interested in. \
+/// An entity is relevant to a client if the client subscribed to the entity itself or to the region it is in.
+/// Entities that have not been placed in a region are relevant to everyone.
+#[derive(Default)]
+pub(crate) struct InterestManager {
+    subscriptions: HashMap<UserId, HashSet<Interest>>,
+    entity_regions: HashMap<EntityId, RegionId>,
+    /// The relevant entities of every client as of the last call to [`Self::refresh`].
+    relevant: HashMap<UserId, HashSet<EntityId>>,
+    events: VecDeque<InterestEvent>,
+}
+
+impl InterestManager {
+    pub(crate) fn add_client(&mut self, id: UserId) {
+        self.subscriptions.insert(id, Default::default());
+        self.relevant.insert(id, Default::default());
+    }
+
+    pub(crate) fn remove_client(&mut self, id: UserId) {
+        self.subscriptions.remove(&id);
+        self.relevant.remove(&id);
+    }
+
+    /// Returns false if the user was already subscribed or is not connected.
+    pub(crate) fn subscribe(&mut self, id: UserId, interest: Interest) -> bool {
+        self.subscriptions.get_mut(&id).is_some_and(|subscriptions| subscriptions.insert(interest))
+    }
+
+    /// Returns false if the user was not subscribed.
+    pub(crate) fn unsubscribe(&mut self, id: UserId, interest: &Interest) -> bool {
+        self.subscriptions.get_mut(&id).is_some_and(|subscriptions| subscriptions.remove(interest))
+    }
+
+    pub(crate) fn subscriptions(&self, id: UserId) -> impl Iterator<Item = &Interest> {
+        self.subscriptions.get(&id).into_iter().flatten()
+    }
+
+    /// All users that subscribed to the topic, or to a pattern that matches it.
+    pub(crate) fn subscribers(&self, topic: &str) -> Vec<UserId> {
+        self.subscriptions.iter()
+            .filter(|(_, subscriptions)| subscriptions.iter().any(|interest| matches!(interest, Interest::Topic(pattern) if topic_matches(pattern, topic))))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// All users in the chat room.
+    pub(crate) fn members(&self, room: &str) -> Vec<UserId> {
+        let room = Interest::Room(room.to_string());
+        self.subscriptions.iter()
+            .filter(|(_, subscriptions)| subscriptions.contains(&room))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Every chat room with at least one member, with the number of members.
+    pub(crate) fn rooms(&self) -> BTreeMap<String, usize> {
+        let mut rooms = BTreeMap::new();
+        for interest in self.subscriptions.values().flatten() {
+            if let Interest::Room(room) = interest {
+                *rooms.entry(room.clone()).or_default() += 1;
+            }
+        }
+        rooms
+    }
+
+    pub(crate) fn set_region(&mut self, entity: EntityId, region: RegionId) {
+        self.entity_regions.insert(entity, region);
+    }
+
+    pub(crate) fn is_relevant(&self, id: UserId, entity: EntityId) -> bool {
+        self.relevant.get(&id).is_some_and(|relevant| relevant.contains(&entity))
+    }
+
+    /// Recomputes which of the existing entities are relevant to every client and raises an event for each change.
+    pub(crate) fn refresh(&mut self, entities: impl Iterator<Item = EntityId>) {
+        let entities: HashSet<EntityId> = entities.collect();
+        self.entity_regions.retain(|entity, _| entities.contains(entity));
+
+        for (user, subscriptions) in &self.subscriptions {
+            let now_relevant: HashSet<EntityId> = entities.iter()
+                .copied()
+                .filter(|entity| match self.entity_regions.get(entity) {
+                    _ if subscriptions.contains(&Interest::Entity(*entity)) => true,
+                    Some(region) => subscriptions.contains(&Interest::Region(*region)),
+                    None => true,
+                })
+                .collect();
+            let previously_relevant = self.relevant.entry(*user).or_default();
+
+            for entity in now_relevant.difference(previously_relevant) {
+                self.events.push_back(InterestEvent::Entered { user: *user, entity: *entity });
+            }
+            for entity in previously_relevant.difference(&now_relevant) {
+                self.events.push_back(InterestEvent::Left { user: *user, entity: *entity });
+            }
+            *previously_relevant = now_relevant;
+        }
+    }
+
+    /// A return value of None means that no more events have happened _yet_.
+    pub(crate) fn poll_event(&mut self) -> Option<InterestEvent> {
+        self.events.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(interests: &mut InterestManager) -> Vec<InterestEvent> {
+        std::iter::from_fn(|| interests.poll_event()).collect()
+    }
+
+    #[test]
+    fn moving_an_entity_between_regions_changes_who_it_is_relevant_to() {
+        let mut interests = InterestManager::default();
+        interests.add_client(1);
+        interests.add_client(2);
+        interests.subscribe(1, Interest::Region(10));
+        interests.subscribe(2, Interest::Region(20));
+        interests.set_region(5, 10);
+        interests.refresh([5].into_iter());
+        assert_eq!(events(&mut interests), vec![InterestEvent::Entered { user: 1, entity: 5 }]);
+        assert!(interests.is_relevant(1, 5) && !interests.is_relevant(2, 5));
+
+        interests.set_region(5, 20);
+        interests.refresh([5].into_iter());
+        let mut changes = events(&mut interests);
+        changes.sort_by_key(|event| match event { InterestEvent::Entered { user, .. } | InterestEvent::Left { user, .. } => *user });
+        assert_eq!(changes, vec![InterestEvent::Left { user: 1, entity: 5 }, InterestEvent::Entered { user: 2, entity: 5 }]);
+        assert!(!interests.is_relevant(1, 5) && interests.is_relevant(2, 5));
+
+        interests.refresh([5].into_iter());
+        assert!(events(&mut interests).is_empty());
+    }
+
+    #[test]
+    fn subscribed_entities_are_relevant_in_any_region() {
+        let mut interests = InterestManager::default();
+        interests.add_client(1);
+        interests.subscribe(1, Interest::Entity(5));
+        interests.set_region(5, 10);
+        interests.set_region(6, 10);
+        interests.refresh([5, 6, 7].into_iter());
+        assert!(interests.is_relevant(1, 5));
+        assert!(!interests.is_relevant(1, 6));
+        assert!(interests.is_relevant(1, 7), "entities without a region are relevant to everyone");
+
+        interests.refresh([6, 7].into_iter());
+        assert_eq!(events(&mut interests).last(), Some(&InterestEvent::Left { user: 1, entity: 5 }));
+    }
+}
@@ -1,15 +1,84 @@
-use common::SERVER_ADDR;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use common::{logger, SERVER_ADDR, WEBSOCKET_ADDR};
+use common::transport::{ServerAddr, TransportKind};
+use log::LevelFilter;
+use crate::admin::AdminConsole;
+use crate::network_interface::config::NetworkConfig;
+use crate::pubsub::Topics;
 use crate::server::Server;
 
+mod admin;
+mod avatars;
 mod server;
+mod clock;
 mod message_resolver;
 mod network_interface;
+mod input_buffer;
+mod interest;
+mod pubsub;
+mod replication;
+mod rpc;
+
+const USAGE: &str = "\
+Usage: server [options]
+Options:
+  --websocket                                 also accept websocket clients, on port 25551
+  --unix-socket <path>                        also accept clients on this host over a unix socket at the path
+  --discovery                                 answer discovery queries from the local network
+  --registry <addr>                           register with the server registry at the address and keep sending it heartbeats
+  --admin-keys <path>                         accept remote admin sessions with the keys in the file, one `<owner|moderator> <key>` per line
+  --topics <path>                             configure the pubsub topics, one `<pattern> <reliable|unreliable> <server|anyone|names>` per line
+  --quic <server name> <certificate path>     use quic instead of tcp and udp, storing the self signed certificate for the clients";
 
 #[tokio::main]
 async fn main() {
-    let server = Server::new(SERVER_ADDR).await;
+    logger::init(LevelFilter::Info);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(options) = parse_args(&args) else {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    };
+    let mut server = Server::new(options.network).await.expect("failed to start the server");
+    if let Some(path) = options.topics_file {
+        server.topics = Topics::load(&path).unwrap_or_else(|e| {
+            eprintln!("Could not load the topics: {e}");
+            std::process::exit(1);
+        });
+    }
+    for addr in server.network_interface.bound_addresses() {
+        println!("Listening on {} (reliable) and {} (unreliable)", addr.reliable, addr.unreliable);
+    }
+
+    server.attach_admin_console(AdminConsole::stdin());
+    println!("Type help for the admin commands");
 
-    server.run();
+    server.run().await;
 }
 
+struct Options {
+    network: NetworkConfig,
+    topics_file: Option<PathBuf>,
+}
 
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut config = NetworkConfig {
+        addresses: vec![ServerAddr::from(SERVER_ADDR.parse::<SocketAddr>().ok()?)],
+        ..Default::default()
+    };
+    let mut topics_file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--websocket" => config.websocket_addr = Some(WEBSOCKET_ADDR.parse().ok()?),
+            "--unix-socket" => config.unix_socket = Some(args.next()?.into()),
+            "--discovery" => config.discovery = true,
+            "--registry" => config.registry_addr = Some(args.next()?.parse().ok()?),
+            "--admin-keys" => config.admin_keys_file = Some(args.next()?.into()),
+            "--topics" => topics_file = Some(args.next()?.into()),
+            "--quic" => config.transport = TransportKind::Quic { server_name: args.next()?.clone(), certificate_path: args.next()?.into() },
+            _ => return None,
+        }
+    }
+    Some(Options { network: config, topics_file })
+}
@@ -1,39 +1,88 @@
-use tokio::sync::mpsc::error::TryRecvError;
-use common::message::{ClientMessage, ClientTcpMessage, ClientUdpMessage};
+use common::message::{ClientMessage, ClientTcpMessage, ClientUdpMessage, ServerTcpMessage, ServerUdpMessage};
 use common::UserId;
-use crate::server::Server;
+use crate::network_interface::ClientEvent;
+use crate::server::{Client, Server};
 
 impl Server {
     pub fn handle_incoming_messages(&mut self) {
-        loop{
-            match self.incoming_messages.try_recv() {
-                Ok((ClientMessage::Tcp(msg), id)) => self.handle_tcp_message(msg, id),
-                Ok((ClientMessage::Udp(msg), id)) => self.handle_udp_message(msg, id),
-                Err(TryRecvError::Disconnected) => {panic!("Network manager shut down unexpectedly")}
-                Err(TryRecvError::Empty) => break,
-            }
-        }
-        while let Ok((message, userid)) = self.incoming_messages.try_recv() {
-            match message {
-                ClientMessage::Tcp(message) => {
-                    self.handle_tcp_message(message, userid);
+        while let Some((event, userid)) = self.network_interface.incoming_message() {
+            match event {
+                ClientEvent::Connected { address } => {
+                    let name = self.state.name_of(userid);
+                    for user in self.state.users.values() {
+                        self.network_interface.send_tcp(ServerTcpMessage::UserOnline { id: user.id, name: user.name.clone() }, userid);
+                    }
+                    self.state.users.insert(userid, Client { name: name.clone(), id: userid, address });
+                    self.send_tcp_to_all(ServerTcpMessage::UserOnline { id: userid, name });
+                    self.replication.add_client(userid);
+                    self.inputs.add_client(userid);
+                    self.interests.add_client(userid);
+                    self.avatars.spawn(userid, &mut self.replication, &mut self.interests);
                 }
-                ClientMessage::Udp(message) => {
-                    self.handle_udp_message(message, userid);
+                ClientEvent::Disconnected => {
+                    if self.state.users.remove(&userid).is_some() {
+                        self.send_tcp_to_all(ServerTcpMessage::UserOffline(userid));
+                    }
+                    self.replication.remove_client(userid);
+                    self.inputs.remove_client(userid);
+                    self.interests.remove_client(userid);
+                    self.avatars.despawn(userid, &mut self.replication);
                 }
+                ClientEvent::ClientMessage(ClientMessage::Tcp(message)) => self.handle_tcp_message(message, userid),
+                ClientEvent::ClientMessage(ClientMessage::Udp(message)) => self.handle_udp_message(message, userid),
             }
         }
     }
     
     fn handle_tcp_message(&mut self, message: ClientTcpMessage, userid: UserId) {
         match message {
-            _ => unimplemented!(),
+            ClientTcpMessage::Subscribe(interest) => { self.interests.subscribe(userid, interest); }
+            ClientTcpMessage::Unsubscribe(interest) => { self.interests.unsubscribe(userid, &interest); }
+            ClientTcpMessage::SetName(name) => self.set_name(userid, name),
+            ClientTcpMessage::Chat { topic, text } => {
+                let from = self.state.name_of(userid);
+                self.broadcast_tcp(&topic.clone(), ServerTcpMessage::Chat { topic, from, text });
+            }
+            ClientTcpMessage::PrivateMessage { to, text } => {
+                let from = self.state.name_of(userid);
+                match self.state.find_by_name(&to) {
+                    Some(target) => self.network_interface.send_tcp(ServerTcpMessage::PrivateMessage { from, text }, target),
+                    None => self.network_interface.send_tcp(ServerTcpMessage::Text(format!("There is no user named {to}")), userid),
+                }
+            }
+            ClientTcpMessage::Publish { topic, payload } => self.handle_publish(userid, topic, payload),
+            ClientTcpMessage::Request { id, method, payload } => self.handle_rpc_request(userid, id, method, payload),
+            // Normally unwrapped by the network manager already.
+            ClientTcpMessage::Unreliable(message) => self.handle_udp_message(message, userid),
+            ClientTcpMessage::Text(text) => log::debug!("Ignored a text from user {userid}: {text}"),
         }
     }
     
+    /// Names are unique, so that private messages reach the right user.
+    fn set_name(&mut self, userid: UserId, name: String) {
+        if self.state.find_by_name(&name).is_some_and(|owner| owner != userid) {
+            self.network_interface.send_tcp(ServerTcpMessage::Text(format!("The name {name} is already taken")), userid);
+            return;
+        }
+        if let Some(user) = self.state.users.get_mut(&userid) {
+            user.name = name.clone();
+            self.send_tcp_to_all(ServerTcpMessage::UserOnline { id: userid, name });
+        }
+    }
+
     fn handle_udp_message(&mut self, message: ClientUdpMessage, userid: UserId) {
         match message {
-            _ => unimplemented!(),
+            ClientUdpMessage::AcknowledgeSnapshot(tick) => self.replication.acknowledge(userid, tick),
+            ClientUdpMessage::Input(sequence, input) => self.inputs.push(userid, sequence, input),
+            // Clients with an unreliable channel get these answered by the network manager, the others end up here.
+            ClientUdpMessage::TimeRequest(client_send_time) => {
+                let response = self.clock.time_response(client_send_time, self.clock.now());
+                self.network_interface.send_udp(ServerUdpMessage::TimeResponse(response), userid);
+            }
+            ClientUdpMessage::Ping(sequence) => self.network_interface.send_udp(ServerUdpMessage::Pong(sequence), userid),
+            // Only answers to the probes of the network manager, which are not sent over the reliable channel.
+            ClientUdpMessage::Pong(_) => {}
+            ClientUdpMessage::ChatMessage(text) => log::debug!("Ignored an unreliable chat message from user {userid}: {text}"),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,40 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use common::transport::{ServerAddr, TransportKind};
+
+/// Where and how the server accepts clients.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConfig {
+    /// Every address the server listens on, e.g. ipv4 and ipv6, or loopback and a public interface. At least one is needed.
+    pub addresses: Vec<ServerAddr>,
+    /// Only used by `Server::new`, a server created with a custom transport uses that one.
+    pub transport: TransportKind,
+    /// Also accept clients over websocket on this address, e.g. browser based dashboards and tools. \
+    /// They carry the reliable messages in binary frames and have no unreliable channel.
+    pub websocket_addr: Option<SocketAddr>,
+    /// Also accept clients on the same host over a unix stream socket at this path,
+    /// see `common::transport::unix` for the unreliable channel.
+    pub unix_socket: Option<PathBuf>,
+    /// What the server tells clients that are looking for servers.
+    pub description: ServerDescription,
+    /// Answer discovery queries from the local network, see `common::discovery`.
+    pub discovery: bool,
+    /// Register with the registry at this address and keep sending it heartbeats, see `common::registry`.
+    pub registry_addr: Option<SocketAddr>,
+    /// Accept admin sessions authenticated with the keys in this file, see `common::admin`. \
+    /// Without it, every admin key is rejected.
+    pub admin_keys_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerDescription {
+    pub name: String,
+    /// Only advertised, the server does not turn away clients once it is full.
+    pub capacity: u32,
+}
+
+impl Default for ServerDescription {
+    fn default() -> Self {
+        Self { name: "Unnamed server".to_string(), capacity: 64 }
+    }
+}
@@ -1,50 +1,239 @@
+pub(crate) mod config;
 mod network_manager;
 
-use std::net::ToSocketAddrs;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use common::admin::{AdminRequest, AdminResponse};
 use common::message::{ClientMessage, ServerMessage, ServerTcpMessage, ServerUdpMessage};
+use common::link_conditioner::NetworkConditions;
+use common::link_quality::ConnectionStats;
+use common::transport::{ServerAddr, TcpUdpTransport, Transport};
 use common::UserId;
-use crate::network_interface::network_manager::NetworkManager;
+use crate::clock::ServerClock;
+use crate::network_interface::config::NetworkConfig;
+use crate::network_interface::network_manager::{ActiveWriters, Bans, LinkMonitors, NetworkManager, NetworkManagerHandle, Outgoing};
+use crate::network_interface::network_manager::admin::{self, AdminKeys};
+use crate::network_interface::network_manager::conditioning::SharedLinkConditioners;
 
 pub enum ClientEvent{
-    Connected,
+    Connected { address: PeerAddress },
     Disconnected,
     ClientMessage(ClientMessage),
 }
 
+/// Where a client connected from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddress {
+    /// Clients connected over the transport or a websocket.
+    Socket(SocketAddr),
+    /// Clients on the same host, identified by the path of their datagram socket.
+    Unix(PathBuf),
+}
+
+impl PeerAddress {
+    /// None for unix socket clients.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            PeerAddress::Socket(addr) => Some(addr.ip()),
+            PeerAddress::Unix(_) => None,
+        }
+    }
+}
+
+impl Display for PeerAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeerAddress::Socket(addr) => write!(f, "{addr}"),
+            PeerAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A request of an authenticated admin, whose role permits it. The tick loop is expected to answer it.
+pub struct AdminCall {
+    pub request: AdminRequest,
+    respond: oneshot::Sender<AdminResponse>,
+}
+
+impl AdminCall {
+    pub fn respond(self, response: AdminResponse) {
+        // The admin might have disconnected in the meantime.
+        let _ = self.respond.send(response);
+    }
+}
 
 pub(super) struct NetworkInterface{
     incoming_messages: UnboundedReceiver<(ClientEvent, UserId)>,
-    outgoing_messages: UnboundedSender<(ServerMessage, UserId)>,
+    outgoing_messages: UnboundedSender<(Outgoing, UserId)>,
+    clock: ServerClock,
+    link_monitors: LinkMonitors,
+    link_conditioners: SharedLinkConditioners,
+    admin_calls: UnboundedReceiver<AdminCall>,
+    admin_keys: AdminKeys,
+    admin_keys_file: Option<PathBuf>,
+    bans: Bans,
+    writers: ActiveWriters,
+    bound_addresses: Vec<ServerAddr>,
 }
 
 impl NetworkInterface{
     const ERROR_MSG: &str = "Servers Network Manager crashed unexpectedly";
+    /// How long a shutdown waits at most for the last messages to be written.
+    const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Create a new ServerNetworkManager using tcp and udp and return an Interface for it.
+    pub async fn create(config: NetworkConfig, clock: ServerClock) -> std::io::Result<Self> {
+        Self::create_with_transport(TcpUdpTransport, config, clock).await
+    }
 
-    /// Create a new ServerNetworkManager and return an Interface for it.
-    pub async fn create<A: ToSocketAddrs>(addr: A) -> Self {
-        let (out_tx, in_rx) = NetworkManager::launch(addr).await;
+    /// Create a new ServerNetworkManager on top of the given transport and return an Interface for it.
+    pub async fn create_with_transport<T: Transport>(transport: T, config: NetworkConfig, clock: ServerClock) -> std::io::Result<Self> {
+        let link_conditioners = SharedLinkConditioners::default();
+        let admin_keys_file = config.admin_keys_file.clone();
+        let NetworkManagerHandle { outgoing_messages, incoming_messages, link_monitors, admin_calls, admin_keys, bans, writers, bound_addresses } =
+            NetworkManager::launch(transport, config, clock.clone(), link_conditioners.clone()).await?;
 
-        Self{
-            outgoing_messages: out_tx,
-            incoming_messages: in_rx,
-        }
+        Ok(Self{
+            outgoing_messages,
+            incoming_messages,
+            clock,
+            link_monitors,
+            link_conditioners,
+            admin_calls,
+            admin_keys,
+            admin_keys_file,
+            bans,
+            writers,
+            bound_addresses,
+        })
+    }
+
+    /// The addresses the server actually listens on, in the order they were configured. \
+    /// Differs from the config if ephemeral ports were requested.
+    pub fn bound_addresses(&self) -> &[ServerAddr] {
+        &self.bound_addresses
     }
 
     pub fn send_tcp(&mut self, msg: ServerTcpMessage, target: UserId){
-        self.outgoing_messages.send((ServerMessage::Tcp(msg), target)).expect(Self::ERROR_MSG)
+        self.outgoing_messages.send((Outgoing::Message(ServerMessage::Tcp(msg)), target)).expect(Self::ERROR_MSG)
     }
     pub fn send_udp(&mut self, msg: ServerUdpMessage, target: UserId){
-        self.outgoing_messages.send((ServerMessage::Udp(msg), target)).expect(Self::ERROR_MSG)
+        self.outgoing_messages.send((Outgoing::Message(ServerMessage::Udp(msg)), target)).expect(Self::ERROR_MSG)
+    }
+    /// Closes the connection of the user once the messages sent before have been handed to it. \
+    /// A Disconnected event follows.
+    pub fn disconnect(&mut self, target: UserId){
+        self.outgoing_messages.send((Outgoing::Disconnect, target)).expect(Self::ERROR_MSG)
+    }
+    /// Refuses the logins of the user id and of the ip, if there is one, from now on. Does not disconnect the user.
+    pub fn ban(&mut self, id: UserId, ip: Option<IpAddr>) {
+        let mut bans = self.bans.write().unwrap();
+        bans.ids.insert(id);
+        bans.ips.extend(ip);
+    }
+
+    /// Returns false if the ip was not banned. Banned user ids stay banned.
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        self.bans.write().unwrap().ips.remove(&ip)
+    }
+
+    /// The number of banned user ids and ips together.
+    pub fn ban_count(&self) -> usize {
+        let bans = self.bans.read().unwrap();
+        bans.ids.len() + bans.ips.len()
+    }
+
+    /// Stops accepting messages to send and disconnects every user once the messages sent so far have been handed to it,
+    /// then waits until they have been written, but at most `SHUTDOWN_TIMEOUT`.
+    pub async fn shutdown(self) {
+        let Self { outgoing_messages, writers, .. } = self;
+        drop(outgoing_messages);
+        let mut writers = writers.subscribe();
+        if tokio::time::timeout(Self::SHUTDOWN_TIMEOUT, writers.wait_for(|count| *count == 0)).await.is_err() {
+            log::warn!("Not every message could be sent before shutting down");
+        }
+    }
+
+    /// Sends the message together with the current server time and tick.
+    pub fn send_udp_timestamped(&mut self, msg: ServerUdpMessage, target: UserId) {
+        let timestamp = self.clock.timestamp();
+        self.send_udp(ServerUdpMessage::Timestamped(timestamp, Box::new(msg)), target)
+    }
+
+    /// Round trip time, jitter and packet loss of a users connection. None until the first probe has been answered.
+    pub fn connection_stats(&self, id: UserId) -> Option<ConnectionStats> {
+        self.link_monitors.lock().unwrap().get(&id)?.stats()
+    }
+
+    /// Simulates bad network conditions on the udp path of every user that connects from now on. Meant for testing.
+    pub fn set_default_link_conditions(&mut self, conditions: Option<NetworkConditions>) {
+        self.link_conditioners.lock().unwrap().set_default(conditions)
+    }
+
+    /// Simulates bad network conditions on the udp path of a single user. None turns the simulation off.
+    pub fn set_link_conditions(&mut self, id: UserId, conditions: Option<NetworkConditions>) {
+        self.link_conditioners.lock().unwrap().set(id, conditions)
+    }
+
+    /// Reads the admin keys file of the config again. Sessions that are already authenticated keep their role. \
+    /// Returns the number of keys.
+    pub fn reload_admin_keys(&mut self) -> io::Result<usize> {
+        let path = self.admin_keys_file.as_ref().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no admin keys file is configured"))?;
+        let keys = admin::load_keys(path)?;
+        let count = keys.len();
+        *self.admin_keys.write().unwrap() = keys;
+        Ok(count)
+    }
+
+    /// A return value of None means that no admin request has been received _yet_.
+    pub fn incoming_admin_call(&mut self) -> Option<AdminCall> {
+        match self.admin_calls.try_recv() {
+            Ok(call) => Some(call),
+            Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => panic!("{}", Self::ERROR_MSG),
+            Err(tokio::sync::mpsc::error::TryRecvError::Empty) => None
+        }
     }
 
     /// A return value of None means that no more Messages have been received _yet_.
     pub fn incoming_message(&mut self) -> Option<(ClientEvent, UserId)> {
         match self.incoming_messages.try_recv() {
             Ok(content) => Some(content),
-            Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => panic!(Self::ERROR_MSG),
+            Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => panic!("{}", Self::ERROR_MSG),
             Err(tokio::sync::mpsc::error::TryRecvError::Empty) => None
         }
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_bound_ephemeral_ports() {
+        let loopback = ServerAddr::from(SocketAddr::from(([127, 0, 0, 1], 0)));
+        let config = NetworkConfig { addresses: vec![loopback, loopback], ..Default::default() };
+        let interface = NetworkInterface::create(config, ServerClock::new()).await.unwrap();
+
+        let bound = interface.bound_addresses();
+        assert_eq!(bound.len(), 2);
+        for addr in bound {
+            assert!(addr.reliable.ip().is_loopback());
+            assert_ne!(addr.reliable.port(), 0);
+            assert_ne!(addr.unreliable.port(), 0);
+        }
+        assert_ne!(bound[0].reliable, bound[1].reliable);
+        assert_ne!(bound[0].unreliable, bound[1].unreliable);
+    }
+
+    #[tokio::test]
+    async fn needs_an_address() {
+        let result = NetworkInterface::create(NetworkConfig::default(), ServerClock::new()).await;
+        assert_eq!(result.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+}
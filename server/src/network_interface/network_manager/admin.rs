@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serializeable::Serializeable;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::oneshot;
+use common::admin::{AdminRequest, AdminResponse, AdminRole};
+use common::message::send_message::TcpSendable;
+use common::transport::Transport;
+use crate::network_interface::AdminCall;
+use crate::network_interface::network_manager::{Connections, WriteGuard};
+
+/// The admin keys and the role each of them grants. Shared with the network interface, which reloads them.
+pub(crate) type AdminKeys = Arc<std::sync::RwLock<HashMap<String, AdminRole>>>;
+
+/// Reads one key per line, preceded by the role it grants, e.g. `owner 6f1c...` or `moderator 93ab...`. \
+/// Empty lines and lines starting with # are skipped.
+pub(crate) fn load_keys(path: &Path) -> io::Result<HashMap<String, AdminRole>> {
+    let mut keys = HashMap::new();
+    for (number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: expected `<owner|moderator> <key>`", path.display(), number + 1));
+        let (role, key) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+        let role = match role {
+            "owner" => AdminRole::Owner,
+            "moderator" => AdminRole::Moderator,
+            _ => return Err(invalid()),
+        };
+        keys.insert(key.trim().to_string(), role);
+    }
+    Ok(keys)
+}
+
+/// Rejected admin keys, by the ip they came from. Clients over the unix socket have no ip and share an entry.
+pub(crate) type AdminLockouts = Arc<std::sync::Mutex<HashMap<Option<IpAddr>, Lockout>>>;
+
+/// Every rejected key doubles the time until the peer may try again, up to `MAX_LOCKOUT`.
+pub(crate) struct Lockout {
+    rejected: u32,
+    until: Instant,
+}
+
+const FIRST_LOCKOUT: Duration = Duration::from_secs(1);
+const MAX_LOCKOUT: Duration = Duration::from_secs(600);
+
+/// Checks the key, unless the peer is locked out after rejected keys. A rejected key extends the lockout,
+/// an accepted one lifts it. \
+/// The key travels in plaintext, so admin sessions should only be used on trusted networks.
+pub(super) fn authenticate(keys: &AdminKeys, lockouts: &AdminLockouts, peer: Option<IpAddr>, key: &str) -> Option<AdminRole> {
+    let mut lockouts = lockouts.lock().unwrap();
+    let now = Instant::now();
+    if lockouts.get(&peer).is_some_and(|lockout| lockout.until > now) {
+        return None;
+    }
+    let role = role_of(keys, key);
+    match role {
+        Some(_) => { lockouts.remove(&peer); }
+        None => {
+            // Peers that stopped trying are forgotten, so the map does not grow without bound.
+            lockouts.retain(|_, lockout| lockout.until + MAX_LOCKOUT > now);
+            let lockout = lockouts.entry(peer).or_insert(Lockout { rejected: 0, until: now });
+            lockout.rejected = lockout.rejected.saturating_add(1);
+            lockout.until = now + FIRST_LOCKOUT.saturating_mul(1 << (lockout.rejected - 1).min(16)).min(MAX_LOCKOUT);
+        }
+    }
+    role
+}
+
+/// Compares digests of the keys, which all have the same length, with every known one in full,
+/// so that the time it takes tells neither how much of a key was right nor how long the keys are.
+fn role_of(keys: &AdminKeys, key: &str) -> Option<AdminRole> {
+    let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+    let mut role = None;
+    for (known, known_role) in keys.read().unwrap().iter() {
+        if constant_time_eq(&Sha256::digest(known.as_bytes()).into(), &digest) {
+            role = Some(*known_role);
+        }
+    }
+    role
+}
+
+fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+/// Answers the requests of an authenticated admin until it disconnects. \
+/// The requests its role permits are handed to the tick loop, which executes them.
+pub(super) async fn run_session<S: AsyncRead + AsyncWrite + Unpin, T: Transport>(mut stream: S, role: AdminRole, connections: &Connections<T>) {
+    while let Ok(request) = AdminRequest::async_deserialize(&mut stream).await {
+        // A shutdown waits for the answer, e.g. to the shutdown request itself.
+        let _write_guard = WriteGuard::new(&connections.writers);
+        let response = if role.permits(&request) {
+            let (respond, answer) = oneshot::channel();
+            let _ = connections.admin_calls.send(AdminCall { request, respond });
+            answer.await.unwrap_or_else(|_| AdminResponse::Error("The server did not answer".to_string()))
+        } else {
+            AdminResponse::Error(format!("The {role:?} role does not permit {request:?}"))
+        };
+        if response.send(&mut stream).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use super::*;
+
+    const PEER: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+
+    fn keys() -> AdminKeys {
+        Arc::new(std::sync::RwLock::new(HashMap::from([("secret".to_string(), AdminRole::Owner)])))
+    }
+
+    #[test]
+    fn accepts_only_known_keys() {
+        let (keys, lockouts) = (keys(), AdminLockouts::default());
+        assert_eq!(authenticate(&keys, &lockouts, PEER, "secret"), Some(AdminRole::Owner));
+        assert_eq!(authenticate(&keys, &lockouts, None, "secre"), None);
+        assert_eq!(authenticate(&keys, &lockouts, PEER, "secret2"), None);
+    }
+
+    #[test]
+    fn locks_out_peers_after_a_rejected_key() {
+        let (keys, lockouts) = (keys(), AdminLockouts::default());
+        assert_eq!(authenticate(&keys, &lockouts, PEER, "guess"), None);
+        assert_eq!(authenticate(&keys, &lockouts, PEER, "secret"), None, "locked out");
+        assert_eq!(authenticate(&keys, &lockouts, None, "secret"), Some(AdminRole::Owner), "other peers are not affected");
+
+        lockouts.lock().unwrap().get_mut(&PEER).unwrap().until = Instant::now();
+        assert_eq!(authenticate(&keys, &lockouts, PEER, "secret"), Some(AdminRole::Owner));
+        assert!(lockouts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn lockouts_grow_with_every_rejected_key() {
+        let (keys, lockouts) = (keys(), AdminLockouts::default());
+        let mut previous = Duration::ZERO;
+        for _ in 0..20 {
+            authenticate(&keys, &lockouts, PEER, "guess");
+            let remaining = lockouts.lock().unwrap()[&PEER].until - Instant::now();
+            assert!(remaining + Duration::from_millis(100) >= previous && remaining <= MAX_LOCKOUT);
+            previous = remaining;
+            lockouts.lock().unwrap().get_mut(&PEER).unwrap().until = Instant::now();
+        }
+        assert!(previous > MAX_LOCKOUT - Duration::from_secs(1));
+    }
+}
@@ -1,130 +1,181 @@
 use common::message::send_message::TcpSendable;
-use std::collections::{HashMap, HashSet};
-use std::hash::{DefaultHasher, Hash, Hasher};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use common::link_quality::LinkMonitor;
 use common::UserId;
 use common::message::{ClientMessage, ClientTcpMessage, ServerMessage, ServerTcpMessage, ServerUdpMessage};
+use common::transport::{ReliableStream, Transport};
 use serializeable::Serializeable;
-use std::sync::Arc;
-use tokio::io::{AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use tokio::net::{TcpStream, UdpSocket};
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::mpsc::{UnboundedReceiver as Receiver, UnboundedSender as Sender};
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::oneshot;
+use common::admin::AdminRole;
 use common::message::client_message::ClientConnectionMessage;
 use common::message::server_message::ServerConnectionMessage;
-use crate::network_interface::ClientEvent;
+use crate::network_interface::{ClientEvent, PeerAddress};
+use crate::network_interface::network_manager::admin;
+use crate::network_interface::network_manager::conditioning;
+use crate::network_interface::network_manager::{Connections, WriteGuard};
 
-pub struct ClientHandler {
+pub struct ClientHandler<T: Transport> {
     id: UserId,
-    udp: Arc<UdpSocket>,
-    tcp_writer: OwnedWriteHalf,
-    tcp_reader: OwnedReadHalf,
-    incoming_messages: Sender<(ClientEvent, UserId)>, //Only for TCP.
+    peer_addr: SocketAddr,
+    tcp_writer: <T::Stream as ReliableStream>::WriteHalf,
+    tcp_reader: <T::Stream as ReliableStream>::ReadHalf,
     outgoing_messages: Receiver<ServerMessage>,
+    disconnect_signal: oneshot::Receiver<()>,
+    write_guard: WriteGuard,
 }
 
 
-impl ClientHandler {
-    pub async fn login_procedure(tcp: &mut TcpStream, connected_ids: Arc<Mutex<HashSet<UserId>>>) -> UserId {
-        async fn create_user_id(addr: &SocketAddr, connected_ids: Arc<Mutex<HashSet<UserId>>>) -> UserId {
-            let mut hasher = DefaultHasher::new();
-            addr.hash(&mut hasher);
-            let mut id = hasher.finish() as UserId;
-            while connected_ids.lock().await.contains(&id) {
-                id += 1;
-            }
-            id
-        }
-
+/// Who is on the other end of a stream, once it has logged in.
+pub(super) enum Login {
+    User(UserId),
+    Admin(AdminRole),
+    /// Banned or with a rejected admin key, for the given reason. The connection should be closed.
+    Refused(String),
+}
 
+impl<T: Transport> ClientHandler<T> {
+    /// Also used for the streams of clients that do not connect over the transport. \
+    /// Returns None if the peer left or sent something invalid before it was logged in.
+    pub async fn login_procedure<S: AsyncRead + AsyncWrite + Unpin>(tcp: &mut S, connections: &Connections<T>, ip: Option<IpAddr>) -> Option<Login> {
         loop {
-            match ClientConnectionMessage::async_deserialize(tcp).await.unwrap() {
-                ClientConnectionMessage::ConnectNew => {
-                    let id = create_user_id(&tcp.peer_addr().unwrap(), connected_ids.clone()).await;
-                    connected_ids.lock().await.insert(id);
-                    ServerConnectionMessage::AssignUserId(id).send(tcp).await.unwrap();
-                    return id;
-                },
-                ClientConnectionMessage::ConnectWithId(requested_id) => {
-                    if connected_ids.lock().await.insert(requested_id) {
-                        ServerConnectionMessage::AcknowledgeId.send(tcp).await.unwrap();
-                        return requested_id;
-                    } else { //let the client start a new connection attempt
-                        ServerConnectionMessage::IdAlreadyInUse.send(tcp).await.unwrap(); 
-                    }
-                },
+            let request = ClientConnectionMessage::async_deserialize(tcp).await.ok()?;
+            if let Some(reason) = connections.refusal(ip, &request) {
+                let _ = ServerConnectionMessage::Refused(reason.clone()).send(tcp).await;
+                return Some(Login::Refused(reason));
+            }
+            if let ClientConnectionMessage::Admin { key } = &request {
+                let role = admin::authenticate(&connections.admin_keys, &connections.admin_lockouts, ip, key);
+                let response = role.map_or(ServerConnectionMessage::AdminRejected, ServerConnectionMessage::AdminAccepted);
+                response.send(tcp).await.ok()?;
+                return Some(role.map_or_else(|| Login::Refused("rejected admin key".to_string()), Login::Admin));
+            }
+            let (response, id) = connections.id_allocator.lock().await.login(request);
+            if response.send(tcp).await.is_err() {
+                if let Some(id) = id {
+                    connections.id_allocator.lock().await.release(id);
+                }
+                return None;
+            }
+            if let Some(id) = id {
+                return Some(Login::User(id));
             }
         }
     }
-    pub fn spawn(
-        udp: Arc<UdpSocket>,
-        mut tcp: TcpStream,
-        incoming_messages: UnboundedSender<(ClientEvent, UserId)>,
-        outgoing_message_writers: Arc<RwLock<HashMap<UserId, UnboundedSender<ServerMessage>>>>,
-        addr_to_user_id: Arc<RwLock<HashMap<SocketAddr, UserId>>>,
-        connected_users: Arc<Mutex<HashSet<UserId>>>,
-    ) {
+    pub fn spawn(mut tcp: T::Stream, connections: Connections<T>) {
         tokio::spawn(
             async move {
-                {
-                    let id = ClientHandler::login_procedure(&mut tcp, connected_users.clone()).await;
-                    incoming_messages.send((ClientEvent::Connected, id)).unwrap();
-                    addr_to_user_id.write().await.insert(tcp.peer_addr().unwrap(), id);
+                let handler = {
+                    let Ok(peer_addr) = tcp.peer_addr() else { return };
+                    let id = match Self::login_procedure(&mut tcp, &connections, Some(peer_addr.ip())).await {
+                        Some(Login::User(id)) => id,
+                        Some(Login::Admin(role)) => {
+                            log::info!("Admin session with {peer_addr} as {role:?}");
+                            return admin::run_session(tcp, role, &connections).await;
+                        }
+                        Some(Login::Refused(reason)) => {
+                            log::warn!("Refused {peer_addr}: {reason}");
+                            return;
+                        }
+                        None => {
+                            log::debug!("{peer_addr} left before logging in");
+                            return;
+                        }
+                    };
+                    let disconnect_signal = connections.disconnect_signal(id);
+                    // Taken before the tick loop learns about the user, so a shutdown waits for everything sent to it.
+                    let write_guard = WriteGuard::new(&connections.writers);
+                    connections.incoming_messages.send((ClientEvent::Connected { address: PeerAddress::Socket(peer_addr) }, id)).unwrap();
+                    connections.socket_addr_to_user_id.write().await.insert(peer_addr, id);
+                    connections.link_monitors.lock().unwrap().insert(id, Default::default());
+                    connections.link_conditioners.lock().unwrap().add_user(id);
 
                     let (outgoing_per_client_tx, outgoing_per_client_rx) = unbounded_channel::<ServerMessage>();
-                    outgoing_message_writers.write().await.insert(id, outgoing_per_client_tx);
+                    connections.user_id_to_message_sender.write().await.insert(id, outgoing_per_client_tx);
 
                     let (tcp_reader, tcp_writer) = tcp.into_split();
                     Self {
                         id,
-                        udp,
+                        peer_addr,
                         tcp_writer,
                         tcp_reader,
-                        incoming_messages,
                         outgoing_messages: outgoing_per_client_rx,
+                        disconnect_signal,
+                        write_guard,
                     }
-                }
-                    .run().await
+                };
+                handler.run(connections).await
             }
         );
     }
 
-    async fn run(mut self) {
+    /// Runs until the client disconnects and cleans up after it.
+    async fn run(self, connections: Connections<T>) {
+        let Self { id, peer_addr, tcp_writer, tcp_reader, outgoing_messages, disconnect_signal, write_guard } = self;
         let (tcp_message_sender, tcp_message_receiver) = unbounded_channel::<ServerTcpMessage>();
         let (udp_message_sender, udp_message_receiver) = unbounded_channel::<ServerUdpMessage>();
-        
-        tokio::spawn(Self::receive_tcp(self.tcp_reader, self.incoming_messages, self.id));
-        tokio::spawn(Self::send_udp(udp_message_receiver, self.udp, self.tcp_writer.peer_addr().unwrap()));
-        tokio::spawn(Self::send_tcp(tcp_message_receiver, self.tcp_writer));
-        loop {
-            match self.outgoing_messages.recv().await.unwrap() {
-                ServerMessage::Tcp(tcp_msg) => {tcp_message_sender.send(tcp_msg).expect(&format!("Tcp Sender for client {}, crashed", self.id));}
-                ServerMessage::Udp(udp_msg) => {udp_message_sender.send(udp_msg).expect(&format!("Udp Sender for client {}, crashed", self.id));}
+
+        tokio::spawn(Self::probe(connections.clone(), peer_addr, id));
+        tokio::spawn(Self::send_udp(udp_message_receiver, connections.clone(), peer_addr, id));
+        tokio::spawn(Self::send_tcp(tcp_message_receiver, tcp_writer, write_guard));
+        tokio::spawn(Self::split_outgoing(outgoing_messages, tcp_message_sender, udp_message_sender, id));
+        tokio::select! {
+            _ = Self::receive_tcp(tcp_reader, connections.incoming_messages.clone(), id) => {}
+            _ = disconnect_signal => {}
+        }
+
+        // Dropping the message writer ends all sending tasks of this client.
+        connections.user_id_to_message_sender.write().await.remove(&id);
+        connections.disconnect_signals.lock().unwrap().remove(&id);
+        connections.socket_addr_to_user_id.write().await.remove(&peer_addr);
+        connections.id_allocator.lock().await.release(id);
+        connections.link_monitors.lock().unwrap().remove(&id);
+        connections.link_conditioners.lock().unwrap().remove_user(id);
+        connections.incoming_messages.send((ClientEvent::Disconnected, id)).unwrap();
+    }
+
+    /// Hands every outgoing message to the sender task of its protocol.
+    async fn split_outgoing(mut outgoing_messages: Receiver<ServerMessage>, tcp_message_sender: Sender<ServerTcpMessage>, udp_message_sender: Sender<ServerUdpMessage>, id: UserId) {
+        while let Some(message) = outgoing_messages.recv().await {
+            match message {
+                ServerMessage::Tcp(tcp_msg) => {tcp_message_sender.send(tcp_msg).expect(&format!("Tcp Sender for client {}, crashed", id));}
+                ServerMessage::Udp(udp_msg) => {udp_message_sender.send(udp_msg).expect(&format!("Udp Sender for client {}, crashed", id));}
             }
         }
+    }
 
+    /// Regularly pings the client to measure the connection, until it disconnects.
+    async fn probe(connections: Connections<T>, peer_addr: SocketAddr, id: UserId) {
+        let mut interval = tokio::time::interval(LinkMonitor::PROBE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(sequence) = connections.link_monitors.lock().unwrap().get_mut(&id).map(LinkMonitor::next_probe) else { break };
+            conditioning::send_to(&connections.udp, ServerUdpMessage::Ping(sequence).serialize(), peer_addr, id, &connections.link_conditioners).await;
+        }
     }
-    
-    async fn receive_tcp(mut tcp_reader: OwnedReadHalf, incoming_messages: Sender<(ClientEvent, UserId)>, id: UserId) {
+
+    async fn receive_tcp(mut tcp_reader: <T::Stream as ReliableStream>::ReadHalf, incoming_messages: Sender<(ClientEvent, UserId)>, id: UserId) {
         while let Ok(msg) = ClientTcpMessage::async_deserialize(&mut tcp_reader).await {
-            incoming_messages.send((ClientEvent::ClientMessage(ClientMessage::Tcp(msg)), id)).unwrap();
+            incoming_messages.send((ClientEvent::ClientMessage(ClientMessage::from(msg)), id)).unwrap();
         }
-        incoming_messages.send((ClientEvent::Disconnected, id)).unwrap();
     }
-    
-    async fn send_tcp(mut receiver: Receiver<ServerTcpMessage>, mut tcp_writer: OwnedWriteHalf) {
+
+    /// Returns once every message has been written, or writing fails.
+    async fn send_tcp(mut receiver: Receiver<ServerTcpMessage>, mut tcp_writer: <T::Stream as ReliableStream>::WriteHalf, _write_guard: WriteGuard) {
         while let Some(tcp_message) = receiver.recv().await {
             let bytes = tcp_message.serialize();
-            tcp_writer.write_all(&bytes).await.unwrap();
+            if tcp_writer.write_all(&bytes).await.is_err() {
+                break;
+            }
         }
     }
-    
-    async fn send_udp(mut receiver: Receiver<ServerUdpMessage>, udp: Arc<UdpSocket>, socket_addr: SocketAddr) {
+
+    async fn send_udp(mut receiver: Receiver<ServerUdpMessage>, connections: Connections<T>, socket_addr: SocketAddr, id: UserId) {
         while let Some(udp_message) = receiver.recv().await {
             let bytes = udp_message.serialize();
-            udp.send_to(&bytes, socket_addr).await.unwrap();
+            conditioning::send_to(&connections.udp, bytes, socket_addr, id, &connections.link_conditioners).await;
         }
     }
 }
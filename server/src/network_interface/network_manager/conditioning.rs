@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use common::link_conditioner::{LinkConditioner, NetworkConditions};
+use common::transport::DatagramSocket;
+use common::UserId;
+
+pub(crate) type SharedLinkConditioners = Arc<std::sync::Mutex<LinkConditioners>>;
+
+/// Opt-in simulation of bad network conditions on the udp path of every connection, for testing.
+#[derive(Default)]
+pub(crate) struct LinkConditioners {
+    /// Applied to every user that connects from now on. Each gets its own rng, seeded with the users id.
+    default: Option<NetworkConditions>,
+    per_user: HashMap<UserId, LinkConditioner>,
+}
+
+impl LinkConditioners {
+    pub(crate) fn set_default(&mut self, conditions: Option<NetworkConditions>) {
+        self.default = conditions;
+    }
+
+    /// Overrides the conditions of a connected user. None turns the simulation off for them.
+    pub(crate) fn set(&mut self, id: UserId, conditions: Option<NetworkConditions>) {
+        match conditions {
+            Some(conditions) => { self.per_user.insert(id, LinkConditioner::new(conditions)); }
+            None => { self.per_user.remove(&id); }
+        }
+    }
+
+    pub(super) fn add_user(&mut self, id: UserId) {
+        if let Some(mut conditions) = self.default.clone() {
+            conditions.seed ^= id;
+            self.per_user.insert(id, LinkConditioner::new(conditions));
+        }
+    }
+
+    pub(super) fn remove_user(&mut self, id: UserId) {
+        self.per_user.remove(&id);
+    }
+
+    /// None means that the user is not conditioned and the packet should be handled right away.
+    pub(super) fn incoming(&mut self, id: UserId, len: usize) -> Option<Vec<Duration>> {
+        Some(self.per_user.get_mut(&id)?.incoming(len))
+    }
+
+    /// None means that the user is not conditioned and the packet should be sent right away.
+    pub(super) fn outgoing(&mut self, id: UserId, len: usize) -> Option<Vec<Duration>> {
+        Some(self.per_user.get_mut(&id)?.outgoing(len))
+    }
+}
+
+/// Sends a packet to a user, through their link conditioner if they have one.
+pub(super) async fn send_to<D: DatagramSocket>(udp: &Arc<D>, bytes: Vec<u8>, target: SocketAddr, id: UserId, conditioners: &SharedLinkConditioners) {
+    let delays = conditioners.lock().unwrap().outgoing(id, bytes.len());
+    match delays {
+        None => { let _ = udp.send_to(&bytes, target).await; }
+        Some(delays) => {
+            for delay in delays {
+                let udp = udp.clone();
+                let bytes = bytes.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = udp.send_to(&bytes, target).await;
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use serializeable::Serializeable;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use common::discovery::{DiscoveryMessage, DISCOVERY_MULTICAST_ADDR, DISCOVERY_PORT};
+use common::transport::Transport;
+use crate::network_interface::network_manager::server_info::ServerInfoSource;
+
+/// Answers discovery queries sent by broadcast or to the multicast group with the current server info.
+pub(super) struct DiscoveryResponder<T: Transport> {
+    socket: UdpSocket,
+    server_info: ServerInfoSource<T>,
+}
+
+impl<T: Transport> DiscoveryResponder<T> {
+    /// The port is shared, so that several servers on the same host can be discovered.
+    pub(super) fn bind(server_info: ServerInfoSource<T>) -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).into())?;
+        socket.join_multicast_v4(&DISCOVERY_MULTICAST_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+
+        Ok(Self { socket: UdpSocket::from_std(socket.into())?, server_info })
+    }
+
+    /// Returns only if the socket breaks down.
+    pub(super) async fn run(self) {
+        let mut buf = [0u8; 512];
+        loop {
+            let (n, sender) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                // Some platforms report an icmp port unreachable of a previous answer on the next receive.
+                Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused) => continue,
+                Err(e) => {
+                    log::error!("Stopped answering discovery queries: {e}");
+                    return;
+                }
+            };
+            let Ok(DiscoveryMessage::Query) = DiscoveryMessage::deserialize(&mut &buf[..n]) else { continue };
+
+            let info = self.server_info.current().await;
+            let _ = self.socket.send_to(&DiscoveryMessage::Info(info).serialize(), sender).await;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use common::message::client_message::ClientConnectionMessage;
+use common::message::server_message::ServerConnectionMessage;
+use common::{ReconnectToken, UserId};
+
+/// Hands out user ids and keeps track of which of them are currently in use. \
+/// Fresh ids and their reconnect tokens are drawn from a cryptographically secure rng,
+/// so they neither depend on the clients address nor can they be predicted.
+pub struct IdAllocator {
+    in_use: HashSet<UserId>,
+    /// Ids of recently disconnected users, mapped to the point in time their reservation runs out.
+    reserved: HashMap<UserId, Instant>,
+    /// The secret every id in use or reserved was handed out with. Only whoever knows it may reclaim the id.
+    tokens: HashMap<UserId, ReconnectToken>,
+    reservation_window: Option<Duration>,
+}
+
+impl IdAllocator {
+    /// With a `reservation_window` set, released ids are kept away from new users for that duration,
+    /// so that a reconnecting user can reclaim the same id.
+    pub fn new(reservation_window: Option<Duration>) -> Self {
+        Self {
+            in_use: Default::default(),
+            reserved: Default::default(),
+            tokens: Default::default(),
+            reservation_window,
+        }
+    }
+
+    /// Allocates a new id that is neither in use nor reserved, together with the token to reclaim it.
+    pub fn allocate(&mut self) -> (UserId, ReconnectToken) {
+        self.clear_expired_reservations();
+        loop {
+            let id = rand::random::<UserId>();
+            if !self.in_use.contains(&id) && !self.reserved.contains_key(&id) {
+                let token = rand::random::<ReconnectToken>();
+                self.in_use.insert(id);
+                self.tokens.insert(id, token);
+                return (id, token);
+            }
+        }
+    }
+
+    /// Tries to claim a specific id, e.g. for a reconnecting user. \
+    /// Returns false if the id is currently in use, or if the token is not the one the id was handed out with.
+    pub fn claim(&mut self, id: UserId, token: ReconnectToken) -> bool {
+        self.clear_expired_reservations();
+        if self.in_use.contains(&id) || self.tokens.get(&id) != Some(&token) {
+            return false;
+        }
+        self.in_use.insert(id);
+        self.reserved.remove(&id);
+        true
+    }
+
+    /// Answers a login request. Returns the id of the user if the login succeeded,
+    /// otherwise the client may start a new attempt. \
+    /// A requested id is refused as `UnknownId` unless the token matches, so that only its owner learns whether it is in use.
+    pub fn login(&mut self, request: ClientConnectionMessage) -> (ServerConnectionMessage, Option<UserId>) {
+        match request {
+            ClientConnectionMessage::ConnectNew => {
+                let (id, token) = self.allocate();
+                (ServerConnectionMessage::AssignUserId { id, token }, Some(id))
+            }
+            ClientConnectionMessage::ConnectWithId { id, token } => {
+                if self.claim(id, token) {
+                    (ServerConnectionMessage::AcknowledgeId, Some(id))
+                } else if self.tokens.get(&id) == Some(&token) {
+                    (ServerConnectionMessage::IdAlreadyInUse, None)
+                } else {
+                    (ServerConnectionMessage::UnknownId, None)
+                }
+            }
+            // Admins are authenticated by the client handler, wherever admin sessions are offered.
+            ClientConnectionMessage::Admin { .. } => (ServerConnectionMessage::AdminRejected, None),
+        }
+    }
+
+    /// Frees an id again. Should be called once the user has disconnected.
+    pub fn release(&mut self, id: UserId) {
+        if self.in_use.remove(&id) {
+            match self.reservation_window {
+                Some(window) => { self.reserved.insert(id, Instant::now() + window); }
+                None => { self.tokens.remove(&id); }
+            }
+        }
+    }
+
+    fn clear_expired_reservations(&mut self) {
+        let now = Instant::now();
+        let tokens = &mut self.tokens;
+        self.reserved.retain(|id, expires_at| {
+            let valid = *expires_at > now;
+            if !valid {
+                tokens.remove(id);
+            }
+            valid
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconnect(allocator: &mut IdAllocator, id: UserId, token: ReconnectToken) -> ServerConnectionMessage {
+        allocator.login(ClientConnectionMessage::ConnectWithId { id, token }).0
+    }
+
+    #[test]
+    fn released_ids_can_be_reclaimed_within_the_window() {
+        let mut allocator = IdAllocator::new(Some(Duration::from_secs(60)));
+        let (id, token) = allocator.allocate();
+        assert!(matches!(reconnect(&mut allocator, id, token), ServerConnectionMessage::IdAlreadyInUse));
+        allocator.release(id);
+        assert!(matches!(reconnect(&mut allocator, id, token.wrapping_add(1)), ServerConnectionMessage::UnknownId));
+        assert!(matches!(reconnect(&mut allocator, id, token), ServerConnectionMessage::AcknowledgeId));
+    }
+
+    #[test]
+    fn a_restarted_server_does_not_know_old_ids() {
+        let (id, token) = IdAllocator::new(Some(Duration::from_secs(60))).allocate();
+        let mut restarted = IdAllocator::new(Some(Duration::from_secs(60)));
+        assert!(matches!(reconnect(&mut restarted, id, token), ServerConnectionMessage::UnknownId));
+    }
+}
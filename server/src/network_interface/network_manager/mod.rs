@@ -1,97 +1,388 @@
+pub(super) mod admin;
 mod client_handler;
+mod discovery;
+pub(crate) mod conditioning;
+mod id_allocator;
+mod registration;
+mod server_info;
+mod unix;
+mod websocket;
 use std::collections::{HashMap, HashSet};
-use std::net::SocketAddr;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc};
-use tokio::sync::{Mutex, RwLock};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{oneshot, watch, Mutex, RwLock};
 use serializeable::Serializeable;
-use tokio::net::{TcpListener, ToSocketAddrs, UdpSocket};
-use common::message::{ClientMessage, ClientUdpMessage, ServerMessage};
+use common::message::{ClientMessage, ClientUdpMessage, ServerMessage, ServerUdpMessage};
+use common::message::client_message::ClientConnectionMessage;
+use common::transport::{DatagramSocket, Listener, ServerAddr, Transport};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver as Receiver, UnboundedSender as Sender, UnboundedSender};
+use common::link_quality::LinkMonitor;
 use common::UserId;
-use crate::network_interface::ClientEvent;
+use crate::clock::ServerClock;
+use crate::network_interface::{AdminCall, ClientEvent};
+use crate::network_interface::config::NetworkConfig;
+use crate::network_interface::network_manager::admin::{AdminKeys, AdminLockouts};
 use crate::network_interface::network_manager::client_handler::ClientHandler;
+use crate::network_interface::network_manager::conditioning::{self, SharedLinkConditioners};
+use crate::network_interface::network_manager::discovery::DiscoveryResponder;
+use crate::network_interface::network_manager::id_allocator::IdAllocator;
+use crate::network_interface::network_manager::server_info::ServerInfoSource;
+use crate::network_interface::network_manager::unix::UnixHandler;
+use crate::network_interface::network_manager::websocket::WebSocketHandler;
 
-pub(super) struct NetworkManager {
-    socket_addr_to_user_id: Arc<RwLock<HashMap<SocketAddr, UserId>>>,
-    user_id_to_message_sender: Arc<RwLock<HashMap<UserId, UnboundedSender<ServerMessage>>>>,
+/// The link monitor of every connected client. Uses a std Mutex, so the tick loop can read it without awaiting.
+pub(super) type LinkMonitors = Arc<std::sync::Mutex<HashMap<UserId, LinkMonitor>>>;
 
-    tcp_listener: TcpListener,
-    udp_socket: Arc<UdpSocket>,
+/// Fires when the user is to be disconnected. Every client handler registers one after the login.
+pub(super) type DisconnectSignals = Arc<std::sync::Mutex<HashMap<UserId, oneshot::Sender<()>>>>;
 
-    outgoing_messages: Receiver<(ServerMessage, UserId)>,
-    incoming_messages: Sender<(ClientEvent, UserId)>
+/// Who may not log in any more. Shared with the network interface, through which the admin commands change it.
+#[derive(Default)]
+pub(crate) struct BanList {
+    pub(crate) ips: HashSet<IpAddr>,
+    /// Banned users cannot reclaim their id, not even with the right token.
+    pub(crate) ids: HashSet<UserId>,
 }
 
+pub(crate) type Bans = Arc<std::sync::RwLock<BanList>>;
 
-impl NetworkManager {
+/// The number of tasks that still have messages to write, see `WriteGuard`. Shutting down waits until it drops to 0.
+pub(super) type ActiveWriters = Arc<watch::Sender<usize>>;
 
-    pub(super) async fn launch<A: ToSocketAddrs>(
-        addr: A,
-    ) -> (Sender<(ServerMessage, UserId)>, Receiver<(ClientEvent, UserId)>){
-        let tcp_listener = TcpListener::bind(&addr).await.unwrap();
-        let udp = UdpSocket::bind(addr).await.unwrap();
+/// Counts as an active writer for as long as it lives. \
+/// Every task that writes to a client holds one, so that a shutdown can wait for the last messages to be sent.
+pub(super) struct WriteGuard(ActiveWriters);
+
+impl WriteGuard {
+    pub(super) fn new(writers: &ActiveWriters) -> Self {
+        writers.send_modify(|count| *count += 1);
+        Self(writers.clone())
+    }
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        self.0.send_modify(|count| *count -= 1);
+    }
+}
+
+/// What the network interface hands to the network manager for a user.
+pub(super) enum Outgoing {
+    Message(ServerMessage),
+    Disconnect,
+}
+
+/// Everything the network manager shares with the client handlers.
+pub(super) struct Connections<T: Transport> {
+    pub(super) socket_addr_to_user_id: Arc<RwLock<HashMap<SocketAddr, UserId>>>,
+    pub(super) user_id_to_message_sender: Arc<RwLock<HashMap<UserId, UnboundedSender<ServerMessage>>>>,
+    pub(super) id_allocator: Arc<Mutex<IdAllocator>>,
+    pub(super) link_monitors: LinkMonitors,
+    pub(super) link_conditioners: SharedLinkConditioners,
+    pub(super) disconnect_signals: DisconnectSignals,
+    pub(super) admin_keys: AdminKeys,
+    pub(super) admin_lockouts: AdminLockouts,
+    pub(super) bans: Bans,
+    pub(super) writers: ActiveWriters,
+    pub(super) udp: Arc<T::Datagram>,
+    pub(super) incoming_messages: Sender<(ClientEvent, UserId)>,
+    pub(super) admin_calls: Sender<AdminCall>,
+}
+
+// Derived Clone would require T: Clone.
+impl<T: Transport> Clone for Connections<T> {
+    fn clone(&self) -> Self {
+        Self {
+            socket_addr_to_user_id: self.socket_addr_to_user_id.clone(),
+            user_id_to_message_sender: self.user_id_to_message_sender.clone(),
+            id_allocator: self.id_allocator.clone(),
+            link_monitors: self.link_monitors.clone(),
+            link_conditioners: self.link_conditioners.clone(),
+            disconnect_signals: self.disconnect_signals.clone(),
+            admin_keys: self.admin_keys.clone(),
+            admin_lockouts: self.admin_lockouts.clone(),
+            bans: self.bans.clone(),
+            writers: self.writers.clone(),
+            udp: self.udp.clone(),
+            incoming_messages: self.incoming_messages.clone(),
+            admin_calls: self.admin_calls.clone(),
+        }
+    }
+}
+
+impl<T: Transport> Connections<T> {
+    /// The returned receiver completes once the network interface asks to disconnect the user.
+    pub(super) fn disconnect_signal(&self, id: UserId) -> oneshot::Receiver<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.disconnect_signals.lock().unwrap().insert(id, sender);
+        receiver
+    }
+
+    /// Why the login is refused, if it is. Clients without an ip, e.g. over a unix socket, can only be banned by id.
+    pub(super) fn refusal(&self, ip: Option<IpAddr>, request: &ClientConnectionMessage) -> Option<String> {
+        let bans = self.bans.read().unwrap();
+        let banned_id = matches!(request, ClientConnectionMessage::ConnectWithId { id, .. } if bans.ids.contains(id));
+        let banned_ip = ip.is_some_and(|ip| bans.ips.contains(&ip));
+        (banned_id || banned_ip).then(|| "You are banned from this server".to_string())
+    }
+}
+
+/// What the network interface gets to talk to a launched network manager.
+pub(super) struct NetworkManagerHandle {
+    pub(super) outgoing_messages: Sender<(Outgoing, UserId)>,
+    pub(super) incoming_messages: Receiver<(ClientEvent, UserId)>,
+    pub(super) link_monitors: LinkMonitors,
+    pub(super) admin_calls: Receiver<AdminCall>,
+    pub(super) admin_keys: AdminKeys,
+    pub(super) bans: Bans,
+    pub(super) writers: ActiveWriters,
+    /// The actual addresses, with ephemeral ports resolved.
+    pub(super) bound_addresses: Vec<ServerAddr>,
+}
+
+pub(super) struct NetworkManager<T: Transport> {
+    /// Every bound address has its own datagram socket, which is the one its clients are answered on.
+    endpoints: Vec<(T::Listener, Connections<T>)>,
+    websocket_listener: Option<TcpListener>,
+    unix_handler: Option<UnixHandler<T>>,
+    discovery_responder: Option<DiscoveryResponder<T>>,
+    registry_addr: Option<SocketAddr>,
+    server_info: ServerInfoSource<T>,
+    clock: ServerClock,
+
+    outgoing_messages: Receiver<(Outgoing, UserId)>,
+}
+
+
+impl<T: Transport> NetworkManager<T> {
+    /// How long the id of a disconnected user stays reserved for them.
+    const ID_RESERVATION_WINDOW: Option<Duration> = Some(Duration::from_secs(60));
+
+    pub(super) async fn launch(
+        transport: T,
+        config: NetworkConfig,
+        clock: ServerClock,
+        link_conditioners: SharedLinkConditioners,
+    ) -> io::Result<NetworkManagerHandle> {
+        if config.addresses.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the server needs at least one address to listen on"));
+        }
+        let mut bound = Vec::new();
+        for addr in &config.addresses {
+            bound.push(transport.bind(*addr).await?);
+        }
+        let bound_addresses = bound.iter()
+            .map(|(listener, udp)| Ok(ServerAddr { reliable: listener.local_addr()?, unreliable: udp.local_addr()? }))
+            .collect::<io::Result<Vec<_>>>()?;
+        let websocket_listener = match config.websocket_addr {
+            Some(addr) => Some(TcpListener::bind(addr).await?),
+            None => None,
+        };
+        let admin_keys = AdminKeys::default();
+        if let Some(path) = &config.admin_keys_file {
+            *admin_keys.write().unwrap() = admin::load_keys(path)?;
+        }
         let (in_tx, in_rx) = unbounded_channel();
+        let (admin_tx, admin_rx) = unbounded_channel();
         let (out_tx, out_rx) = unbounded_channel();
-        
+        let link_monitors = LinkMonitors::default();
+        let (socket_addr_to_user_id, user_id_to_message_sender) = (Arc::default(), Arc::default());
+        let disconnect_signals = DisconnectSignals::default();
+        let admin_lockouts = AdminLockouts::default();
+        let bans = Bans::default();
+        // Writers are counted whether or not anyone is waiting for them yet.
+        let writers = Arc::new(watch::channel(0).0);
+        let id_allocator = Arc::new(Mutex::new(IdAllocator::new(Self::ID_RESERVATION_WINDOW)));
+        let endpoints: Vec<_> = bound.into_iter()
+            .map(|(listener, udp)| {
+                let connections = Connections {
+                    socket_addr_to_user_id: Arc::clone(&socket_addr_to_user_id),
+                    user_id_to_message_sender: Arc::clone(&user_id_to_message_sender),
+                    id_allocator: id_allocator.clone(),
+                    link_monitors: link_monitors.clone(),
+                    link_conditioners: link_conditioners.clone(),
+                    disconnect_signals: disconnect_signals.clone(),
+                    admin_keys: admin_keys.clone(),
+                    admin_lockouts: admin_lockouts.clone(),
+                    bans: bans.clone(),
+                    writers: writers.clone(),
+                    udp: Arc::new(udp),
+                    incoming_messages: in_tx.clone(),
+                    admin_calls: admin_tx.clone(),
+                };
+                (listener, connections)
+            })
+            .collect();
+        // Clients connected over unix sockets never use the datagram socket of the transport.
+        let unix_handler = match config.unix_socket {
+            Some(path) => Some(UnixHandler::bind(path, endpoints[0].1.clone(), clock.clone())?),
+            None => None,
+        };
+        let server_info = ServerInfoSource::new(config.description, &bound_addresses, endpoints[0].1.clone());
+        let discovery_responder = if config.discovery { Some(DiscoveryResponder::bind(server_info.clone())?) } else { None };
+
         Self{
-            socket_addr_to_user_id: Default::default(),
-            user_id_to_message_sender: Default::default(),
-            tcp_listener,
-            udp_socket: Arc::new(udp),
-            incoming_messages: in_tx,
+            endpoints,
+            websocket_listener,
+            unix_handler,
+            discovery_responder,
+            registry_addr: config.registry_addr,
+            server_info,
+            clock,
             outgoing_messages: out_rx,
         }.run();
-        
-        (out_tx, in_rx)
+
+        Ok(NetworkManagerHandle { outgoing_messages: out_tx, incoming_messages: in_rx, link_monitors, admin_calls: admin_rx, admin_keys, bans, writers, bound_addresses })
     }
 
     ///Call this to start accepting clients
     pub(super) fn run(self){
-        tokio::spawn(Self::accept_clients(self.tcp_listener, self.incoming_messages.clone(), self.udp_socket.clone(), self.user_id_to_message_sender.clone(), self.socket_addr_to_user_id.clone()));
-        tokio::spawn(Self::receive_messages_udp(self.udp_socket.clone(), self.incoming_messages.clone(), self.socket_addr_to_user_id.clone()));
-        tokio::spawn(Self::distribute_messages(self.user_id_to_message_sender, self.outgoing_messages));
+        // All endpoints share the same maps, so any of them will do for the other kinds of clients.
+        let connections = self.endpoints[0].1.clone();
+        if let Some(websocket_listener) = self.websocket_listener {
+            tokio::spawn(WebSocketHandler::accept_clients(websocket_listener, connections.clone(), self.clock.clone()));
+        }
+        if let Some(unix_handler) = self.unix_handler {
+            unix_handler.run();
+        }
+        if let Some(discovery_responder) = self.discovery_responder {
+            tokio::spawn(discovery_responder.run());
+        }
+        if let Some(registry_addr) = self.registry_addr {
+            tokio::spawn(registration::keep_registered(registry_addr, self.server_info));
+        }
+        for (listener, connections) in self.endpoints {
+            tokio::spawn(Self::receive_messages_udp(UdpHandler { connections: connections.clone(), clock: self.clock.clone() }));
+            tokio::spawn(Self::accept_clients(listener, connections));
+        }
+        let write_guard = WriteGuard::new(&connections.writers);
+        tokio::spawn(Self::distribute_messages(connections.user_id_to_message_sender, connections.disconnect_signals, self.outgoing_messages, write_guard));
     }
-    
-    
+
+
     /// Distributes messages to their respective client thread to be send. \
-    /// This will not return
-    async fn distribute_messages(user_id_to_message_sender: Arc<RwLock<HashMap<UserId, UnboundedSender<ServerMessage>>>>, mut outgoing_messages: Receiver<(ServerMessage, UserId)>) {
-        while let Some((message, user_id)) = outgoing_messages.recv().await {
-            if let Some(sender) = user_id_to_message_sender.read().await.get(&user_id) {
-                sender.send(message).unwrap();
+    /// Returns once the network interface is gone, after disconnecting every remaining user.
+    async fn distribute_messages(
+        user_id_to_message_sender: Arc<RwLock<HashMap<UserId, UnboundedSender<ServerMessage>>>>,
+        disconnect_signals: DisconnectSignals,
+        mut outgoing_messages: Receiver<(Outgoing, UserId)>,
+        _write_guard: WriteGuard,
+    ) {
+        while let Some((outgoing, user_id)) = outgoing_messages.recv().await {
+            match outgoing {
+                Outgoing::Message(message) => {
+                    if let Some(sender) = user_id_to_message_sender.read().await.get(&user_id) {
+                        sender.send(message).unwrap();
+                    }
+                }
+                // The messages already handed to the client are still sent, since only its receiving side stops right away.
+                Outgoing::Disconnect => {
+                    user_id_to_message_sender.write().await.remove(&user_id);
+                    if let Some(signal) = disconnect_signals.lock().unwrap().remove(&user_id) {
+                        let _ = signal.send(());
+                    }
+                }
             }
         }
+        // The writers of the users still send what they have been handed, then stop.
+        user_id_to_message_sender.write().await.clear();
+        for (_, signal) in disconnect_signals.lock().unwrap().drain() {
+            let _ = signal.send(());
+        }
     }
-    
-    /// Open a TcpListener and spawn a client handler for every incoming connection. \
+
+    /// Accept every incoming connection and spawn a client handler for it. \
     /// This will not return
-    async fn accept_clients(
-        listener: TcpListener,
-        incoming_messages: Sender<(ClientEvent, UserId)>,
-        udp: Arc<UdpSocket>,
-        message_senders: Arc<RwLock<HashMap<UserId, UnboundedSender<ServerMessage>>>>,
-        addr_to_user_id: Arc<RwLock<HashMap<SocketAddr, UserId>>>,
-    ) {
-        let connected_ids: Arc<Mutex<HashSet<UserId>>> = Default::default();
-        loop { 
+    async fn accept_clients(listener: T::Listener, connections: Connections<T>) {
+        loop {
             let client_stream= listener.accept().await.unwrap().0;
-            
-            ClientHandler::spawn(udp.clone(), client_stream, incoming_messages.clone(), message_senders.clone(), addr_to_user_id.clone(), connected_ids.clone());
-        } 
+
+            ClientHandler::spawn(client_stream, connections.clone());
+        }
     }
 
     /// Spawn once to receive messages over udp. \
-    /// This will not return
-    async fn receive_messages_udp(udp: Arc<UdpSocket>, incoming_messages: Sender<(ClientEvent, UserId)>, addr_to_user_id: Arc<RwLock<HashMap<SocketAddr, UserId>>>) {
+    /// Packets of conditioned users are handled once their simulated delay has passed, invalid ones are dropped. \
+    /// Returns only if the socket breaks down.
+    async fn receive_messages_udp(handler: UdpHandler<T>) {
         let mut buf = [0u8; 2048];
         loop {
-            let (n, sender) = udp.recv_from(&mut buf).await.unwrap();
-            let msg = ClientUdpMessage::deserialize(&mut &buf[..n]).unwrap();
-            println!("socket_addr: {sender}, list: {:?}", addr_to_user_id.read().await);
+            let (n, sender) = match handler.connections.udp.recv_from(&mut buf).await {
+                Ok(received) => received,
+                // Some platforms report an icmp port unreachable of a previous send on the next receive.
+                Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused) => continue,
+                Err(e) => {
+                    log::error!("Stopped receiving datagrams: {e}");
+                    return;
+                }
+            };
+            let Ok(msg) = ClientUdpMessage::deserialize(&mut &buf[..n]) else {
+                log::debug!("Dropped an invalid datagram from {sender}");
+                continue;
+            };
+            let addr_to_user_id = &handler.connections.socket_addr_to_user_id;
+            log::trace!("socket_addr: {sender}, list: {:?}", addr_to_user_id.read().await);
 
-            if let Some(id) = addr_to_user_id.read().await.get(&sender) {
-                incoming_messages.send((ClientEvent::ClientMessage(ClientMessage::Udp(msg)), *id)).unwrap();
-            } else{ println!("Received message from unknown client. msg: {:?}", msg); }
+            let Some(id) = addr_to_user_id.read().await.get(&sender).copied() else {
+                log::warn!("Received message from unknown client. msg: {:?}", msg);
+                continue;
+            };
+            let delays = handler.connections.link_conditioners.lock().unwrap().incoming(id, n);
+            match delays {
+                None => handler.handle(msg, sender, id).await,
+                Some(delays) => {
+                    for delay in delays {
+                        let (handler, msg) = (handler.clone(), msg.clone());
+                        tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            handler.handle(msg, sender, id).await;
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Everything needed to answer or forward a message received over udp.
+struct UdpHandler<T: Transport> {
+    connections: Connections<T>,
+    clock: ServerClock,
+}
+
+impl<T: Transport> Clone for UdpHandler<T> {
+    fn clone(&self) -> Self {
+        Self { connections: self.connections.clone(), clock: self.clock.clone() }
+    }
+}
+
+/// Time requests and pings are answered right away, so that the tick loop does not distort the measurement. \
+/// Returns None for every other message.
+fn immediate_answer(msg: &ClientUdpMessage, clock: &ServerClock) -> Option<ServerUdpMessage> {
+    match msg {
+        ClientUdpMessage::TimeRequest(client_send_time) => Some(ServerUdpMessage::TimeResponse(clock.time_response(*client_send_time, clock.now()))),
+        ClientUdpMessage::Ping(sequence) => Some(ServerUdpMessage::Pong(*sequence)),
+        _ => None,
+    }
+}
+
+impl<T: Transport> UdpHandler<T> {
+    async fn handle(&self, msg: ClientUdpMessage, sender: SocketAddr, id: UserId) {
+        let connections = &self.connections;
+        if let Some(answer) = immediate_answer(&msg, &self.clock) {
+            conditioning::send_to(&connections.udp, answer.serialize(), sender, id, &connections.link_conditioners).await;
+            return;
+        }
+        match msg {
+            ClientUdpMessage::Pong(sequence) => {
+                if let Some(monitor) = connections.link_monitors.lock().unwrap().get_mut(&id) {
+                    monitor.pong_received(sequence);
+                }
+            }
+            msg => connections.incoming_messages.send((ClientEvent::ClientMessage(ClientMessage::Udp(msg)), id)).unwrap(),
         }
     }
-}
\ No newline at end of file
+}
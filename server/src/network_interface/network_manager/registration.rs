@@ -0,0 +1,21 @@
+use std::net::SocketAddr;
+use common::registry::{self, RegistryRequest, RegistryResponse, HEARTBEAT_INTERVAL};
+use common::transport::Transport;
+use crate::network_interface::network_manager::server_info::ServerInfoSource;
+
+/// Keeps the server registered with the registry by sending it heartbeats. \
+/// A registry that is unreachable for a while is retried with the next heartbeat. \
+/// This will not return
+pub(super) async fn keep_registered<T: Transport>(registry_addr: SocketAddr, server_info: ServerInfoSource<T>) {
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        let heartbeat = RegistryRequest::Heartbeat(server_info.current().await);
+        match registry::request(registry_addr, heartbeat).await {
+            Ok(RegistryResponse::Registered) => {}
+            Ok(RegistryResponse::Rejected(reason)) => log::warn!("The registry at {registry_addr} rejected the heartbeat: {reason}"),
+            Ok(response) => log::warn!("Unexpected response from the registry at {registry_addr}: {response:?}"),
+            Err(e) => log::warn!("Failed to send a heartbeat to the registry at {registry_addr}: {e}"),
+        }
+    }
+}
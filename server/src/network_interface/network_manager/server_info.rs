@@ -0,0 +1,35 @@
+use common::discovery::{ServerEndpoint, ServerInfo};
+use common::transport::{ServerAddr, Transport};
+use crate::network_interface::config::ServerDescription;
+use crate::network_interface::network_manager::Connections;
+
+/// Puts together the current server info for discovery and the registry.
+pub(super) struct ServerInfoSource<T: Transport> {
+    description: ServerDescription,
+    endpoints: Vec<ServerEndpoint>,
+    connections: Connections<T>,
+}
+
+// Derived Clone would require T: Clone.
+impl<T: Transport> Clone for ServerInfoSource<T> {
+    fn clone(&self) -> Self {
+        Self { description: self.description.clone(), endpoints: self.endpoints.clone(), connections: self.connections.clone() }
+    }
+}
+
+impl<T: Transport> ServerInfoSource<T> {
+    pub(super) fn new(description: ServerDescription, bound_addresses: &[ServerAddr], connections: Connections<T>) -> Self {
+        let endpoints = bound_addresses.iter().copied().map(ServerEndpoint::from).collect();
+        Self { description, endpoints, connections }
+    }
+
+    pub(super) async fn current(&self) -> ServerInfo {
+        ServerInfo {
+            name: self.description.name.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            player_count: self.connections.user_id_to_message_sender.read().await.len() as u32,
+            capacity: self.description.capacity,
+            endpoints: self.endpoints.clone(),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serializeable::Serializeable;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixDatagram, UnixListener};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::RwLock;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver as Receiver};
+use common::link_quality::LinkMonitor;
+use common::message::{ClientMessage, ClientTcpMessage, ClientUdpMessage, ServerMessage, ServerTcpMessage, ServerUdpMessage};
+use common::message::send_message::TcpSendable;
+use common::transport::unix::{client_datagram_path, server_datagram_path};
+use common::transport::Transport;
+use common::UserId;
+use crate::clock::ServerClock;
+use crate::network_interface::{ClientEvent, PeerAddress};
+use crate::network_interface::network_manager::admin;
+use crate::network_interface::network_manager::client_handler::{ClientHandler, Login};
+use crate::network_interface::network_manager::{immediate_answer, Connections, WriteGuard};
+
+/// Accepts clients on the same host over a unix stream socket, with a unix datagram socket for the unreliable channel. \
+/// See `common::transport::unix` for how the sockets are named.
+pub(super) struct UnixHandler<T: Transport> {
+    path: PathBuf,
+    listener: UnixListener,
+    datagram: Arc<UnixDatagram>,
+    /// The datagram path of every logged in client.
+    path_to_user_id: Arc<RwLock<HashMap<PathBuf, UserId>>>,
+    connections: Connections<T>,
+    clock: ServerClock,
+}
+
+impl<T: Transport> UnixHandler<T> {
+    /// Stale socket files of a previous run are removed first.
+    pub(super) fn bind(path: PathBuf, connections: Connections<T>, clock: ServerClock) -> io::Result<Self> {
+        let datagram_path = server_datagram_path(&path);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&datagram_path);
+        Ok(Self {
+            listener: UnixListener::bind(&path)?,
+            datagram: Arc::new(UnixDatagram::bind(&datagram_path)?),
+            path,
+            path_to_user_id: Default::default(),
+            connections,
+            clock,
+        })
+    }
+
+    pub(super) fn run(self) {
+        tokio::spawn(Self::receive_datagrams(self.datagram.clone(), self.path_to_user_id.clone(), self.connections.clone(), self.clock.clone()));
+        tokio::spawn(self.accept_clients());
+    }
+
+    /// Accept every incoming connection and spawn a task for it. \
+    /// This will not return
+    async fn accept_clients(self) {
+        loop {
+            let (mut stream, _) = self.listener.accept().await.unwrap();
+            let (path, datagram, path_to_user_id, connections) = (self.path.clone(), self.datagram.clone(), self.path_to_user_id.clone(), self.connections.clone());
+            tokio::spawn(async move {
+                let id = match ClientHandler::<T>::login_procedure(&mut stream, &connections, None).await {
+                    Some(Login::User(id)) => id,
+                    Some(Login::Admin(role)) => {
+                        log::info!("Admin session over the unix socket as {role:?}");
+                        return admin::run_session(stream, role, &connections).await;
+                    }
+                    Some(Login::Refused(reason)) => {
+                        log::warn!("Refused a client over the unix socket: {reason}");
+                        return;
+                    }
+                    None => return,
+                };
+                let datagram_path = client_datagram_path(&path, rand::random());
+                let bind_here = ServerTcpMessage::UnixDatagramPath(datagram_path.to_string_lossy().into_owned());
+                if bind_here.send(&mut stream).await.is_err() {
+                    connections.id_allocator.lock().await.release(id);
+                    return;
+                }
+                path_to_user_id.write().await.insert(datagram_path.clone(), id);
+                connections.link_monitors.lock().unwrap().insert(id, Default::default());
+
+                let (outgoing_messages_sender, outgoing_messages) = unbounded_channel::<ServerMessage>();
+                connections.user_id_to_message_sender.write().await.insert(id, outgoing_messages_sender);
+                let disconnect_signal = connections.disconnect_signal(id);
+                let write_guard = WriteGuard::new(&connections.writers);
+                connections.incoming_messages.send((ClientEvent::Connected { address: PeerAddress::Unix(datagram_path.clone()) }, id)).unwrap();
+
+                let (reader, writer) = stream.into_split();
+                tokio::spawn(Self::probe(connections.clone(), datagram.clone(), datagram_path.clone(), id));
+                tokio::spawn(Self::send_messages(outgoing_messages, writer, datagram, datagram_path.clone(), write_guard));
+                tokio::select! {
+                    _ = Self::receive_stream(reader, &connections, id) => {}
+                    _ = disconnect_signal => {}
+                }
+
+                // Dropping the message writer ends the sending task of this client.
+                connections.user_id_to_message_sender.write().await.remove(&id);
+                connections.disconnect_signals.lock().unwrap().remove(&id);
+                path_to_user_id.write().await.remove(&datagram_path);
+                connections.id_allocator.lock().await.release(id);
+                connections.link_monitors.lock().unwrap().remove(&id);
+                connections.incoming_messages.send((ClientEvent::Disconnected, id)).unwrap();
+            });
+        }
+    }
+
+    /// Regularly pings the client to measure the connection, until it disconnects.
+    async fn probe(connections: Connections<T>, datagram: Arc<UnixDatagram>, datagram_path: PathBuf, id: UserId) {
+        let mut interval = tokio::time::interval(LinkMonitor::PROBE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(sequence) = connections.link_monitors.lock().unwrap().get_mut(&id).map(LinkMonitor::next_probe) else { break };
+            let _ = datagram.send_to(&ServerUdpMessage::Ping(sequence).serialize(), &datagram_path).await;
+        }
+    }
+
+    async fn receive_stream(mut reader: OwnedReadHalf, connections: &Connections<T>, id: UserId) {
+        while let Ok(msg) = ClientTcpMessage::async_deserialize(&mut reader).await {
+            connections.incoming_messages.send((ClientEvent::ClientMessage(ClientMessage::from(msg)), id)).unwrap();
+        }
+    }
+
+    async fn send_messages(mut outgoing_messages: Receiver<ServerMessage>, mut writer: OwnedWriteHalf, datagram: Arc<UnixDatagram>, datagram_path: PathBuf, _write_guard: WriteGuard) {
+        while let Some(message) = outgoing_messages.recv().await {
+            match message {
+                ServerMessage::Tcp(message) => {
+                    if writer.write_all(&message.serialize()).await.is_err() {
+                        break;
+                    }
+                }
+                // Like udp, a datagram the client is not (yet) bound for is lost.
+                ServerMessage::Udp(message) => { let _ = datagram.send_to(&message.serialize(), &datagram_path).await; }
+            }
+        }
+    }
+
+    /// Spawn once to receive the datagrams of all unix socket clients. \
+    /// Returns only if the socket breaks down.
+    async fn receive_datagrams(datagram: Arc<UnixDatagram>, path_to_user_id: Arc<RwLock<HashMap<PathBuf, UserId>>>, connections: Connections<T>, clock: ServerClock) {
+        let mut buf = [0u8; 2048];
+        loop {
+            let (n, sender) = match datagram.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    log::error!("Stopped receiving unix datagrams: {e}");
+                    return;
+                }
+            };
+            let Some(sender) = sender.as_pathname().map(Path::to_path_buf) else { continue };
+            let Some(id) = path_to_user_id.read().await.get(&sender).copied() else {
+                log::warn!("Received datagram from unknown unix socket {}", sender.display());
+                continue;
+            };
+            let Ok(msg) = ClientUdpMessage::deserialize(&mut &buf[..n]) else { continue };
+            if let Some(answer) = immediate_answer(&msg, &clock) {
+                let _ = datagram.send_to(&answer.serialize(), &sender).await;
+                continue;
+            }
+            match msg {
+                ClientUdpMessage::Pong(sequence) => {
+                    if let Some(monitor) = connections.link_monitors.lock().unwrap().get_mut(&id) {
+                        monitor.pong_received(sequence);
+                    }
+                }
+                msg => connections.incoming_messages.send((ClientEvent::ClientMessage(ClientMessage::Udp(msg)), id)).unwrap(),
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::net::SocketAddr;
+use futures_util::{SinkExt, StreamExt};
+use futures_util::stream::{SplitSink, SplitStream};
+use serializeable::Serializeable;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver as Receiver, UnboundedSender as Sender};
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use common::message::{ClientMessage, ClientTcpMessage, ClientUdpMessage, ServerMessage, ServerTcpMessage};
+use common::message::client_message::ClientConnectionMessage;
+use common::message::server_message::ServerConnectionMessage;
+use common::transport::Transport;
+use common::UserId;
+use crate::clock::ServerClock;
+use crate::network_interface::{ClientEvent, PeerAddress};
+use crate::network_interface::network_manager::{immediate_answer, Connections, WriteGuard};
+
+/// Every binary frame holds exactly one message. Unreliable messages fall back to the websocket as well.
+pub(super) struct WebSocketHandler<T: Transport> {
+    id: UserId,
+    ws_writer: SplitSink<WebSocketStream<TcpStream>, Message>,
+    ws_reader: SplitStream<WebSocketStream<TcpStream>>,
+    outgoing_messages: Receiver<ServerMessage>,
+    outgoing_messages_sender: Sender<ServerMessage>,
+    disconnect_signal: oneshot::Receiver<()>,
+    write_guard: WriteGuard,
+    connections: Connections<T>,
+    clock: ServerClock,
+}
+
+impl<T: Transport> WebSocketHandler<T> {
+    /// Accept every incoming websocket connection and spawn a handler for it. \
+    /// This will not return
+    pub(super) async fn accept_clients(listener: TcpListener, connections: Connections<T>, clock: ServerClock) {
+        loop {
+            let (stream, peer_addr) = listener.accept().await.unwrap();
+            tokio::spawn(Self::handle(stream, peer_addr, connections.clone(), clock.clone()));
+        }
+    }
+
+    async fn handle(stream: TcpStream, peer_addr: SocketAddr, connections: Connections<T>, clock: ServerClock) {
+        let mut ws = match tokio_tungstenite::accept_async(stream).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                log::warn!("Websocket handshake with {peer_addr} failed: {e}");
+                return;
+            }
+        };
+        let Some(id) = Self::login_procedure(&mut ws, &connections, peer_addr).await else { return };
+
+        let (outgoing_messages_sender, outgoing_messages) = unbounded_channel::<ServerMessage>();
+        connections.user_id_to_message_sender.write().await.insert(id, outgoing_messages_sender.clone());
+        let disconnect_signal = connections.disconnect_signal(id);
+        let write_guard = WriteGuard::new(&connections.writers);
+        connections.incoming_messages.send((ClientEvent::Connected { address: PeerAddress::Socket(peer_addr) }, id)).unwrap();
+
+        let (ws_writer, ws_reader) = ws.split();
+        Self { id, ws_writer, ws_reader, outgoing_messages, outgoing_messages_sender, disconnect_signal, write_guard, connections, clock }.run().await
+    }
+
+    /// Same procedure as over tcp, with one connection message per frame. \
+    /// Returns None if the client left before it was logged in, or is banned.
+    async fn login_procedure(ws: &mut WebSocketStream<TcpStream>, connections: &Connections<T>, peer_addr: SocketAddr) -> Option<UserId> {
+        loop {
+            let frame = Self::next_binary_frame(ws).await?;
+            let request = ClientConnectionMessage::deserialize(&mut &frame[..]).ok()?;
+            if let Some(reason) = connections.refusal(Some(peer_addr.ip()), &request) {
+                log::info!("Refused websocket client {peer_addr}: {reason}");
+                let _ = ws.send(Message::Binary(ServerConnectionMessage::Refused(reason).serialize().into())).await;
+                return None;
+            }
+            let (response, id) = connections.id_allocator.lock().await.login(request);
+            if ws.send(Message::Binary(response.serialize().into())).await.is_err() {
+                if let Some(id) = id {
+                    connections.id_allocator.lock().await.release(id);
+                }
+                return None;
+            }
+            if id.is_some() {
+                return id;
+            }
+        }
+    }
+
+    /// Runs until the client disconnects and cleans up after it.
+    async fn run(self) {
+        let Self { id, ws_writer, mut ws_reader, outgoing_messages, outgoing_messages_sender, disconnect_signal, write_guard, connections, clock } = self;
+        tokio::spawn(Self::send_messages(outgoing_messages, ws_writer, write_guard));
+
+        let receive = async {
+            while let Some(frame) = Self::next_binary_frame(&mut ws_reader).await {
+                let Ok(msg) = ClientTcpMessage::deserialize(&mut &frame[..]) else { break };
+                let msg = ClientMessage::from(msg);
+                if let ClientMessage::Udp(udp_msg) = &msg {
+                    if let Some(answer) = immediate_answer(udp_msg, &clock) {
+                        let _ = outgoing_messages_sender.send(ServerMessage::Udp(answer));
+                        continue;
+                    }
+                }
+                match msg {
+                    // The connection is not probed, so there is nothing to measure.
+                    ClientMessage::Udp(ClientUdpMessage::Pong(_)) => {}
+                    msg => connections.incoming_messages.send((ClientEvent::ClientMessage(msg), id)).unwrap(),
+                }
+            }
+        };
+        tokio::select! {
+            _ = receive => {}
+            _ = disconnect_signal => {}
+        }
+
+        // Dropping the message writers ends the sending task of this client.
+        drop(outgoing_messages_sender);
+        connections.user_id_to_message_sender.write().await.remove(&id);
+        connections.disconnect_signals.lock().unwrap().remove(&id);
+        connections.id_allocator.lock().await.release(id);
+        connections.incoming_messages.send((ClientEvent::Disconnected, id)).unwrap();
+    }
+
+    async fn send_messages(mut outgoing_messages: Receiver<ServerMessage>, mut ws_writer: SplitSink<WebSocketStream<TcpStream>, Message>, _write_guard: WriteGuard) {
+        while let Some(message) = outgoing_messages.recv().await {
+            let message = match message {
+                ServerMessage::Tcp(message) => message,
+                ServerMessage::Udp(message) => ServerTcpMessage::Unreliable(message),
+            };
+            if ws_writer.send(Message::Binary(message.serialize().into())).await.is_err() {
+                break;
+            }
+        }
+        let _ = ws_writer.close().await;
+    }
+
+    /// Skips pings and text frames. Returns None once the connection is closed.
+    async fn next_binary_frame<S>(ws: &mut S) -> Option<Vec<u8>>
+    where S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin {
+        loop {
+            match ws.next().await?.ok()? {
+                Message::Binary(frame) => return Some(frame.to_vec()),
+                Message::Close(_) => return None,
+                _ => continue,
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use common::message::{ServerTcpMessage, ServerUdpMessage};
+use common::pubsub::{is_pattern, topic_matches, DeliveryMode, Publication};
+use common::UserId;
+use crate::server::Server;
+
+/// Who may publish to a topic, besides the server itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) enum Publishers {
+    #[default]
+    ServerOnly,
+    Anyone,
+    /// The users with these names. Ids are drawn anew for every login, so they cannot be configured up front.
+    /// Names are unique among the connected users, but whoever connects first may take one.
+    Users(HashSet<String>),
+}
+
+impl Publishers {
+    fn permit(&self, name: &str) -> bool {
+        match self {
+            Publishers::ServerOnly => false,
+            Publishers::Anyone => true,
+            Publishers::Users(names) => names.contains(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TopicConfig {
+    pub(crate) delivery: DeliveryMode,
+    pub(crate) publishers: Publishers,
+}
+
+/// How the topics are delivered and who may publish to them. \
+/// A topic uses the config of the first pattern that matches it. Unconfigured topics are reliable and only the server publishes to them.
+#[derive(Default)]
+pub(crate) struct Topics {
+    configs: Vec<(String, TopicConfig)>,
+    default: TopicConfig,
+}
+
+impl Topics {
+    /// Reads one pattern per line, followed by its delivery mode and who may publish to it,
+    /// e.g. `game/positions/* unreliable server` or `chat/** reliable alice,bob`. \
+    /// Publishers are `server`, `anyone` or a comma separated list of user names. Empty lines and lines starting with # are skipped.
+    pub(crate) fn load(path: &Path) -> io::Result<Self> {
+        let mut topics = Self::default();
+        for (number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (pattern, config) = parse_line(line)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: expected `<pattern> <reliable|unreliable> <server|anyone|names>`", path.display(), number + 1)))?;
+            topics.configure(pattern, config);
+        }
+        Ok(topics)
+    }
+
+    /// Replaces the config of the pattern, or adds it after the existing ones.
+    pub(crate) fn configure(&mut self, pattern: impl Into<String>, config: TopicConfig) {
+        let pattern = pattern.into();
+        match self.configs.iter_mut().find(|(configured, _)| *configured == pattern) {
+            Some((_, existing)) => *existing = config,
+            None => self.configs.push((pattern, config)),
+        }
+    }
+
+    pub(crate) fn config(&self, topic: &str) -> &TopicConfig {
+        self.configs.iter()
+            .find(|(pattern, _)| topic_matches(pattern, topic))
+            .map_or(&self.default, |(_, config)| config)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(&str, TopicConfig)> {
+    let mut fields = line.split_whitespace();
+    let pattern = fields.next()?;
+    let delivery = match fields.next()? {
+        "reliable" => DeliveryMode::Reliable,
+        "unreliable" => DeliveryMode::Unreliable,
+        _ => return None,
+    };
+    let publishers = match fields.next()? {
+        "server" => Publishers::ServerOnly,
+        "anyone" => Publishers::Anyone,
+        names => Publishers::Users(names.split(',').map(|name| Some(name.to_string()).filter(|name| !name.is_empty())).collect::<Option<_>>()?),
+    };
+    fields.next().is_none().then_some((pattern, TopicConfig { delivery, publishers }))
+}
+
+impl Server {
+    /// Sends the payload to every subscriber of the topic, using the delivery mode of the topic. \
+    /// Returns the number of subscribers.
+    pub(crate) fn publish(&mut self, topic: &str, payload: Vec<u8>) -> usize {
+        self.fan_out(Publication { topic: topic.to_string(), publisher: None, payload })
+    }
+
+    /// Publishes for a client, if the config of the topic permits it.
+    pub(crate) fn handle_publish(&mut self, publisher: UserId, topic: String, payload: Vec<u8>) {
+        if is_pattern(&topic) {
+            self.network_interface.send_tcp(ServerTcpMessage::Text(format!("Cannot publish to the pattern {topic}")), publisher);
+            return;
+        }
+        if !self.topics.config(&topic).publishers.permit(&self.state.name_of(publisher)) {
+            self.network_interface.send_tcp(ServerTcpMessage::Text(format!("You may not publish to {topic}")), publisher);
+            return;
+        }
+        self.fan_out(Publication { topic, publisher: Some(publisher), payload });
+    }
+
+    fn fan_out(&mut self, publication: Publication) -> usize {
+        let subscribers = self.interests.subscribers(&publication.topic);
+        match self.topics.config(&publication.topic).delivery {
+            DeliveryMode::Reliable => {
+                for id in &subscribers {
+                    self.network_interface.send_tcp(ServerTcpMessage::Published(publication.clone()), *id);
+                }
+            }
+            DeliveryMode::Unreliable => {
+                for id in &subscribers {
+                    self.network_interface.send_udp(ServerUdpMessage::Published(publication.clone()), *id);
+                }
+            }
+        }
+        subscribers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_config_lines() {
+        let (pattern, config) = parse_line("game/positions/*  unreliable server").unwrap();
+        assert_eq!(pattern, "game/positions/*");
+        assert_eq!(config.delivery, DeliveryMode::Unreliable);
+        assert_eq!(config.publishers, Publishers::ServerOnly);
+        let names = HashSet::from(["alice".to_string(), "bob".to_string()]);
+        assert_eq!(parse_line("chat/** reliable alice,bob").unwrap().1.publishers, Publishers::Users(names));
+        assert!(parse_line("chat/** reliable").is_none());
+        assert!(parse_line("chat/** sometimes anyone").is_none());
+        assert!(parse_line("chat/** reliable alice,,bob").is_none());
+        assert!(parse_line("chat/** reliable anyone extra").is_none());
+    }
+
+    #[test]
+    fn the_first_matching_pattern_wins() {
+        let mut topics = Topics::default();
+        topics.configure("game/*/chat", TopicConfig { delivery: DeliveryMode::Reliable, publishers: Publishers::Anyone });
+        topics.configure("game/**", TopicConfig { delivery: DeliveryMode::Unreliable, publishers: Publishers::ServerOnly });
+        assert_eq!(topics.config("game/7/chat").publishers, Publishers::Anyone);
+        assert_eq!(topics.config("game/7/positions").delivery, DeliveryMode::Unreliable);
+        assert_eq!(topics.config("news").publishers, Publishers::ServerOnly, "unconfigured topics use the default");
+    }
+
+    #[test]
+    fn permits_publishers_by_name() {
+        let publishers = Publishers::Users(HashSet::from(["alice".to_string()]));
+        assert!(publishers.permit("alice"));
+        assert!(!publishers.permit("bob"));
+        assert!(Publishers::Anyone.permit("bob"));
+        assert!(!Publishers::ServerOnly.permit("alice"));
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, VecDeque};
+use common::replication::{EntityId, Replicated, Snapshot, Tick, WorldState};
+use common::{InputSequence, UserId};
+
+/// Holds all replicated entities and tracks which state every client has acknowledged,
+/// so that only the differences have to be sent each tick.
+#[derive(Default)]
+pub(crate) struct Replication {
+    world: WorldState,
+    next_entity_id: EntityId,
+    clients: HashMap<UserId, ClientBaseline>,
+}
+
+#[derive(Default)]
+struct ClientBaseline {
+    acknowledged: Option<Tick>,
+    /// States sent to the client that have not been superseded by an acknowledgement yet.
+    sent: VecDeque<SentState>,
+}
+
+/// What a snapshot told the client.
+struct SentState {
+    tick: Tick,
+    world: WorldState,
+    last_processed_input: Option<InputSequence>,
+}
+
+impl ClientBaseline {
+    fn baseline(&self) -> Option<&SentState> {
+        let acknowledged = self.acknowledged?;
+        self.sent.iter().find(|sent| sent.tick == acknowledged)
+    }
+}
+
+impl Replication {
+    /// If a client stops acknowledging, the oldest states get dropped and it eventually receives a full snapshot again.
+    const MAX_UNACKNOWLEDGED: usize = 64;
+
+    pub(crate) fn spawn(&mut self) -> EntityId {
+        let entity = self.next_entity_id;
+        self.next_entity_id += 1;
+        self.world.insert_entity(entity);
+        entity
+    }
+
+    pub(crate) fn despawn(&mut self, entity: EntityId) -> bool {
+        self.world.remove_entity(entity)
+    }
+
+    /// Returns false if the entity does not exist.
+    pub(crate) fn set_component<C: Replicated>(&mut self, entity: EntityId, component: &C) -> bool {
+        self.world.set_component(entity, component)
+    }
+
+    pub(crate) fn component<C: Replicated>(&self, entity: EntityId) -> Option<C> {
+        self.world.component(entity)
+    }
+
+    pub(crate) fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.world.entities()
+    }
+
+    pub(crate) fn add_client(&mut self, id: UserId) {
+        self.clients.insert(id, Default::default());
+    }
+
+    pub(crate) fn remove_client(&mut self, id: UserId) {
+        self.clients.remove(&id);
+    }
+
+    pub(crate) fn acknowledge(&mut self, id: UserId, tick: Tick) {
+        let Some(client) = self.clients.get_mut(&id) else { return };
+        if client.acknowledged.is_some_and(|acknowledged| acknowledged >= tick)
+            || !client.sent.iter().any(|sent| sent.tick == tick) {
+            return;
+        }
+        client.acknowledged = Some(tick);
+        client.sent.retain(|sent| sent.tick >= tick);
+    }
+
+    /// Builds the snapshot of this tick for every client, each relative to the last state that client acknowledged. \
+    /// Entities for which `is_relevant` returns false are left out of a clients snapshot. \
+    /// Clients for which neither the entities nor `last_processed_input` changed since their acknowledged state get no snapshot.
+    pub(crate) fn snapshots(
+        &mut self,
+        tick: Tick,
+        is_relevant: impl Fn(UserId, EntityId) -> bool,
+        last_processed_input: impl Fn(UserId) -> Option<InputSequence>,
+    ) -> Vec<(UserId, Snapshot)> {
+        let empty = WorldState::default();
+        let mut snapshots = Vec::with_capacity(self.clients.len());
+        for (id, client) in &mut self.clients {
+            let visible = self.world.filtered(|entity| is_relevant(*id, entity));
+            let last_processed_input = last_processed_input(*id);
+            let mut snapshot = match client.baseline() {
+                Some(baseline) => {
+                    let snapshot = visible.delta_from(&baseline.world, tick, Some(baseline.tick));
+                    if snapshot.is_empty() && baseline.last_processed_input == last_processed_input {
+                        continue;
+                    }
+                    snapshot
+                }
+                None => visible.delta_from(&empty, tick, None),
+            };
+            snapshot.last_processed_input = last_processed_input;
+            client.sent.push_back(SentState { tick, world: visible, last_processed_input });
+            if client.sent.len() > Self::MAX_UNACKNOWLEDGED {
+                let dropped = client.sent.pop_front().unwrap().tick;
+                if client.acknowledged == Some(dropped) {
+                    client.acknowledged = None;
+                }
+            }
+            snapshots.push((*id, snapshot));
+        }
+        snapshots
+    }
+}
@@ -0,0 +1,26 @@
+use common::interest::Interest;
+use common::rpc::RpcError;
+use common::services::{directory, SubscriberCount, Subscriptions, Whois};
+use common::UserId;
+use crate::server::Server;
+
+impl directory::Handler for Server {
+    fn whois(&mut self, _: UserId, Whois { name }: Whois) -> Result<UserId, RpcError> {
+        self.state.find_by_name(&name).ok_or_else(|| RpcError::Failed(format!("There is no user named {name}")))
+    }
+
+    fn subscriptions(&mut self, caller: UserId, _: Subscriptions) -> Result<Vec<String>, RpcError> {
+        let mut topics: Vec<String> = self.interests.subscriptions(caller)
+            .filter_map(|interest| match interest {
+                Interest::Topic(topic) => Some(topic.clone()),
+                _ => None,
+            })
+            .collect();
+        topics.sort();
+        Ok(topics)
+    }
+
+    fn subscriber_count(&mut self, _: UserId, SubscriberCount { topic }: SubscriberCount) -> Result<u32, RpcError> {
+        Ok(self.interests.subscribers(&topic).len() as u32)
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use serializeable::Serializeable;
+use common::message::ServerTcpMessage;
+use common::rpc::{CallId, Rpc, RpcError, RpcResult};
+use common::UserId;
+use crate::server::Server;
+
+mod directory;
+
+type Handler = Rc<dyn Fn(&mut Server, UserId, &[u8]) -> RpcResult>;
+
+/// The handler of every method, see `common::rpc`. Handlers run on the tick loop and answer right away.
+#[derive(Default)]
+pub(crate) struct RpcHandlers {
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl RpcHandlers {
+    /// Replaces the previous handler of the method, if there was one.
+    pub(crate) fn register<R: Rpc + 'static>(&mut self, handler: fn(&mut Server, UserId, R) -> Result<R::Response, RpcError>) {
+        self.handlers.insert(R::METHOD, Rc::new(move |server: &mut Server, caller: UserId, payload: &[u8]| {
+            let Ok(request) = R::deserialize(&mut &payload[..]) else { return RpcResult::Err(RpcError::InvalidPayload) };
+            match handler(server, caller, request) {
+                Ok(response) => RpcResult::Ok(response.serialize()),
+                Err(e) => RpcResult::Err(e),
+            }
+        }));
+    }
+
+    /// The methods every server offers.
+    pub(crate) fn with_builtins() -> Self {
+        let mut handlers = Self::default();
+        handlers.register::<common::services::directory::Request>(|server, caller, request| request.dispatch(server, caller));
+        handlers
+    }
+}
+
+impl Server {
+    /// Runs the handler of the method and sends its result back to the caller.
+    pub(crate) fn handle_rpc_request(&mut self, caller: UserId, id: CallId, method: String, payload: Vec<u8>) {
+        let handler = self.rpc.handlers.get(method.as_str()).cloned();
+        let result = match handler {
+            Some(handler) => handler(self, caller, &payload),
+            None => RpcResult::Err(RpcError::UnknownMethod(method)),
+        };
+        self.network_interface.send_tcp(ServerTcpMessage::Response { id, result }, caller);
+    }
+}
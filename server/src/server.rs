@@ -1,51 +1,385 @@
 use std::collections::HashMap;
-use std::thread::sleep;
 use std::time::{Duration, Instant};
-use tokio::net::ToSocketAddrs;
+use serializeable::Serializeable;
+use common::message::{ServerTcpMessage, ServerUdpMessage};
+use common::transport::{QuicTransport, TcpUdpTransport, Transport, TransportKind};
+use common::replication::{SnapshotPart, Tick};
 use common::UserId;
-use crate::network_interface::NetworkInterface;
+use crate::admin::AdminConsole;
+use crate::avatars::Avatars;
+use crate::clock::ServerClock;
+use crate::input_buffer::InputBuffer;
+use crate::interest::{InterestEvent, InterestManager};
+use crate::network_interface::{NetworkInterface, PeerAddress};
+use crate::network_interface::config::NetworkConfig;
+use crate::pubsub::Topics;
+use crate::replication::Replication;
+use crate::rpc::RpcHandlers;
 
 pub(crate) struct Server {
-    network_interface: NetworkInterface,
-    state: ServerState,
+    pub(crate) network_interface: NetworkInterface,
+    pub(crate) replication: Replication,
+    pub(crate) avatars: Avatars,
+    pub(crate) inputs: InputBuffer,
+    pub(crate) interests: InterestManager,
+    pub(crate) clock: ServerClock,
+    pub(crate) state: ServerState,
+    pub(crate) rpc: RpcHandlers,
+    pub(crate) topics: Topics,
+    pub(crate) admin_console: Option<AdminConsole>,
+    /// Cleared by the shutdown admin command.
+    pub(crate) running: bool,
+    pub(crate) tick: Tick,
     last_tick: Instant,
 }
 
 impl Server {
-    const TICK_INTERVAL: Duration = Duration::from_millis(10);
-    pub(crate) async fn new<A: ToSocketAddrs>(addr: A) -> Self {
-        let network_interface = NetworkInterface::create(addr);
+    const TICK_INTERVAL: Duration = common::TICK_INTERVAL;
+    /// Creates a server using the transport selected in the config.
+    pub(crate) async fn new(config: NetworkConfig) -> std::io::Result<Self> {
+        match config.transport.clone() {
+            TransportKind::TcpUdp => Self::with_transport(TcpUdpTransport, config).await,
+            TransportKind::Quic { server_name, certificate_path } => {
+                let transport = QuicTransport::self_signed(&server_name);
+                std::fs::write(&certificate_path, transport.certificate())?;
+                Self::with_transport(transport, config).await
+            }
+        }
+    }
 
-        Self{
+    /// Creates a server on top of a custom transport, e.g. an in-memory one for tests.
+    pub(crate) async fn with_transport<T: Transport>(transport: T, config: NetworkConfig) -> std::io::Result<Self> {
+        let clock = ServerClock::new();
+        let network_interface = NetworkInterface::create_with_transport(transport, config, clock.clone()).await?;
+
+        Ok(Self{
             state: Default::default(),
+            rpc: RpcHandlers::with_builtins(),
+            topics: Default::default(),
             network_interface,
+            replication: Default::default(),
+            avatars: Default::default(),
+            inputs: Default::default(),
+            interests: Default::default(),
+            clock,
+            admin_console: None,
+            running: true,
+            tick: 0,
             last_tick: Instant::now(),
-        }
+        })
     }
 
-    pub(crate) fn run(mut self) {
-        loop {
-            self.suspend_until_next_tick();
+    /// Reads admin commands from stdin while the server runs.
+    pub(crate) fn attach_admin_console(&mut self, console: AdminConsole) {
+        self.admin_console = Some(console);
+    }
+
+    /// Runs until an admin shuts the server down, then waits for the last messages to be sent.
+    pub(crate) async fn run(mut self) {
+        while self.running {
+            self.suspend_until_next_tick().await;
+            self.clock.start_tick(self.tick);
             self.handle_incoming_messages();
+            self.handle_admin_commands();
+            self.handle_admin_calls();
+            self.avatars.process_inputs(&mut self.inputs, &mut self.replication, &mut self.interests);
+            self.replicate();
+            self.tick += 1;
+        }
+        self.network_interface.shutdown().await;
+    }
+
+    /// Sends every client the changes to the relevant replicated entities since their last acknowledged snapshot,
+    /// together with the last of its inputs that has been processed. \
+    /// Snapshots are timestamped, so that clients can tell how old the state they show is.
+    fn replicate(&mut self) {
+        self.interests.refresh(self.replication.entities());
+        while let Some(event) = self.interests.poll_event() {
+            match event {
+                InterestEvent::Entered { user, entity } => self.network_interface.send_tcp(ServerTcpMessage::EntityEntered(entity), user),
+                InterestEvent::Left { user, entity } => self.network_interface.send_tcp(ServerTcpMessage::EntityLeft(entity), user),
+            }
+        }
+        let (interests, inputs) = (&self.interests, &self.inputs);
+        let snapshots = self.replication.snapshots(self.tick, |id, entity| interests.is_relevant(id, entity), |id| inputs.last_processed(id));
+        for (id, snapshot) in snapshots {
+            let bytes = snapshot.serialize();
+            if bytes.len() <= SnapshotPart::MAX_SIZE {
+                self.network_interface.send_udp_timestamped(ServerUdpMessage::Snapshot(snapshot), id);
+            } else {
+                for part in SnapshotPart::split(snapshot.tick, &bytes) {
+                    self.network_interface.send_udp_timestamped(ServerUdpMessage::SnapshotPart(part), id);
+                }
+            }
         }
     }
 
-    fn suspend_until_next_tick(&mut self) {
-        let diff = self.last_tick.elapsed();
-        let time_until_tick = Self::TICK_INTERVAL - diff;
-        if time_until_tick.as_millis() > 0 {
-            sleep(time_until_tick);
+    /// Sends the message to every user in the chat room.
+    pub(crate) fn broadcast_tcp(&mut self, room: &str, msg: ServerTcpMessage) {
+        for id in self.interests.members(room) {
+            self.network_interface.send_tcp(msg.clone(), id);
+        }
+    }
+
+    /// Sends the message to every connected user.
+    pub(crate) fn send_tcp_to_all(&mut self, msg: ServerTcpMessage) {
+        for id in self.state.users.keys() {
+            self.network_interface.send_tcp(msg.clone(), *id);
+        }
+    }
+
+    /// Sends the message to every user in the chat room.
+    pub(crate) fn broadcast_udp(&mut self, room: &str, msg: ServerUdpMessage) {
+        for id in self.interests.members(room) {
+            self.network_interface.send_udp(msg.clone(), id);
+        }
+    }
+
+    /// A tick that took longer than the interval is followed by the next one right away.
+    async fn suspend_until_next_tick(&mut self) {
+        let time_until_tick = Self::TICK_INTERVAL.saturating_sub(self.last_tick.elapsed());
+        if !time_until_tick.is_zero() {
+            tokio::time::sleep(time_until_tick).await;
         }
         self.last_tick = Instant::now();
     }
 }
 
-struct Client {
-    name: String,
-    id: UserId,
+pub(crate) struct Client {
+    pub(crate) name: String,
+    pub(crate) id: UserId,
+    pub(crate) address: PeerAddress,
 }
 
 #[derive(Default)]
-struct ServerState {
-    users: HashMap<UserId, Client>,
-}
\ No newline at end of file
+pub(crate) struct ServerState {
+    pub(crate) users: HashMap<UserId, Client>,
+}
+
+impl ServerState {
+    pub(crate) fn name_of(&self, id: UserId) -> String {
+        self.users.get(&id).map_or_else(|| format!("user{id}"), |user| user.name.clone())
+    }
+
+    pub(crate) fn find_by_name(&self, name: &str) -> Option<UserId> {
+        self.users.values().find(|user| user.name == name).map(|user| user.id)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use serializeable::Serializeable;
+    use common::avatar::{Move, Position};
+    use common::interest::Interest;
+    use common::message::{ClientTcpMessage, ClientUdpMessage, ServerTcpMessage, ServerUdpMessage};
+    use common::message::client_message::ClientConnectionMessage;
+    use common::message::send_message::TcpSendable;
+    use common::message::server_message::ServerConnectionMessage;
+    use common::replication::WorldState;
+    use common::transport::{DatagramSocket, MemoryTransport, ServerAddr, Transport};
+    use crate::admin::AdminCommand;
+    use super::*;
+
+    async fn memory_server() -> (Server, MemoryTransport, ServerAddr) {
+        let transport = MemoryTransport::default();
+        let config = NetworkConfig { addresses: vec![ServerAddr::from(SocketAddr::from(([127, 0, 0, 1], 0)))], ..Default::default() };
+        let server = Server::with_transport(transport.clone(), config).await.unwrap();
+        let addr = server.network_interface.bound_addresses()[0];
+        (server, transport, addr)
+    }
+
+    /// A whole session over the in-memory transport. The test runtime is single threaded and nothing depends on timing,
+    /// so the client task and the server loop always interleave the same way.
+    #[tokio::test]
+    async fn memory_session() {
+        let (mut server, transport, addr) = memory_server().await;
+        let client = tokio::spawn(async move {
+            let (mut tcp, udp) = transport.connect(addr).await.unwrap();
+            ClientConnectionMessage::ConnectNew.send(&mut tcp).await.unwrap();
+            let ServerConnectionMessage::AssignUserId { id, .. } = ServerConnectionMessage::async_deserialize(&mut tcp).await.unwrap() else {
+                panic!("expected a new user id");
+            };
+
+            ClientTcpMessage::Subscribe(Interest::Room("lobby".to_string())).send(&mut tcp).await.unwrap();
+            ClientTcpMessage::Chat { topic: "lobby".to_string(), text: "hello".to_string() }.send(&mut tcp).await.unwrap();
+            loop {
+                if let ServerTcpMessage::Chat { topic, text, .. } = ServerTcpMessage::async_deserialize(&mut tcp).await.unwrap() {
+                    assert_eq!((topic.as_str(), text.as_str()), ("lobby", "hello"));
+                    break;
+                }
+            }
+
+            udp.send_to(&ClientUdpMessage::Ping(7).serialize(), addr.unreliable).await.unwrap();
+            let mut buf = [0u8; 2048];
+            loop {
+                let (n, sender) = udp.recv_from(&mut buf).await.unwrap();
+                if let Ok(ServerUdpMessage::Pong(7)) = ServerUdpMessage::deserialize(&mut &buf[..n]) {
+                    assert_eq!(sender, addr.unreliable);
+                    break;
+                }
+            }
+            id
+        });
+
+        for _ in 0..10_000 {
+            if client.is_finished() {
+                break;
+            }
+            server.handle_incoming_messages();
+            tokio::task::yield_now().await;
+        }
+        assert!(client.is_finished(), "the session did not complete");
+        let id = client.await.unwrap();
+        assert!(server.state.users.contains_key(&id));
+    }
+
+    /// Inputs sent over udp move the avatar, and the snapshots tell the client which of its inputs they reflect.
+    #[tokio::test]
+    async fn inputs_come_back_in_snapshots() {
+        let (mut server, transport, addr) = memory_server().await;
+        let client = tokio::spawn(async move {
+            let (mut tcp, udp) = transport.connect(addr).await.unwrap();
+            ClientConnectionMessage::ConnectNew.send(&mut tcp).await.unwrap();
+            ServerConnectionMessage::async_deserialize(&mut tcp).await.unwrap();
+
+            let mut buf = [0u8; 2048];
+            let mut sent_inputs = false;
+            loop {
+                let (n, _) = udp.recv_from(&mut buf).await.unwrap();
+                let Ok(ServerUdpMessage::Timestamped(timestamp, message)) = ServerUdpMessage::deserialize(&mut &buf[..n]) else { continue };
+                let ServerUdpMessage::Snapshot(snapshot) = *message else { continue };
+                assert_eq!(timestamp.tick, snapshot.tick);
+                if !sent_inputs {
+                    // The first snapshot shows that the server knows the client, so its inputs are not dropped.
+                    for sequence in 0..2 {
+                        udp.send_to(&ClientUdpMessage::Input(sequence, Move::Right.serialize()).serialize(), addr.unreliable).await.unwrap();
+                    }
+                    sent_inputs = true;
+                }
+                if snapshot.last_processed_input == Some(1) {
+                    // Nothing is ever acknowledged, so every snapshot holds the full state.
+                    let mut world = WorldState::default();
+                    world.apply(&snapshot);
+                    let avatar = world.entities().next().unwrap();
+                    return world.component::<Position>(avatar);
+                }
+            }
+        });
+
+        for _ in 0..10_000 {
+            if client.is_finished() {
+                break;
+            }
+            server.clock.start_tick(server.tick);
+            server.handle_incoming_messages();
+            server.avatars.process_inputs(&mut server.inputs, &mut server.replication, &mut server.interests);
+            server.replicate();
+            server.tick += 1;
+            tokio::task::yield_now().await;
+        }
+        assert!(client.is_finished(), "the session did not complete");
+        assert_eq!(client.await.unwrap(), Some(Position { x: Position::SPAWN.x + 2, y: Position::SPAWN.y }));
+    }
+
+    /// Logs in over the unix socket, then talks to the server over both the stream and the datagram socket it names.
+    #[tokio::test]
+    async fn unix_socket_session() {
+        use common::transport::unix::server_datagram_path;
+        use tokio::net::{UnixDatagram, UnixStream};
+
+        let path = std::env::temp_dir().join(format!("server-test-{:016x}.sock", rand::random::<u64>()));
+        let config = NetworkConfig {
+            addresses: vec![ServerAddr::from(SocketAddr::from(([127, 0, 0, 1], 0)))],
+            unix_socket: Some(path.clone()),
+            ..Default::default()
+        };
+        let mut server = Server::with_transport(MemoryTransport::default(), config).await.unwrap();
+        let client_path = path.clone();
+        let client = tokio::spawn(async move {
+            let mut stream = UnixStream::connect(&client_path).await.unwrap();
+            ClientConnectionMessage::ConnectNew.send(&mut stream).await.unwrap();
+            let ServerConnectionMessage::AssignUserId { id, .. } = ServerConnectionMessage::async_deserialize(&mut stream).await.unwrap() else {
+                panic!("expected a new user id");
+            };
+            let ServerTcpMessage::UnixDatagramPath(datagram_path) = ServerTcpMessage::async_deserialize(&mut stream).await.unwrap() else {
+                panic!("expected the path of the datagram socket");
+            };
+            let datagram = UnixDatagram::bind(&datagram_path).unwrap();
+            datagram.send_to(&ClientUdpMessage::Ping(7).serialize(), server_datagram_path(&client_path)).await.unwrap();
+            let mut buf = [0u8; 2048];
+            loop {
+                let n = datagram.recv(&mut buf).await.unwrap();
+                if let Ok(ServerUdpMessage::Pong(7)) = ServerUdpMessage::deserialize(&mut &buf[..n]) {
+                    break;
+                }
+            }
+            let _ = std::fs::remove_file(&datagram_path);
+
+            ClientTcpMessage::Subscribe(Interest::Room("lobby".to_string())).send(&mut stream).await.unwrap();
+            ClientTcpMessage::Chat { topic: "lobby".to_string(), text: "hello".to_string() }.send(&mut stream).await.unwrap();
+            while !matches!(ServerTcpMessage::async_deserialize(&mut stream).await.unwrap(), ServerTcpMessage::Chat { .. }) {}
+            id
+        });
+
+        for _ in 0..10_000 {
+            if client.is_finished() {
+                break;
+            }
+            server.handle_incoming_messages();
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(server_datagram_path(&path));
+        assert!(client.is_finished(), "the session did not complete");
+        let id = client.await.unwrap();
+        assert!(matches!(server.state.users.get(&id).map(|user| &user.address), Some(PeerAddress::Unix(_))));
+    }
+
+    #[tokio::test]
+    async fn a_restarted_server_does_not_know_old_ids() {
+        let (server, transport, addr) = memory_server().await;
+        let (mut tcp, _udp) = transport.connect(addr).await.unwrap();
+        ClientConnectionMessage::ConnectNew.send(&mut tcp).await.unwrap();
+        let ServerConnectionMessage::AssignUserId { id, token } = ServerConnectionMessage::async_deserialize(&mut tcp).await.unwrap() else {
+            panic!("expected a new user id");
+        };
+        drop((tcp, server));
+
+        let (_server, transport, addr) = memory_server().await;
+        let (mut tcp, _udp) = transport.connect(addr).await.unwrap();
+        ClientConnectionMessage::ConnectWithId { id, token }.send(&mut tcp).await.unwrap();
+        assert!(matches!(ServerConnectionMessage::async_deserialize(&mut tcp).await.unwrap(), ServerConnectionMessage::UnknownId));
+    }
+
+    #[tokio::test]
+    async fn banned_users_are_kicked_and_refused() {
+        let (mut server, transport, addr) = memory_server().await;
+        let client = tokio::spawn(async move {
+            let (mut tcp, _udp) = transport.connect(addr).await.unwrap();
+            ClientConnectionMessage::ConnectNew.send(&mut tcp).await.unwrap();
+            let ServerConnectionMessage::AssignUserId { id, token } = ServerConnectionMessage::async_deserialize(&mut tcp).await.unwrap() else {
+                panic!("expected a new user id");
+            };
+            while !matches!(ServerTcpMessage::async_deserialize(&mut tcp).await.unwrap(), ServerTcpMessage::Kicked(_)) {}
+
+            let (mut tcp, _udp) = transport.connect(addr).await.unwrap();
+            ClientConnectionMessage::ConnectWithId { id, token }.send(&mut tcp).await.unwrap();
+            ServerConnectionMessage::async_deserialize(&mut tcp).await.unwrap()
+        });
+
+        let mut banned = false;
+        for _ in 0..10_000 {
+            if client.is_finished() {
+                break;
+            }
+            server.handle_incoming_messages();
+            if let Some(&id) = server.state.users.keys().next().filter(|_| !banned) {
+                server.execute_admin(AdminCommand::Ban { id }).unwrap();
+                banned = true;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert!(client.is_finished(), "the session did not complete");
+        assert!(matches!(client.await.unwrap(), ServerConnectionMessage::Refused(_)));
+    }
+}